@@ -0,0 +1,109 @@
+// Size and speed of sealing Editor.js notes the way the store writes them, with and without
+// compression and hardened padding.
+// Run with `cargo bench --bench compression`.
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use noetiq_lib::bench::{self, Sealer};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+// Label, compress, hardened.
+const MODES: [(&str, bool, bool); 4] = [
+    ("plain", false, false),
+    ("compressed", true, false),
+    ("hardened", false, true),
+    ("compressed, hardened", true, true),
+];
+const WORDS: [&str; 24] = [
+    "meeting", "notes", "project", "deadline", "review", "the", "and", "with", "for", "budget",
+    "client", "design", "draft", "follow", "up", "on", "next", "week", "team", "update", "plan",
+    "ideas", "todo", "research",
+];
+
+// Deterministic pseudo random text, so runs are comparable.
+struct Text(u64);
+
+impl Text {
+    fn sentence(&mut self, words: usize) -> String {
+        (0..words)
+            .map(|_| {
+                self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                WORDS[(self.0 >> 33) as usize % WORDS.len()]
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn block(id: usize, kind: &str, data: Value) -> Value {
+    json!({ "id": format!("blk{:07}", id), "type": kind, "data": data })
+}
+
+// The mix of blocks the editor produces for a typical note.
+fn note(sections: usize) -> Vec<u8> {
+    let mut text = Text(sections as u64);
+    let mut blocks = Vec::new();
+    for i in 0..sections {
+        blocks.push(block(blocks.len(), "header", json!({ "text": text.sentence(4), "level": 2 })));
+        for _ in 0..3 {
+            blocks.push(block(blocks.len(), "paragraph", json!({ "text": text.sentence(40) })));
+        }
+        let items: Vec<Value> = (0..5)
+            .map(|_| json!({ "content": text.sentence(8), "meta": {}, "items": [] }))
+            .collect();
+        blocks.push(block(blocks.len(), "list", json!({ "style": "unordered", "meta": {}, "items": items })));
+        if i % 3 == 0 {
+            let rows: Vec<Vec<String>> = (0..4).map(|_| (0..3).map(|_| text.sentence(2)).collect()).collect();
+            blocks.push(block(blocks.len(), "table", json!({ "withHeadings": true, "content": rows })));
+        }
+    }
+    serde_json::to_vec(&json!({ "time": 1_700_000_000_000u64, "blocks": blocks, "version": "2.30.8" })).unwrap()
+}
+
+fn time<T>(iterations: u32, mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / iterations
+}
+
+fn throughput(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0)
+}
+
+fn main() {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+
+    for (label, sections) in [("small", 2), ("medium", 20), ("large", 200)] {
+        let plaintext = note(sections);
+        let iterations = (2_000_000 / plaintext.len()).clamp(5, 2_000) as u32;
+
+        let compressed = bench::compress(&plaintext).unwrap();
+        println!(
+            "{} note, {} bytes, compresses to {} bytes ({:.1}%)",
+            label,
+            plaintext.len(),
+            compressed.len(),
+            100.0 * compressed.len() as f64 / plaintext.len() as f64
+        );
+
+        for (suite, xchacha) in [("AES-256-GCM", false), ("XChaCha20-Poly1305", true)] {
+            for (mode, compress, hardened) in MODES {
+                let sealer = Sealer::new(&key, xchacha, compress, hardened).unwrap();
+                let sealed = sealer.seal(&plaintext).unwrap();
+                let seal = time(iterations, || sealer.seal(&plaintext).unwrap());
+                let open = time(iterations, || sealer.open(&sealed).unwrap());
+                println!(
+                    "  {:<18} {:<20}: {:>7} bytes on disk, seal {:>6.0} MiB/s, open {:>6.0} MiB/s",
+                    suite,
+                    mode,
+                    sealed.len(),
+                    throughput(plaintext.len(), seal),
+                    throughput(plaintext.len(), open)
+                );
+            }
+        }
+    }
+}
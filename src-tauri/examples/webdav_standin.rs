@@ -0,0 +1,278 @@
+// A minimal local WebDAV server for trying out sync without a real one.
+// Run with `cargo run --example webdav_standin -- <folder> [port] [--flaky]` and set up sync with
+// the address http://127.0.0.1:<port>/ and any user name.
+//
+// It understands the requests the sync backend sends: PROPFIND, GET with ranges, PUT, MKCOL and
+// DELETE. With --flaky every second download is cut off halfway, to exercise resuming.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+    // How much of the body to actually send, for --flaky.
+    cut: Option<usize>,
+}
+
+impl Response {
+    fn new(status: u16) -> Self {
+        Response { status, headers: Vec::new(), body: Vec::new(), cut: None }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        411 => "Length Required",
+        416 => "Range Not Satisfiable",
+        _ => "Error",
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            output.push(u8::from_str_radix(value.get(i + 1..i + 3)?, 16).ok()?);
+            i += 3;
+        } else {
+            output.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(output).ok()
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length: usize = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).ok()?;
+
+    let path = percent_decode(target.split('?').next()?)?;
+    Some(Request { method, path, headers, body })
+}
+
+// Maps the request path below the served folder, refusing anything that climbs out of it.
+fn local_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    Some(root.join(relative))
+}
+
+fn etag(metadata: &fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos())
+}
+
+fn propfind_entry(href: &str, metadata: &fs::Metadata) -> String {
+    if metadata.is_dir() {
+        format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            href
+        )
+    } else {
+        format!(
+            "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:resourcetype/><d:getetag>{}</d:getetag><d:getcontentlength>{}</d:getcontentlength></d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+            href,
+            etag(metadata),
+            metadata.len()
+        )
+    }
+}
+
+fn propfind(path: &Path, request: &Request) -> Response {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Response::new(404);
+    };
+
+    let href = request.path.clone();
+    let mut body = String::from(r#"<?xml version="1.0" encoding="utf-8"?><d:multistatus xmlns:d="DAV:">"#);
+    body.push_str(&propfind_entry(&href, &metadata));
+    let depth = request.headers.get("depth").map(String::as_str).unwrap_or("1");
+    if metadata.is_dir() && depth != "0" {
+        let base = if href.ends_with('/') { href } else { format!("{}/", href) };
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            if let (Ok(metadata), Some(name)) = (entry.metadata(), entry.file_name().to_str()) {
+                body.push_str(&propfind_entry(&format!("{}{}", base, name), &metadata));
+            }
+        }
+    }
+    body.push_str("</d:multistatus>");
+
+    let mut response = Response::new(207);
+    response.headers.push(("Content-Type", "application/xml; charset=utf-8".into()));
+    response.body = body.into_bytes();
+    response
+}
+
+fn get(path: &Path, request: &Request, cut: bool) -> Response {
+    let (Ok(metadata), Ok(data)) = (fs::metadata(path), fs::read(path)) else {
+        return Response::new(404);
+    };
+    let tag = etag(&metadata);
+
+    let range_start = request
+        .headers
+        .get("range")
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.strip_suffix('-'))
+        .and_then(|r| r.parse::<usize>().ok())
+        .filter(|_| request.headers.get("if-range").is_none_or(|t| *t == tag));
+
+    let mut response = match range_start {
+        Some(start) if start >= data.len() => return Response::new(416),
+        Some(start) => {
+            let mut response = Response::new(206);
+            response.headers.push(("Content-Range", format!("bytes {}-{}/{}", start, data.len() - 1, data.len())));
+            response.body = data[start..].to_vec();
+            response
+        }
+        None => {
+            let mut response = Response::new(200);
+            response.body = data;
+            response
+        }
+    };
+    response.headers.push(("ETag", tag));
+    if cut && response.body.len() > 1 {
+        response.cut = Some(response.body.len() / 2);
+    }
+    response
+}
+
+fn put(path: &Path, request: &Request) -> Response {
+    if !path.parent().is_some_and(Path::is_dir) {
+        return Response::new(409);
+    }
+    if path.is_dir() {
+        return Response::new(405);
+    }
+    let existed = path.exists();
+    let tmp_path = path.with_extension("upload");
+    if fs::write(&tmp_path, &request.body).and_then(|_| fs::rename(&tmp_path, path)).is_err() {
+        return Response::new(500);
+    }
+    Response::new(if existed { 204 } else { 201 })
+}
+
+fn mkcol(path: &Path) -> Response {
+    if path.exists() {
+        return Response::new(405);
+    }
+    if !path.parent().is_some_and(Path::is_dir) {
+        return Response::new(409);
+    }
+    match fs::create_dir(path) {
+        Ok(()) => Response::new(201),
+        Err(_) => Response::new(500),
+    }
+}
+
+fn delete(path: &Path) -> Response {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        return Response::new(404);
+    };
+    match result {
+        Ok(()) => Response::new(204),
+        Err(_) => Response::new(500),
+    }
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.body.len()));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body[..response.cut.unwrap_or(response.body.len())])?;
+    stream.flush()
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let flaky = args.iter().any(|a| a == "--flaky");
+    let mut positional = args.iter().filter(|a| !a.starts_with("--"));
+    let root = PathBuf::from(positional.next().expect("usage: webdav_standin <folder> [port] [--flaky]"));
+    let port = positional.next().map(|p| p.parse::<u16>().expect("invalid port")).unwrap_or(8080);
+
+    fs::create_dir_all(&root).expect("cannot create the served folder");
+    let listener = TcpListener::bind(("127.0.0.1", port)).expect("cannot listen");
+    println!("Serving {} on http://127.0.0.1:{}/", root.display(), port);
+
+    let mut downloads = 0u64;
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { continue };
+        let Some(request) = read_request(&mut stream) else { continue };
+
+        let response = match local_path(&root, &request.path) {
+            None => Response::new(400),
+            Some(path) => match request.method.as_str() {
+                "PROPFIND" => propfind(&path, &request),
+                "GET" => {
+                    downloads += 1;
+                    get(&path, &request, flaky && downloads.is_multiple_of(2))
+                }
+                "PUT" if request.headers.contains_key("transfer-encoding") => Response::new(411),
+                "PUT" => put(&path, &request),
+                "MKCOL" => mkcol(&path),
+                "DELETE" => delete(&path),
+                _ => Response::new(405),
+            },
+        };
+        println!("{} {} -> {}", request.method, request.path, response.status);
+        let _ = write_response(&mut stream, &response);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::cipher::CipherSuite;
+use crate::secret::Plaintext;
+use crate::storage::{self, VaultStore};
+use crate::{read_note, read_notes_list, shred, stream};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+pub(crate) const PROTOCOL: &str = "noetiq-attachment";
+pub(crate) const ATTACHMENTS_FOLDER: &str = "attachments";
+pub(crate) const MANIFEST_FILE: &str = "attachments.json";
+pub(crate) const MAX_SIZE: usize = 64 * 1024 * 1024;
+
+// Uploads younger than this are never collected, the note that uses them may not be saved yet.
+const GC_GRACE_SECS: i64 = 60 * 60;
+
+// Each blob has its own key, so deleting its manifest entry crypto-shreds it like a note.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct AttachmentEntry {
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    pub created: i64,
+    key: String,
+    // Blobs written before the streaming format are a single AES-GCM message.
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct AttachmentInfo {
+    pub id: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+}
+
+type Manifest = BTreeMap<String, AttachmentEntry>;
+
+impl AttachmentEntry {
+    pub fn info(&self, id: &str) -> AttachmentInfo {
+        AttachmentInfo {
+            id: id.to_string(),
+            name: self.name.clone(),
+            mime: self.mime.clone(),
+            size: self.size,
+        }
+    }
+
+    // Only raster images are rendered inline. Anything else, SVG included since it can carry
+    // scripts, is served as an opaque download.
+    pub fn served_mime(&self) -> &str {
+        if self.mime.starts_with("image/") && self.mime != "image/svg+xml" {
+            &self.mime
+        } else {
+            "application/octet-stream"
+        }
+    }
+}
+
+fn blob_path(store: &VaultStore, id: &str) -> PathBuf {
+    store.dir().join(ATTACHMENTS_FOLDER).join(id)
+}
+
+fn read_manifest(store: &VaultStore) -> Result<Manifest, String> {
+    if !store.contains(MANIFEST_FILE)? {
+        return Ok(Manifest::new());
+    }
+    serde_json::from_slice(&store.read(MANIFEST_FILE)?).map_err(|_| "Invalid attachment index".to_string())
+}
+
+fn write_manifest(store: &VaultStore, manifest: &Manifest) -> Result<(), String> {
+    let json = Zeroizing::new(serde_json::to_vec(manifest).map_err(|e| e.to_string())?);
+    store.write(MANIFEST_FILE, &json)
+}
+
+// The content hash is keyed with the store's attachment key and the vault, so identical files
+// dedupe within a vault without their names revealing matches across vaults or against known files.
+fn content_id(store_key: &[u8], vault: &str, data: &[u8]) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(store_key).map_err(|e| e.to_string())?;
+    mac.update(b"noetiq-attachment\0");
+    mac.update(vault.as_bytes());
+    mac.update(b"\0");
+    mac.update(data);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+pub(crate) fn store(
+    store: &VaultStore,
+    store_key: &[u8],
+    vault: &str,
+    name: &str,
+    mime: &str,
+    data: &[u8],
+) -> Result<AttachmentInfo, String> {
+    if data.len() > MAX_SIZE {
+        return Err(format!("Attachments are limited to {} MiB", MAX_SIZE / (1024 * 1024)));
+    }
+
+    let id = content_id(store_key, vault, data)?;
+    let mut manifest = read_manifest(store)?;
+    if let Some(entry) = manifest.get(&id) {
+        if blob_path(store, &id).exists() {
+            return Ok(entry.info(&id));
+        }
+    }
+
+    let entry = AttachmentEntry {
+        name: name.to_string(),
+        mime: mime.to_string(),
+        size: data.len() as u64,
+        created: Utc::now().timestamp(),
+        key: shred::new_note_key(),
+        stream: true,
+    };
+    write_blob(store, &id, &entry, data)?;
+
+    let info = entry.info(&id);
+    manifest.insert(id, entry);
+    write_manifest(store, &manifest)?;
+    Ok(info)
+}
+
+fn write_blob(store: &VaultStore, id: &str, entry: &AttachmentEntry, data: &[u8]) -> Result<(), String> {
+    let settings = store.settings();
+    let (header, fill) = if settings.hardened {
+        storage::padding(data.len())
+    } else {
+        (Vec::new(), 0)
+    };
+    let source = header.as_slice().chain(data).chain(io::repeat(0).take(fill as u64));
+
+    let folder = store.dir().join(ATTACHMENTS_FOLDER);
+    fs::create_dir_all(&folder).map_err(|_| "Failed to create attachment folder".to_string())?;
+    let path = blob_path(store, id);
+    stream::write_file(&path, &shred::note_cipher(&entry.key, settings.cipher)?, source)?;
+    if settings.hardened {
+        storage::normalize_mtime(&path)?;
+        let _ = storage::normalize_mtime(&folder);
+    }
+    Ok(())
+}
+
+fn read_blob(store: &VaultStore, id: &str, entry: &AttachmentEntry) -> Result<Plaintext, String> {
+    let path = blob_path(store, id);
+    let plaintext = if entry.stream {
+        stream::read_file(&path, &shred::note_cipher(&entry.key, CipherSuite::default())?)?
+    } else {
+        let data = fs::read(&path).map_err(|_| "Error reading attachment".to_string())?;
+        shred::open_note(&entry.key, &data)?
+    };
+    storage::unpad(plaintext)
+}
+
+pub(crate) fn load(store: &VaultStore, id: &str) -> Result<(AttachmentEntry, Plaintext), String> {
+    let manifest = read_manifest(store)?;
+    let entry = manifest.get(id).ok_or("Attachment not found")?;
+    Ok((entry.clone(), read_blob(store, id, entry)?))
+}
+
+// Attachments whose blob is present, for sync.
+pub(crate) fn list(store: &VaultStore) -> Result<BTreeMap<String, AttachmentInfo>, String> {
+    Ok(read_manifest(store)?
+        .iter()
+        .filter(|(id, _)| blob_path(store, id).exists())
+        .map(|(id, entry)| (id.clone(), entry.info(id)))
+        .collect())
+}
+
+// Stores an attachment under the id another device gave it, so the notes referring to it resolve.
+pub(crate) fn import(store: &VaultStore, info: &AttachmentInfo, data: &[u8]) -> Result<(), String> {
+    if data.len() > MAX_SIZE {
+        return Err(format!("Attachments are limited to {} MiB", MAX_SIZE / (1024 * 1024)));
+    }
+
+    let entry = AttachmentEntry {
+        name: info.name.clone(),
+        mime: info.mime.clone(),
+        size: data.len() as u64,
+        created: Utc::now().timestamp(),
+        key: shred::new_note_key(),
+        stream: true,
+    };
+    write_blob(store, &info.id, &entry, data)?;
+
+    let mut manifest = read_manifest(store)?;
+    manifest.insert(info.id.clone(), entry);
+    write_manifest(store, &manifest)
+}
+
+// Rewrites every blob with the store's current cipher suite, keeping its key.
+pub(crate) fn reseal(store: &VaultStore) -> Result<(), String> {
+    let mut manifest = read_manifest(store)?;
+    for (id, entry) in manifest.iter_mut() {
+        if !blob_path(store, id).exists() {
+            continue;
+        }
+        let data = read_blob(store, id, entry)?;
+        entry.stream = true;
+        write_blob(store, id, entry, &data)?;
+    }
+    write_manifest(store, &manifest)
+}
+
+pub(crate) fn remove(store: &VaultStore, id: &str) -> Result<(), String> {
+    let mut manifest = read_manifest(store)?;
+    if manifest.remove(id).is_none() {
+        return Err("Attachment not found".into());
+    }
+    write_manifest(store, &manifest)?;
+    remove_blob(store, id)
+}
+
+fn remove_blob(store: &VaultStore, id: &str) -> Result<(), String> {
+    let path = blob_path(store, id);
+    if path.exists() {
+        shred::remove_file(&path, store.settings().secure_delete).map_err(|_| "Error deleting attachment".to_string())?;
+    }
+    Ok(())
+}
+
+// Image and attaches blocks keep the id next to the file URL, which depends on the platform.
+pub(crate) fn collect_references(value: &Value, ids: &mut HashSet<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(id)) = map.get("attachment") {
+                ids.insert(id.clone());
+            }
+            map.values().for_each(|v| collect_references(v, ids));
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_references(v, ids)),
+        _ => {}
+    }
+}
+
+// Points references at the ids their attachments were stored under here. An id missing from the
+// map did not come with the note and could only match an unrelated attachment, so it is dropped.
+pub(crate) fn replace_references(value: &mut Value, ids: &HashMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(id)) = map.get("attachment") {
+                match ids.get(id) {
+                    Some(new_id) => {
+                        map.insert("attachment".into(), Value::String(new_id.clone()));
+                    }
+                    None => {
+                        map.remove("attachment");
+                    }
+                }
+            }
+            map.values_mut().for_each(|v| replace_references(v, ids));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| replace_references(v, ids)),
+        _ => {}
+    }
+}
+
+// Drops attachments no note refers to any more, and blob files missing from the manifest.
+// Returns how many were removed.
+pub(crate) fn collect_garbage(store: &VaultStore) -> Result<usize, String> {
+    let mut manifest = read_manifest(store)?;
+
+    let mut referenced = HashSet::new();
+    for entry in read_notes_list(store)? {
+        let filename = match entry.get("filename").and_then(|f| f.as_str()) {
+            Some(filename) => filename,
+            None => continue,
+        };
+        let decrypted = read_note(store, &entry, filename)?;
+        if let Ok(document) = serde_json::from_slice::<Value>(&decrypted) {
+            collect_references(&document, &mut referenced);
+        }
+    }
+
+    let cutoff = Utc::now().timestamp() - GC_GRACE_SECS;
+    let unreferenced: Vec<String> = manifest
+        .iter()
+        .filter(|(id, entry)| !referenced.contains(*id) && entry.created < cutoff)
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    let mut orphans = Vec::new();
+    let folder = store.dir().join(ATTACHMENTS_FOLDER);
+    if folder.exists() {
+        for entry in fs::read_dir(&folder).map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if manifest.contains_key(name) {
+                continue;
+            }
+            // Hardened stores normalize mtimes, so the creation time wins where the platform has one.
+            let metadata = entry.metadata().map_err(|e| e.to_string())?;
+            let written = metadata.created().or_else(|_| metadata.modified()).map_err(|e| e.to_string())?;
+            if DateTime::<Utc>::from(written).timestamp() < cutoff {
+                orphans.push(name.to_string());
+            }
+        }
+    }
+
+    if !unreferenced.is_empty() {
+        for id in &unreferenced {
+            manifest.remove(id);
+        }
+        write_manifest(store, &manifest)?;
+    }
+    for id in unreferenced.iter().chain(orphans.iter()) {
+        remove_blob(store, id)?;
+    }
+
+    Ok(unreferenced.len() + orphans.len())
+}
+
+pub(crate) fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3).ok_or("Invalid percent encoding")?;
+            output.push(u8::from_str_radix(hex, 16).map_err(|_| "Invalid percent encoding")?);
+            i += 3;
+        } else {
+            output.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(output).map_err(|_| "Invalid percent encoding".to_string())
+}
@@ -0,0 +1,39 @@
+use crate::cipher::{Cipher, CipherSuite};
+use crate::secret::Plaintext;
+use crate::storage::{self, StorageSettings};
+
+// Lets benches/ time the path a note takes to disk, with the settings a store can choose.
+pub struct Sealer {
+    cipher: Cipher,
+    settings: StorageSettings,
+}
+
+impl Sealer {
+    pub fn new(key: &[u8], xchacha: bool, compress: bool, hardened: bool) -> Result<Self, String> {
+        let cipher = match xchacha {
+            true => CipherSuite::XChaCha20Poly1305,
+            false => CipherSuite::Aes256Gcm,
+        };
+        Ok(Sealer {
+            cipher: Cipher::new(key, cipher)?,
+            settings: StorageSettings {
+                compress,
+                hardened,
+                cipher,
+                ..Default::default()
+            },
+        })
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        storage::seal(&self.cipher, plaintext, self.settings)
+    }
+
+    pub fn open(&self, data: &[u8]) -> Result<Plaintext, String> {
+        storage::open(&self.cipher, data)
+    }
+}
+
+pub fn compress(plaintext: &[u8]) -> Result<Plaintext, String> {
+    storage::compress(plaintext)
+}
@@ -0,0 +1,109 @@
+use crate::secret::{Plaintext, SecretKey};
+use crate::DECRYPT_FAILED;
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{rand_core::RngCore, Aead, KeyInit, OsRng};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::XChaCha20Poly1305;
+use hmac::digest::FixedOutput;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+pub(crate) const NONCE_LEN: usize = 12;
+const XNONCE_LEN: usize = 24;
+
+// XChaCha20-Poly1305 data starts with this marker. AES-GCM data keeps the original bare
+// nonce|ciphertext layout, so every file written before the choice existed still reads.
+const XCHACHA_MAGIC: &[u8; 4] = b"NQX1";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub(crate) enum CipherSuite {
+    #[default]
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    // 192 bit random nonces never realistically collide, and it is fast without AES-NI.
+    #[serde(rename = "xchacha20-poly1305")]
+    XChaCha20Poly1305,
+}
+
+// Reads either suite, writes the selected one.
+#[derive(Clone)]
+pub(crate) struct Cipher {
+    suite: CipherSuite,
+    pub(crate) aes: Aes256Gcm,
+    pub(crate) xchacha: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn new(key: &[u8], suite: CipherSuite) -> Result<Self, String> {
+        let aes = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+
+        // XChaCha20 gets its own subkey, so one key is never used with two algorithms.
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|e| e.to_string())?;
+        mac.update(b"noetiq-xchacha20poly1305");
+        let mut subkey = SecretKey::zeroed();
+        mac.finalize_into(GenericArray::from_mut_slice(subkey.as_mut()));
+        let xchacha = XChaCha20Poly1305::new_from_slice(&subkey[..]).map_err(|e| e.to_string())?;
+
+        Ok(Cipher { suite, aes, xchacha })
+    }
+
+    pub fn suite(&self) -> CipherSuite {
+        self.suite
+    }
+
+    pub fn set_suite(&mut self, suite: CipherSuite) {
+        self.suite = suite;
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        match self.suite {
+            CipherSuite::Aes256Gcm => {
+                let mut nonce = [0u8; NONCE_LEN];
+                OsRng.fill_bytes(&mut nonce);
+                let ciphertext = self
+                    .aes
+                    .encrypt(GenericArray::from_slice(&nonce), plaintext)
+                    .map_err(|e| e.to_string())?;
+
+                let mut output = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+                output.extend_from_slice(&nonce);
+                output.extend_from_slice(&ciphertext);
+                Ok(output)
+            }
+            CipherSuite::XChaCha20Poly1305 => {
+                let mut nonce = [0u8; XNONCE_LEN];
+                OsRng.fill_bytes(&mut nonce);
+                let ciphertext = self
+                    .xchacha
+                    .encrypt(GenericArray::from_slice(&nonce), plaintext)
+                    .map_err(|e| e.to_string())?;
+
+                let mut output = Vec::with_capacity(XCHACHA_MAGIC.len() + XNONCE_LEN + ciphertext.len());
+                output.extend_from_slice(XCHACHA_MAGIC);
+                output.extend_from_slice(&nonce);
+                output.extend_from_slice(&ciphertext);
+                Ok(output)
+            }
+        }
+    }
+
+    pub fn open(&self, data: &[u8]) -> Result<Plaintext, String> {
+        if data.starts_with(XCHACHA_MAGIC) && data.len() >= XCHACHA_MAGIC.len() + XNONCE_LEN {
+            let (nonce, ciphertext) = data[XCHACHA_MAGIC.len()..].split_at(XNONCE_LEN);
+            if let Ok(plaintext) = self.xchacha.decrypt(GenericArray::from_slice(nonce), ciphertext) {
+                return Ok(Zeroizing::new(plaintext));
+            }
+            // An AES-GCM nonce starts with the marker once in 2^32 files, those fall through.
+        }
+
+        if data.len() < NONCE_LEN {
+            return Err("Corrupted file".into());
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.aes
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map(Zeroizing::new)
+            .map_err(|_| DECRYPT_FAILED.to_string())
+    }
+}
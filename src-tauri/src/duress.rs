@@ -0,0 +1,127 @@
+use crate::storage::{self, StorageSettings, VaultStore, INDEX_FILE};
+use crate::{atomic_write, cipher_from_key, gen_id, shred, RootIndex, SALT_LEN};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+// Every store carries this file next to index.json. Without a duress password one of both holds
+// an index sealed under a throwaway key, so their presence says nothing about whether a decoy exists.
+pub(crate) const ALT_INDEX_FILE: &str = "index.alt";
+
+pub(crate) const INDEX_FILES: [&str; 2] = [INDEX_FILE, ALT_INDEX_FILE];
+
+// Which of both files holds the real index is drawn at random, and only the encrypted index
+// records its slot.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Slot {
+    Primary,
+    Alternate,
+}
+
+pub(crate) fn random_file() -> &'static str {
+    INDEX_FILES[(OsRng.next_u32() % 2) as usize]
+}
+
+pub(crate) fn other_file(file: &str) -> &'static str {
+    if file == INDEX_FILE {
+        ALT_INDEX_FILE
+    } else {
+        INDEX_FILE
+    }
+}
+
+// In the primary index this records that a decoy is configured. In the decoy index it tells
+// unlock what to do when the duress password is used.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Default)]
+pub(crate) struct DuressSettings {
+    #[serde(default)]
+    pub wipe_primary: bool,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct DuressStatus {
+    pub enabled: bool,
+    pub wipe_primary: bool,
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+pub(crate) fn write_filler(vault_dir: &Path, file: &str, storage: StorageSettings) -> Result<(), String> {
+    let key = Zeroizing::new(random_bytes(32));
+    let cipher = cipher_from_key(&key, storage.cipher)?;
+
+    let plaintext = serde_json::to_vec(&RootIndex::default()).map_err(|e| e.to_string())?;
+    let mut data = random_bytes(SALT_LEN);
+    data.extend_from_slice(&storage::seal(&cipher, &plaintext, storage)?);
+
+    let path = vault_dir.join(file);
+    atomic_write(&path, &data)?;
+    if storage.hardened {
+        storage::normalize_mtime(&path)?;
+    }
+    Ok(())
+}
+
+// Folders no index lists are part of every store: a batch for each slot from the start, and one
+// more with every vault either slot creates. Finding some says nothing about a second store.
+const MIN_FILLER_FOLDERS: u32 = 2;
+const MAX_FILLER_FOLDERS: u32 = 8;
+
+// Laid out like an empty vault, sealed under a key nobody keeps.
+pub(crate) fn write_filler_folder(vault_dir: &Path, storage: StorageSettings) -> Result<(), String> {
+    let key = Zeroizing::new(random_bytes(32));
+    let cipher = cipher_from_key(&key, storage.cipher)?;
+    let folder_id = gen_id(vault_dir)?;
+    VaultStore::new(vault_dir.join(folder_id), &cipher, storage).init()
+}
+
+pub(crate) fn write_initial_filler(vault_dir: &Path, storage: StorageSettings) -> Result<(), String> {
+    for _ in [Slot::Primary, Slot::Alternate] {
+        for _ in 0..MIN_FILLER_FOLDERS + OsRng.next_u32() % (MAX_FILLER_FOLDERS - MIN_FILLER_FOLDERS + 1) {
+            write_filler_folder(vault_dir, storage)?;
+        }
+    }
+    Ok(())
+}
+
+// Stores created before decoys existed get their filler on the next unlock.
+pub(crate) fn ensure_filler(vault_dir: &Path, storage: StorageSettings) -> Result<(), String> {
+    for file in INDEX_FILES {
+        if !vault_dir.join(file).exists() {
+            write_filler(vault_dir, file, storage)?;
+        }
+    }
+    Ok(())
+}
+
+// Replaces the primary salt and index with random bytes of the same length, then shreds every
+// folder the decoy does not list: the real vaults along with the filler, which is written again
+// so the store keeps the layout of one that never had a second slot in use.
+pub(crate) fn shred_primary(vault_dir: &Path, file: &str, storage: StorageSettings, keep: &[&str]) -> Result<(), String> {
+    let path = vault_dir.join(file);
+    let len = fs::metadata(&path).map_err(|e| e.to_string())?.len() as usize;
+    shred::replace(&path, &random_bytes(len), true)?;
+
+    for entry in fs::read_dir(vault_dir).map_err(|e| e.to_string())? {
+        let folder = entry.map_err(|e| e.to_string())?.path();
+        let listed = folder.file_name().and_then(|n| n.to_str()).is_some_and(|n| keep.contains(&n));
+        if folder.is_dir() && !listed {
+            shred::remove_dir_all(&folder, true)?;
+        }
+    }
+    write_initial_filler(vault_dir, storage)?;
+    for _ in keep {
+        write_filler_folder(vault_dir, storage)?;
+    }
+
+    if storage.hardened {
+        storage::normalize_tree(vault_dir)?;
+    }
+    Ok(())
+}
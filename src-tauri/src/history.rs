@@ -0,0 +1,303 @@
+use crate::cipher::Cipher;
+use crate::secret::Plaintext;
+use crate::storage::{self, PACK_FILE};
+use crate::DECRYPT_FAILED;
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    Commit, IndexAddOption, Oid, PushOptions, RemoteCallbacks, Repository,
+    RepositoryInitOptions, Signature, Sort,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Changes are committed in batches by the background thread rather than on every save, so a
+// burst of typing ends up as one commit.
+pub(crate) const COMMIT_INTERVAL: Duration = Duration::from_secs(60);
+
+// The vault's name and icon, sealed like its notes, so a vault pulled onto another copy of the
+// store can be listed there.
+pub(crate) const VAULT_FILE: &str = "vault.json";
+
+const GIT_FOLDER: &str = ".git";
+const BRANCH: &str = "main";
+const BRANCH_REF: &str = "refs/heads/main";
+const FETCHED_REF: &str = "refs/remotes/history/main";
+const AUTHOR: &str = "Noetiq";
+const AUTHOR_EMAIL: &str = "noetiq@localhost";
+const VERSION_LIMIT: usize = 100;
+const REMOTE_SUFFIX: &str = ".git";
+// Local sync state and interrupted writes are not part of a vault's history.
+const IGNORED: &str = "sync.json\n*.tmp\n";
+
+// Every vault folder is its own repository of the encrypted files, exactly as they are on disk.
+// Old versions therefore stay readable with whatever password they were written under.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+pub(crate) struct HistoryConfig {
+    // A folder holding one bare repository per vault, named after the vault folder.
+    #[serde(default)]
+    pub remote: Option<String>,
+    // Vaults deleted here, which a pull must not bring back.
+    #[serde(default)]
+    pub removed: BTreeSet<String>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct HistoryStatus {
+    enabled: bool,
+    remote: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct NoteVersion {
+    commit: String,
+    time: i64,
+}
+
+pub(crate) fn status(config: Option<&HistoryConfig>) -> HistoryStatus {
+    HistoryStatus {
+        enabled: config.is_some(),
+        remote: config.and_then(|c| c.remote.clone()),
+    }
+}
+
+fn git_error(e: git2::Error) -> String {
+    e.message().to_string()
+}
+
+fn open(dir: &Path) -> Result<Repository, String> {
+    Repository::open(dir).map_err(|_| "This vault has no history".to_string())
+}
+
+fn signature() -> Result<Signature<'static>, String> {
+    Signature::now(AUTHOR, AUTHOR_EMAIL).map_err(git_error)
+}
+
+fn head_commit(repo: &Repository) -> Option<Commit<'_>> {
+    repo.head().ok().and_then(|head| head.peel_to_commit().ok())
+}
+
+pub(crate) fn is_enabled(dir: &Path) -> bool {
+    dir.join(GIT_FOLDER).is_dir()
+}
+
+// Turns a vault folder into a repository and records its current state.
+pub(crate) fn init(dir: &Path) -> Result<(), String> {
+    if !is_enabled(dir) {
+        Repository::init_opts(dir, RepositoryInitOptions::new().initial_head(BRANCH)).map_err(git_error)?;
+    }
+    fs::write(dir.join(".gitignore"), IGNORED).map_err(|e| e.to_string())?;
+    commit(dir, "Start history").map(|_| ())
+}
+
+pub(crate) fn remove(dir: &Path) -> Result<(), String> {
+    if is_enabled(dir) {
+        fs::remove_dir_all(dir.join(GIT_FOLDER)).map_err(|e| e.to_string())?;
+    }
+    let _ = fs::remove_file(dir.join(".gitignore"));
+    Ok(())
+}
+
+// Commits whatever changed since the last commit. Returns false when nothing did.
+pub(crate) fn commit(dir: &Path, message: &str) -> Result<bool, String> {
+    let repo = open(dir)?;
+    let mut index = repo.index().map_err(git_error)?;
+    index.add_all(["*"], IndexAddOption::DEFAULT, None).map_err(git_error)?;
+    index.update_all(["*"], None).map_err(git_error)?;
+    index.write().map_err(git_error)?;
+    let tree_id = index.write_tree().map_err(git_error)?;
+
+    let parent = head_commit(&repo);
+    if parent.as_ref().is_some_and(|p| p.tree_id() == tree_id) {
+        return Ok(false);
+    }
+
+    let tree = repo.find_tree(tree_id).map_err(git_error)?;
+    let signature = signature()?;
+    let parents: Vec<&Commit> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+        .map_err(git_error)?;
+    Ok(true)
+}
+
+// Runs on the background thread and needs no key, the files are committed as they are.
+pub(crate) fn commit_pending(vaults_dir: &Path) {
+    for entry in fs::read_dir(vaults_dir).into_iter().flatten().flatten() {
+        let dir = entry.path();
+        if is_enabled(&dir) {
+            let _ = commit(&dir, "Save changes");
+        }
+    }
+}
+
+pub(crate) fn check_remote(remote: &str) -> Result<(), String> {
+    if !Path::new(remote).is_dir() {
+        return Err("The history folder does not exist".into());
+    }
+    Ok(())
+}
+
+fn remote_repo(remote: &str, folder_id: &str) -> Result<PathBuf, String> {
+    check_remote(remote)?;
+    Ok(Path::new(remote).join(format!("{}{}", folder_id, REMOTE_SUFFIX)))
+}
+
+// The vault folders the remote holds a history for.
+pub(crate) fn remote_vaults(remote: &str) -> Result<Vec<String>, String> {
+    check_remote(remote)?;
+    let entries = fs::read_dir(remote).map_err(|e| e.to_string())?;
+    Ok(entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(REMOTE_SUFFIX).map(str::to_string))
+        .filter(|name| !name.is_empty() && !name.starts_with('.'))
+        .collect())
+}
+
+pub(crate) fn clone(remote: &str, folder_id: &str, dir: &Path) -> Result<(), String> {
+    let source = remote_repo(remote, folder_id)?;
+    RepoBuilder::new()
+        .branch(BRANCH)
+        .clone(&source.to_string_lossy(), dir)
+        .map_err(git_error)?;
+    Ok(())
+}
+
+pub(crate) fn push(dir: &Path, remote: &str, folder_id: &str) -> Result<(), String> {
+    commit(dir, "Save changes")?;
+    let target = remote_repo(remote, folder_id)?;
+    if !target.exists() {
+        Repository::init_opts(&target, RepositoryInitOptions::new().bare(true).initial_head(BRANCH))
+            .map_err(git_error)?;
+    }
+
+    let repo = open(dir)?;
+    let mut remote = repo.remote_anonymous(&target.to_string_lossy()).map_err(git_error)?;
+    let mut rejected = None;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.push_update_reference(|_, status| {
+        rejected = status.map(str::to_string);
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let result = remote.push(&[format!("{}:{}", BRANCH_REF, BRANCH_REF)], Some(&mut options));
+    drop(options);
+
+    match (result, rejected) {
+        (Ok(()), None) => Ok(()),
+        _ => Err("The remote history has changes this device does not have yet, pull first".into()),
+    }
+}
+
+// Only touches the repository, so it can run while the vault is in use. Returns whether the
+// remote has a history for the vault.
+pub(crate) fn fetch(dir: &Path, remote: &str, folder_id: &str) -> Result<bool, String> {
+    let target = remote_repo(remote, folder_id)?;
+    if !target.exists() {
+        return Ok(false);
+    }
+
+    let repo = open(dir)?;
+    let mut remote = repo.remote_anonymous(&target.to_string_lossy()).map_err(git_error)?;
+    remote
+        .fetch(&[format!("+{}:{}", BRANCH_REF, FETCHED_REF)], None, None)
+        .map_err(git_error)?;
+    Ok(true)
+}
+
+// Moves the vault to what `fetch` brought in. Only fast-forwards: encrypted files cannot be
+// merged, so diverged histories are left for the user to settle. Returns whether the vault changed.
+pub(crate) fn fast_forward(dir: &Path) -> Result<bool, String> {
+    commit(dir, "Save changes")?;
+
+    let repo = open(dir)?;
+    let Ok(fetched) = repo.find_reference(FETCHED_REF).and_then(|r| r.peel_to_commit()) else {
+        return Ok(false);
+    };
+
+    let local = head_commit(&repo);
+    if let Some(local) = &local {
+        if local.id() == fetched.id() || repo.graph_descendant_of(local.id(), fetched.id()).map_err(git_error)? {
+            return Ok(false);
+        }
+        if !repo.graph_descendant_of(fetched.id(), local.id()).map_err(git_error)? {
+            return Err("This vault changed here and in the remote history, they cannot be combined".into());
+        }
+    }
+
+    repo.checkout_tree(fetched.as_object(), Some(CheckoutBuilder::new().force()))
+        .map_err(git_error)?;
+    repo.reference(BRANCH_REF, fetched.id(), true, "pull").map_err(git_error)?;
+    repo.set_head(BRANCH_REF).map_err(git_error)?;
+    Ok(true)
+}
+
+// A packed vault keeps every note in one container, so any change to it counts as a version.
+fn entry_id(commit: &Commit, name: &str) -> Option<Oid> {
+    let tree = commit.tree().ok()?;
+    let entry = tree.get_name(name).or_else(|| tree.get_name(PACK_FILE))?;
+    Some(entry.id())
+}
+
+// The commits that changed a note, newest first.
+pub(crate) fn versions(dir: &Path, name: &str) -> Result<Vec<NoteVersion>, String> {
+    let repo = open(dir)?;
+    if head_commit(&repo).is_none() {
+        return Ok(Vec::new());
+    }
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    walk.push_head().map_err(git_error)?;
+    walk.set_sorting(Sort::TIME).map_err(git_error)?;
+
+    let mut versions = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id.map_err(git_error)?).map_err(git_error)?;
+        let current = entry_id(&commit, name);
+        let previous = commit.parent(0).ok().and_then(|parent| entry_id(&parent, name));
+        if current.is_some() && current != previous {
+            versions.push(NoteVersion {
+                commit: commit.id().to_string(),
+                time: commit.time().seconds(),
+            });
+            if versions.len() == VERSION_LIMIT {
+                break;
+            }
+        }
+    }
+    Ok(versions)
+}
+
+// Reads entries as they were in a commit, with only the vault layer removed.
+pub(crate) fn read_version(dir: &Path, cipher: &Cipher, commit: &str, names: &[&str]) -> Result<Vec<Plaintext>, String> {
+    let repo = open(dir)?;
+    let id = Oid::from_str(commit).map_err(|_| "Version not found".to_string())?;
+    let tree = repo
+        .find_commit(id)
+        .and_then(|c| c.tree())
+        .map_err(|_| "Version not found".to_string())?;
+
+    let blob = |name: &str| -> Result<Vec<u8>, String> {
+        let entry = tree.get_name(name).ok_or("This note did not exist in that version")?;
+        let blob = repo.find_blob(entry.id()).map_err(git_error)?;
+        Ok(blob.content().to_vec())
+    };
+    let open = |data: &[u8]| {
+        storage::open(cipher, data).map_err(|e| {
+            if e == DECRYPT_FAILED {
+                "This version was saved under an earlier password".to_string()
+            } else {
+                e
+            }
+        })
+    };
+
+    if tree.get_name(PACK_FILE).is_some() {
+        let mut entries = storage::decode_pack(&open(&blob(PACK_FILE)?)?)?;
+        return names
+            .iter()
+            .map(|name| entries.remove(*name).ok_or_else(|| "This note did not exist in that version".to_string()))
+            .collect();
+    }
+    names.iter().map(|name| open(&blob(name)?)).collect()
+}
@@ -0,0 +1,208 @@
+use crate::secret::{SecretKey, KEY_LEN};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::Utc;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+pub(crate) type PublicKey = [u8; KEY_LEN];
+
+const KEY_PREFIX: &str = "noetiq-pk-";
+const CHECKSUM_LEN: usize = 4;
+const FINGERPRINT_LEN: usize = 10;
+const CARD_FORMAT: &str = "noetiq-contact";
+const MAX_NAME_LEN: usize = 100;
+
+// Lives inside the encrypted root index, like the TOTP secret. Each store, and each duress slot,
+// has its own.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct Identity {
+    secret: Zeroizing<String>,
+    pub created: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct Contact {
+    pub name: String,
+    pub public_key: String,
+    pub added: i64,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct IdentityInfo {
+    pub public_key: String,
+    pub fingerprint: String,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct ContactInfo {
+    pub name: String,
+    pub public_key: String,
+    pub fingerprint: String,
+}
+
+// What export_identity writes. Only the public half, so it can travel over any channel as long as
+// the fingerprint is compared over one the sender trusts.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ContactCard {
+    format: String,
+    name: String,
+    public_key: String,
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        let mut secret = Zeroizing::new([0u8; KEY_LEN]);
+        OsRng.fill_bytes(&mut secret[..]);
+        Identity {
+            secret: Zeroizing::new(hex::encode(&secret[..])),
+            created: Utc::now().timestamp(),
+        }
+    }
+
+    fn secret(&self) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+        let mut secret = Zeroizing::new([0u8; KEY_LEN]);
+        hex::decode_to_slice(self.secret.as_bytes(), &mut secret[..]).map_err(|_| "Invalid identity key".to_string())?;
+        Ok(secret)
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey, String> {
+        Ok(MontgomeryPoint::mul_base_clamped(*self.secret()?).to_bytes())
+    }
+
+    pub fn info(&self) -> Result<IdentityInfo, String> {
+        let public_key = self.public_key()?;
+        Ok(IdentityInfo {
+            public_key: encode_public_key(&public_key),
+            fingerprint: fingerprint(&public_key),
+        })
+    }
+
+    // A low order point yields the same all-zero secret whoever holds the private key, so it is
+    // refused rather than used to wrap anything.
+    fn agree(&self, peer: &PublicKey) -> Result<Zeroizing<[u8; KEY_LEN]>, String> {
+        let shared = Zeroizing::new(MontgomeryPoint(*peer).mul_clamped(*self.secret()?).to_bytes());
+        if shared.iter().all(|b| *b == 0) {
+            return Err("Invalid public key".into());
+        }
+        Ok(shared)
+    }
+}
+
+impl Contact {
+    pub fn info(&self) -> Result<ContactInfo, String> {
+        Ok(ContactInfo {
+            name: self.name.clone(),
+            public_key: self.public_key.clone(),
+            fingerprint: fingerprint(&decode_public_key(&self.public_key)?),
+        })
+    }
+}
+
+// The checksum catches a key mangled while being copied around, the fingerprint is what proves
+// whose it is.
+pub(crate) fn encode_public_key(key: &PublicKey) -> String {
+    let mut data = key.to_vec();
+    data.extend_from_slice(&Sha256::digest(key)[..CHECKSUM_LEN]);
+    format!("{}{}", KEY_PREFIX, BASE32_NOPAD.encode(&data).to_lowercase())
+}
+
+pub(crate) fn decode_public_key(value: &str) -> Result<PublicKey, String> {
+    let body = value.trim().strip_prefix(KEY_PREFIX).ok_or("Not a Noetiq public key")?;
+    let data = BASE32_NOPAD
+        .decode(body.to_uppercase().as_bytes())
+        .map_err(|_| "Not a Noetiq public key".to_string())?;
+    if data.len() != KEY_LEN + CHECKSUM_LEN || Sha256::digest(&data[..KEY_LEN])[..CHECKSUM_LEN] != data[KEY_LEN..] {
+        return Err("The public key is damaged, copy it again".into());
+    }
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(&data[..KEY_LEN]);
+    Ok(key)
+}
+
+pub(crate) fn fingerprint(key: &PublicKey) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"noetiq-identity\0");
+    hasher.update(key);
+    let hash = hex::encode(&hasher.finalize()[..FINGERPRINT_LEN]);
+    hash.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn check_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("Contact names need 1 to {} characters", MAX_NAME_LEN));
+    }
+    Ok(name.to_string())
+}
+
+pub(crate) fn write_card(path: &Path, name: &str, identity: &Identity) -> Result<(), String> {
+    let card = ContactCard {
+        format: CARD_FORMAT.to_string(),
+        name: check_name(name)?,
+        public_key: encode_public_key(&identity.public_key()?),
+    };
+    crate::atomic_write(path, &serde_json::to_vec_pretty(&card).map_err(|e| e.to_string())?)
+}
+
+// Returns the card as a contact for the user to confirm, nothing is added yet.
+pub(crate) fn read_card(path: &Path) -> Result<ContactInfo, String> {
+    let data = std::fs::read(path).map_err(|_| "Failed to read the contact file".to_string())?;
+    let card: ContactCard = serde_json::from_slice(&data).map_err(|_| "Not a Noetiq contact file".to_string())?;
+    if card.format != CARD_FORMAT {
+        return Err("Not a Noetiq contact file".into());
+    }
+    let public_key = decode_public_key(&card.public_key)?;
+    Ok(ContactInfo {
+        name: check_name(&card.name)?,
+        public_key: encode_public_key(&public_key),
+        fingerprint: fingerprint(&public_key),
+    })
+}
+
+// Both the one-off ephemeral key and the sender's identity take part, so only the sender or the
+// recipient could have wrapped the key and a recipient knows who it came from.
+fn key_encryption_key(
+    ephemeral_shared: &[u8],
+    static_shared: &[u8],
+    ephemeral: &PublicKey,
+    sender: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<SecretKey, String> {
+    let secret = Zeroizing::new([ephemeral_shared, static_shared].concat());
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret).map_err(|e| e.to_string())?;
+    mac.update(b"noetiq-share\0");
+    mac.update(ephemeral);
+    mac.update(sender);
+    mac.update(recipient);
+    let mut key = SecretKey::zeroed();
+    key.as_mut().copy_from_slice(&mac.finalize().into_bytes());
+    Ok(key)
+}
+
+pub(crate) fn wrapping_key(ephemeral: &Identity, sender: &Identity, recipient: &PublicKey) -> Result<SecretKey, String> {
+    key_encryption_key(
+        &ephemeral.agree(recipient)?[..],
+        &sender.agree(recipient)?[..],
+        &ephemeral.public_key()?,
+        &sender.public_key()?,
+        recipient,
+    )
+}
+
+pub(crate) fn unwrapping_key(recipient: &Identity, ephemeral: &PublicKey, sender: &PublicKey) -> Result<SecretKey, String> {
+    key_encryption_key(
+        &recipient.agree(ephemeral)?[..],
+        &recipient.agree(sender)?[..],
+        ephemeral,
+        sender,
+        &recipient.public_key()?,
+    )
+}
@@ -0,0 +1,33 @@
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use zeroize::Zeroizing;
+
+const KEYFILE_CONTEXT: &[u8] = b"noetiq-keyfile-v1";
+
+fn hash_keyfile(path: &str) -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut file = File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!("Keyfile not found: {}", path),
+        _ => format!("Failed to read keyfile: {}", e),
+    })?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(KEYFILE_CONTEXT);
+    let read = io::copy(&mut file, &mut hasher).map_err(|e| format!("Failed to read keyfile: {}", e))?;
+    if read == 0 {
+        return Err("The keyfile is empty".into());
+    }
+
+    Ok(Zeroizing::new(hasher.finalize().into()))
+}
+
+// Builds the KDF input. Without a keyfile it is the bare password, so stores created
+// before keyfiles existed keep deriving the same key.
+pub(crate) fn secret(password: &str, keyfile: Option<&str>) -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut secret = Zeroizing::new(password.as_bytes().to_vec());
+    if let Some(path) = keyfile {
+        secret.push(0);
+        secret.extend_from_slice(&hash_keyfile(path)?[..]);
+    }
+    Ok(secret)
+}
@@ -1,12 +1,8 @@
-use aes_gcm::{
-    aead::{rand_core::RngCore, Aead, OsRng},
-    Aes256Gcm, KeyInit, Nonce,
-};
-use argon2::Argon2;
-use argon2::{Algorithm, Params, Version};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
 use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
 use dirs_next::config_dir;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::time::UNIX_EPOCH;
@@ -15,26 +11,64 @@ use std::fs::{File, rename};
 use std::io::Write;
 use std::path::Path;
 use std::ffi::OsStr;
+use tauri::{Emitter, Manager, State, WindowEvent};
+use zeroize::Zeroizing;
+
+mod attachments;
+// Public only for benches/compression.rs.
+#[doc(hidden)]
+pub mod bench;
+mod cipher;
+mod crdt;
+mod duress;
+mod history;
+mod identity;
+mod keyfile;
+mod lock;
+mod metadata;
+mod os_lock;
+// Public only for the wipe checks under tests/, which need an allocator of their own.
+#[doc(hidden)]
+pub mod secret;
+mod share;
+mod shred;
+mod stats;
+mod storage;
+mod stream;
+mod strength;
+mod sync;
+mod team;
+mod throttle;
+mod totp;
+
+use attachments::AttachmentInfo;
+use cipher::{Cipher, CipherSuite, NONCE_LEN};
+use duress::{DuressSettings, DuressStatus, Slot};
+use history::{HistoryConfig, HistoryStatus, NoteVersion};
+use identity::{Contact, ContactInfo, Identity, IdentityInfo, PublicKey};
+use lock::{AppLock, LockSettings, StoreGuard, StoreLock, UnlockedKey};
+use secret::{Plaintext, SecretKey};
+use share::{OpenedFiles, Protection, SharedContent, SharedFileInfo, SharedNote, SharedVault};
+use metadata::{HintInput, KdfParams, PublicInfo, PublicMetadata, FORMAT_VERSION, PUBLIC_FILE};
+use stats::VaultStats;
+use storage::{StorageSettings, VaultStore, INDEX_FILE, PACK_FILE};
+use strength::{PasswordPolicy, PasswordStrength};
+use team::{MemberInfo, Team, TeamState};
+use sync::{HostEvents, LanHost, LanHostStatus, RemoteConfig, SyncConfig, SyncMonitor, SyncReport, SyncStatus};
+use throttle::{AttemptLog, UnlockReport};
+use totp::{TotpConfig, TotpEnrollment, TotpStatus};
 
 const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
 const ARGON2_ITERATIONS: u32 = 3;
 const ARGON2_PARALLELISM: u32 = 1;
 const SALT_LEN: usize = 16;
-const NONCE_LEN: usize = 12;
-
-fn make_argon2() -> Result<Argon2<'static>, String> {
-    let params = Params::new(
-        ARGON2_MEMORY_KIB,
-        ARGON2_ITERATIONS,
-        ARGON2_PARALLELISM,
-        None,
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
-}
 
 const MIN_DATA_LEN: usize = 44;
+const DECRYPT_FAILED: &str = "Decrypt failed";
 const VAULTS_FOLDER: &str = "noetiq-vaults";
+// Moves the store elsewhere, so two instances can run side by side, to try LAN sync over loopback
+// for example.
+const DATA_DIR_VAR: &str = "NOETIQ_DATA_DIR";
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 struct VaultEntry {
@@ -43,167 +77,655 @@ struct VaultEntry {
     description: String,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
 struct IdVaultEntry {
     icon: String,
     name: String,
     description: String,
     folder_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    team: Option<TeamState>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct RootIndex {
+    vaults: Vec<IdVaultEntry>,
+    #[serde(default)]
+    storage: StorageSettings,
+    #[serde(default)]
+    lock: LockSettings,
+    #[serde(default)]
+    password_policy: PasswordPolicy,
+    #[serde(default)]
+    totp: Option<TotpConfig>,
+    #[serde(default)]
+    duress: Option<DuressSettings>,
+    // Stores from before the slot files were drawn at random keep the real index in index.json.
+    #[serde(default)]
+    slot: Option<Slot>,
+    // What a decoy reports as its own duress password. It changes nothing at unlock.
+    #[serde(default)]
+    shown_duress: Option<DuressSettings>,
+    #[serde(default)]
+    sync: Option<SyncConfig>,
+    #[serde(default)]
+    history: Option<HistoryConfig>,
+    #[serde(default)]
+    identity: Option<Identity>,
+    #[serde(default)]
+    contacts: Vec<Contact>,
+    // Stores created before filler folders existed get theirs on the next unlock.
+    #[serde(default)]
+    filler_folders: bool,
+    // Keys attachment ids, which have to outlive password changes to keep deduplicating.
+    #[serde(default)]
+    attachment_key: Option<Zeroizing<String>>,
+}
+
+// Stores created before the root index became an object only hold the vault list.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum StoredRootIndex {
+    Legacy(Vec<IdVaultEntry>),
+    Current(Box<RootIndex>),
+}
+
+fn load_root_index(key: &UnlockedKey) -> Result<(PathBuf, RootIndex), String> {
+    let (vault_dir, data) = read_root_index_file(key.file)?;
+    if data[0..SALT_LEN] != key.salt[..] {
+        return Err("Vault key no longer matches the store".into());
+    }
+
+    let cipher = cipher_from_key(&key.key[..], CipherSuite::default())?;
+    let decrypted_data = storage::open(&cipher, &data[SALT_LEN..])?;
+    Ok((vault_dir, parse_root_index(&decrypted_data)?))
+}
+
+fn parse_root_index(data: &[u8]) -> Result<RootIndex, String> {
+    let stored: StoredRootIndex =
+        serde_json::from_slice(data).map_err(|_| "Invalid vault JSON".to_string())?;
+
+    Ok(match stored {
+        StoredRootIndex::Legacy(vaults) => RootIndex {
+            vaults,
+            ..Default::default()
+        },
+        StoredRootIndex::Current(index) => *index,
+    })
+}
+
+struct Session {
+    vault_dir: PathBuf,
+    key: UnlockedKey,
+    cipher: Cipher,
+    index: RootIndex,
+    // Team vaults are sealed with their own key, unwrapped from their member list at unlock.
+    teams: HashMap<String, Cipher>,
+    writes: StoreLock,
+    held: Option<StoreGuard>,
+}
+
+fn base_dir() -> Result<PathBuf, String> {
+    match std::env::var_os(DATA_DIR_VAR) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => config_dir().ok_or_else(|| "No config directory found".to_string()),
+    }
+}
+
+fn read_root_index_file(file: &str) -> Result<(PathBuf, Vec<u8>), String> {
+    let base_dir = base_dir()?;
+    let vault_dir = base_dir.join(VAULTS_FOLDER);
+
+    let data = fs::read(vault_dir.join(file)).map_err(|e| e.to_string())?;
+    if data.len() < MIN_DATA_LEN {
+        return Err("Corrupted vault file".into());
+    }
+    Ok((vault_dir, data))
+}
+
+impl Session {
+    fn open(password: &str, keyfile: Option<&str>, writes: StoreLock) -> Result<Session, String> {
+        let vault_dir = base_dir()?.join(VAULTS_FOLDER);
+        let metadata = PublicMetadata::load(&vault_dir)?;
+        let secret = match (metadata.keyfile, keyfile) {
+            (true, None) => return Err("This store needs its keyfile to unlock".into()),
+            (false, Some(_)) => return Err("This store does not use a keyfile".into()),
+            _ => keyfile::secret(password, keyfile)?,
+        };
+
+        // Both slots are always derived so unlock takes the same time whichever one matches.
+        let mut keys = Vec::new();
+        for file in duress::INDEX_FILES {
+            if let Ok((_, data)) = read_root_index_file(file) {
+                let salt = data[0..SALT_LEN].to_vec();
+                let key = derive_key(&secret, &salt, &metadata.kdf)?;
+                keys.push(UnlockedKey { file, salt, key });
+            }
+        }
+        if keys.is_empty() {
+            return Err("No vaults found".into());
+        }
+
+        let mut result = Err(DECRYPT_FAILED.to_string());
+        for key in keys {
+            result = Session::from_key(key, writes.clone(), true);
+            if !matches!(&result, Err(e) if e == DECRYPT_FAILED) {
+                break;
+            }
+        }
+        result
+    }
+
+    // A held lock is taken before the index is read, so the session saves over nothing newer.
+    fn from_key(key: UnlockedKey, writes: StoreLock, hold: bool) -> Result<Session, String> {
+        let held = hold.then(|| writes.acquire());
+        let (vault_dir, index) = load_root_index(&key)?;
+        let mut cipher = cipher_from_key(&key.key[..], CipherSuite::default())?;
+        cipher.set_suite(index.storage.cipher);
+
+        let mut session = Session {
+            vault_dir,
+            key,
+            cipher,
+            index,
+            teams: HashMap::new(),
+            writes,
+            held,
+        };
+        session.open_teams()?;
+        Ok(session)
+    }
+
+    fn slot(&self) -> Slot {
+        match self.index.slot {
+            Some(slot) => slot,
+            None if self.key.file == INDEX_FILE => Slot::Primary,
+            None => Slot::Alternate,
+        }
+    }
+
+    // The duress password the session reports as its own, a decoy only one that was set from it.
+    fn shown_duress(&self) -> Option<DuressSettings> {
+        match self.slot() {
+            Slot::Primary => self.index.duress,
+            Slot::Alternate => self.index.shown_duress,
+        }
+    }
+
+    // None when the session holds the store lock already.
+    fn writing(&self) -> Option<StoreGuard> {
+        match self.held {
+            Some(_) => None,
+            None => Some(self.writes.acquire()),
+        }
+    }
+
+    // A session without the store lock reads the root index again under it before changing it,
+    // instead of saving the copy it started with over whatever commands saved since.
+    fn update_index<T>(&mut self, change: impl FnOnce(&mut Session) -> Result<T, String>) -> Result<T, String> {
+        let writing = self.writing();
+        if writing.is_some() {
+            self.index = load_root_index(&self.key)?.1;
+            self.cipher.set_suite(self.index.storage.cipher);
+        }
+        let result = change(self)?;
+        self.save_index()?;
+        Ok(result)
+    }
+
+    // A vault whose member list can no longer be trusted, or no longer lists this store, stays
+    // locked.
+    fn open_teams(&mut self) -> Result<(), String> {
+        self.teams.clear();
+        let Some(identity) = self.index.identity.clone() else {
+            return Ok(());
+        };
+        let mut changed = Vec::new();
+        for vault in &self.index.vaults {
+            let Some(state) = &vault.team else { continue };
+            let dir = self.vault_dir.join(&vault.folder_id);
+            let Ok(team) = Team::open(&dir, &vault.folder_id, &identity, Some(state), &self.index.contacts) else {
+                continue;
+            };
+            self.teams.insert(vault.folder_id.clone(), team.cipher(self.index.storage.cipher)?);
+            if team.state != *state {
+                changed.push((vault.folder_id.clone(), team.state));
+            }
+        }
+        if changed.is_empty() {
+            return Ok(());
+        }
+        self.update_index(|session| {
+            for (folder_id, state) in changed {
+                if let Some(vault) = session.index.vaults.iter_mut().find(|v| v.folder_id == folder_id && v.team.is_some()) {
+                    vault.team = Some(state);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn save_index(&self) -> Result<(), String> {
+        self.write_index(&self.vault_dir)
+    }
+
+    // Into `dir`, which is a copy of the store while it is being converted.
+    fn write_index(&self, dir: &Path) -> Result<(), String> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(&self.index).map_err(|e| e.to_string())?);
+
+        let mut output_data = Vec::new();
+        output_data.extend_from_slice(&self.key.salt);
+        output_data.extend_from_slice(&storage::seal(&self.cipher, &plaintext, self.index.storage)?);
+
+        let index_path = dir.join(self.key.file);
+        atomic_write(&index_path, &output_data)?;
+        if self.index.storage.hardened {
+            storage::normalize_mtime(&index_path)?;
+        }
+        Ok(())
+    }
+
+    // A team vault whose list this store could not open has no key here, and must not get the
+    // store's own in its place.
+    fn vault_cipher(&self, folder_id: &str) -> Result<&Cipher, String> {
+        if let Some(cipher) = self.teams.get(folder_id) {
+            return Ok(cipher);
+        }
+        if self.index.vaults.iter().any(|v| v.folder_id == folder_id && v.team.is_some()) {
+            return Err("You no longer have access to this team vault".into());
+        }
+        Ok(&self.cipher)
+    }
+
+    fn vault(&self, folder_id: &str) -> Result<VaultStore<'_>, String> {
+        Ok(VaultStore::new(self.vault_dir.join(folder_id), self.vault_cipher(folder_id)?, self.index.storage))
+    }
 }
 
 #[tauri::command]
-fn read_public() -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_file = base_dir.join(VAULTS_FOLDER).join("public.json");
+fn read_public() -> Result<PublicInfo, String> {
+    let base_dir = base_dir()?;
+    let vault_dir = base_dir.join(VAULTS_FOLDER);
+    if !vault_dir.join(INDEX_FILE).exists() {
+        return Err("No vaults found".into());
+    }
+    Ok(PublicMetadata::load(&vault_dir)?.info())
+}
 
-    fs::read_to_string(&vault_file).map_err(|e| format!("Failed to read vaults"))
+#[tauri::command]
+fn reveal_hint(answer: Zeroizing<String>) -> Result<String, String> {
+    let base_dir = base_dir()?;
+    PublicMetadata::load(&base_dir.join(VAULTS_FOLDER))?.reveal_hint(&answer)
 }
 
 #[tauri::command]
-fn set_password(password: String, hint: &str) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
+fn set_password(
+    lock: State<'_, AppLock>,
+    password: Zeroizing<String>,
+    hint: HintInput,
+    store_name: Option<String>,
+    keyfile: Option<String>,
+) -> Result<(), String> {
+    let strength = strength::evaluate(&password, hint.text(), &PasswordPolicy::default());
+    if !strength.meets_policy {
+        return Err(strength.feedback.join(". "));
+    }
+
+    let base_dir = base_dir()?;
     let vault_dir = base_dir.join(VAULTS_FOLDER);
     fs::create_dir_all(&vault_dir).map_err(|e| e.to_string())?;
 
-    let public_path = vault_dir.join("public.json");
-    let public_json = serde_json::json!({ "hint": hint });
-    fs::write(
-        &public_path,
-        serde_json::to_vec_pretty(&public_json).unwrap(),
-    )
-    .map_err(|e| e.to_string())?;
-
-    let index_data: &[u8] = b"[]";
+    let mut metadata = PublicMetadata::default();
+    if let Some(store_name) = store_name.filter(|name| !name.trim().is_empty()) {
+        metadata.store_name = store_name;
+    }
+    metadata.set_hint(&hint)?;
+    metadata.keyfile = keyfile.is_some();
+    let secret = keyfile::secret(&password, keyfile.as_deref())?;
+    metadata.save(&vault_dir)?;
 
     let mut salt = [0u8; SALT_LEN];
     OsRng.fill_bytes(&mut salt);
 
-    let cipher = derive_cipher(&password, &salt)?;
+    let key = derive_key(&secret, &salt, &metadata.kdf)?;
+    let cipher = cipher_from_key(&key[..], CipherSuite::default())?;
+
+    let session = Session {
+        vault_dir,
+        key: UnlockedKey {
+            file: duress::random_file(),
+            salt: salt.to_vec(),
+            key,
+        },
+        cipher,
+        index: RootIndex {
+            slot: Some(Slot::Primary),
+            filler_folders: true,
+            ..Default::default()
+        },
+        teams: HashMap::new(),
+        writes: lock.store_lock(),
+        held: None,
+    };
+    session.save_index()?;
+    duress::write_filler(&session.vault_dir, duress::other_file(session.key.file), session.index.storage)?;
+    duress::write_initial_filler(&session.vault_dir, session.index.storage)?;
+
+    lock.unlock(session.key.clone(), session.index.lock);
+    Ok(())
+}
 
-    let (nonce_bytes, ciphertext) = encrypt_data(&cipher, index_data)?;
+#[tauri::command]
+fn evaluate_password(lock: State<'_, AppLock>, password: Zeroizing<String>, hint: &str) -> PasswordStrength {
+    let policy = lock
+        .session()
+        .map(|session| session.index.password_policy)
+        .unwrap_or_default();
+    strength::evaluate(&password, hint, &policy)
+}
 
-    let mut output_data = Vec::new();
-    output_data.extend_from_slice(&salt);
-    output_data.extend_from_slice(&nonce_bytes);
-    output_data.extend_from_slice(&ciphertext);
+#[tauri::command]
+fn get_password_policy(lock: State<'_, AppLock>) -> Result<PasswordPolicy, String> {
+    Ok(lock.session()?.index.password_policy)
+}
 
-    let index_path = vault_dir.join("index.json");
-    fs::write(&index_path, &output_data).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn set_password_policy(lock: State<'_, AppLock>, policy: PasswordPolicy) -> Result<(), String> {
+    if policy.min_length == 0 || policy.min_score > 4 {
+        return Err("Invalid password policy".into());
+    }
 
-    Ok(())
+    let mut session = lock.session()?;
+    session.index.password_policy = policy;
+    session.save_index()
 }
 
 #[tauri::command]
-fn get_vaults(password: &str) -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
+fn unlock(
+    lock: State<'_, AppLock>,
+    password: Zeroizing<String>,
+    keyfile: Option<&str>,
+    code: Option<&str>,
+) -> Result<UnlockReport, String> {
+    let base_dir = base_dir()?;
     let vault_dir = base_dir.join(VAULTS_FOLDER);
-    let index_path = vault_dir.join("index.json");
 
-    let data = fs::read(&index_path).map_err(|e| e.to_string())?;
+    let (mut session, report) = throttle::attempt(&vault_dir, || {
+        let mut session = Session::open(&password, keyfile, lock.store_lock())?;
+        if let Some(totp) = session.index.totp.as_mut() {
+            let code = code.filter(|c| !c.trim().is_empty()).ok_or(totp::CODE_REQUIRED)?;
+            totp.verify(code)?;
+            session.save_index()?;
+        }
+        Ok(session)
+    })
+        .map_err(|e| match keyfile {
+            Some(_) if e == throttle::WRONG_PASSWORD => "Wrong password or keyfile".to_string(),
+            _ => e,
+        })?;
+
+    duress::ensure_filler(&vault_dir, session.index.storage)?;
+    if !session.index.filler_folders {
+        duress::write_initial_filler(&vault_dir, session.index.storage)?;
+        session.index.filler_folders = true;
+        session.save_index()?;
+    }
+    if session.slot() == Slot::Alternate && session.index.duress.is_some_and(|d| d.wipe_primary) {
+        let keep: Vec<&str> = session.index.vaults.iter().map(|v| v.folder_id.as_str()).collect();
+        duress::shred_primary(&vault_dir, duress::other_file(session.key.file), session.index.storage, &keep)?;
+        session.index.duress = None;
+        session.save_index()?;
+    }
 
-    if data.len() < MIN_DATA_LEN {
-        return Err("Corrupted vault file".into());
+    lock.unlock(session.key, session.index.lock);
+    Ok(report)
+}
+
+#[tauri::command]
+fn lock_vaults(app: tauri::AppHandle, lock: State<'_, AppLock>) {
+    if lock.lock() {
+        let _ = app.emit("locked", ());
     }
+}
+
+#[tauri::command]
+fn report_activity(lock: State<'_, AppLock>) {
+    lock.touch();
+}
 
-    let salt_bytes = &data[0..SALT_LEN];
-    let nonce_bytes = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let ciphertext = &data[SALT_LEN + NONCE_LEN..];
+#[tauri::command]
+fn get_lock_settings(lock: State<'_, AppLock>) -> Result<LockSettings, String> {
+    Ok(lock.session()?.index.lock)
+}
 
-    let cipher = derive_cipher(password, salt_bytes)?;
-    let decrypted_data = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+#[tauri::command]
+fn set_lock_settings(lock: State<'_, AppLock>, settings: LockSettings) -> Result<(), String> {
+    let mut session = lock.session()?;
+    session.index.lock = settings;
+    session.save_index()?;
 
-    let json_str = String::from_utf8(decrypted_data).map_err(|e| e.to_string())?;
-    Ok(json_str)
+    lock.set_settings(settings);
+    Ok(())
 }
 
 #[tauri::command]
-fn create_vault(password: &str, new_vault: VaultEntry) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER);
-    let index_path = vault_dir.join("index.json");
+fn get_wipe_policy(lock: State<'_, AppLock>) -> Result<Option<u32>, String> {
+    let session = lock.session()?;
+    Ok(AttemptLog::load(&session.vault_dir).wipe_after)
+}
 
-    let data = fs::read(&index_path).map_err(|e| e.to_string())?;
-    if data.len() < MIN_DATA_LEN {
-        return Err("Corrupted vault file".into());
+#[tauri::command]
+fn set_wipe_policy(lock: State<'_, AppLock>, wipe_after: Option<u32>) -> Result<(), String> {
+    let session = lock.session()?;
+    if wipe_after.is_some_and(|limit| limit < 3) {
+        return Err("Wiping needs to allow at least 3 attempts".into());
     }
 
-    let salt_bytes = &data[0..SALT_LEN];
-    let nonce_bytes = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let ciphertext = &data[SALT_LEN + NONCE_LEN..];
+    let mut log = AttemptLog::load(&session.vault_dir);
+    log.wipe_after = wipe_after;
+    log.save(&session.vault_dir)
+}
 
-    let cipher = derive_cipher(password, salt_bytes)?;
-    let decrypted_data = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+// Both slots share the TOTP flag in the public metadata, and the decoy asks for the same codes, so
+// neither can change it alone.
+fn check_totp_change(session: &Session) -> Result<(), String> {
+    if session.index.duress.is_some() {
+        return Err(match session.shown_duress().is_some() {
+            true => "Remove the duress password before changing two-factor authentication",
+            false => "Two-factor authentication cannot be changed right now",
+        }
+        .into());
+    }
+    Ok(())
+}
 
-    let mut vaults: Vec<IdVaultEntry> =
-        serde_json::from_slice(&decrypted_data).map_err(|e| format!("Invalid vault JSON"))?;
+#[tauri::command]
+fn get_totp_status(lock: State<'_, AppLock>) -> Result<TotpStatus, String> {
+    Ok(TotpConfig::status(lock.session()?.index.totp.as_ref()))
+}
 
-    let folder_id = gen_id(&vault_dir)?;
-    let new_entry = IdVaultEntry {
-        icon: new_vault.icon,
-        name: new_vault.name,
-        description: new_vault.description,
-        folder_id: folder_id.clone(),
-    };
-    vaults.push(new_entry);
+#[tauri::command]
+fn begin_totp_enrollment(lock: State<'_, AppLock>) -> Result<TotpEnrollment, String> {
+    let session = lock.session()?;
+    if session.index.totp.is_some() {
+        return Err("Two-factor authentication is already enabled".into());
+    }
+
+    let metadata = PublicMetadata::load(&session.vault_dir)?;
+    totp::enrollment(&totp::new_secret(), &metadata.store_name)
+}
 
-    let plaintext = serde_json::to_vec_pretty(&vaults).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_ciphertext) = encrypt_data(&cipher, &plaintext)?;
+#[tauri::command]
+fn confirm_totp_enrollment(lock: State<'_, AppLock>, secret: &str, code: &str) -> Result<Vec<String>, String> {
+    let mut session = lock.session()?;
+    if session.index.totp.is_some() {
+        return Err("Two-factor authentication is already enabled".into());
+    }
+    check_totp_change(&session)?;
 
-    let mut output_data = Vec::new();
-    output_data.extend_from_slice(salt_bytes);
-    output_data.extend_from_slice(&new_nonce_bytes);
-    output_data.extend_from_slice(&new_ciphertext);
-    fs::write(&index_path, &output_data).map_err(|e| e.to_string())?;
+    let (config, backup_codes) = TotpConfig::enroll(secret, code)?;
+    session.index.totp = Some(config);
+    session.save_index()?;
 
-    let new_vault_path = vault_dir.join(&folder_id);
-    fs::create_dir_all(&new_vault_path).map_err(|e| format!("Failed to create vault folder"))?;
+    let mut metadata = PublicMetadata::load(&session.vault_dir)?;
+    metadata.totp = true;
+    metadata.save(&session.vault_dir)?;
+    Ok(backup_codes)
+}
 
-    let notes_json_data: Vec<serde_json::Value> = Vec::new(); // []
-    let notes_json = serde_json::to_vec_pretty(&notes_json_data)
-        .map_err(|e| format!("Failed to serialize index.json"))?;
+#[tauri::command]
+fn disable_totp(lock: State<'_, AppLock>, code: &str) -> Result<(), String> {
+    let mut session = lock.session()?;
+    check_totp_change(&session)?;
+    session.index.totp.as_mut().ok_or("Two-factor authentication is not enabled")?.verify(code)?;
+    session.index.totp = None;
+    session.save_index()?;
+
+    let mut metadata = PublicMetadata::load(&session.vault_dir)?;
+    metadata.totp = false;
+    metadata.save(&session.vault_dir)
+}
 
-    let (notes_nonce_bytes, encrypted_notes) = encrypt_data(&cipher, &notes_json)?;
+#[tauri::command]
+fn regenerate_backup_codes(lock: State<'_, AppLock>, code: &str) -> Result<Vec<String>, String> {
+    let mut session = lock.session()?;
+    let totp = session.index.totp.as_mut().ok_or("Two-factor authentication is not enabled")?;
+    totp.verify(code)?;
+
+    let (codes, hashes) = totp::generate_backup_codes();
+    totp.backup_codes = hashes;
+    session.save_index()?;
+    Ok(codes)
+}
 
-    let mut notes_index_data = Vec::new();
-    notes_index_data.extend_from_slice(&notes_nonce_bytes);
-    notes_index_data.extend_from_slice(&encrypted_notes);
+#[tauri::command]
+fn get_duress_status(lock: State<'_, AppLock>) -> Result<DuressStatus, String> {
+    let session = lock.session()?;
+    let settings = session.shown_duress();
+    Ok(DuressStatus {
+        enabled: settings.is_some(),
+        wipe_primary: settings.is_some_and(|s| s.wipe_primary),
+    })
+}
 
-    let notes_index_path = new_vault_path.join("index.json");
-    fs::write(&notes_index_path, notes_index_data)
-        .map_err(|e| format!("Failed to write index.json"))?;
+#[tauri::command]
+fn set_duress_password(
+    lock: State<'_, AppLock>,
+    password: Zeroizing<String>,
+    keyfile: Option<&str>,
+    wipe_primary: bool,
+) -> Result<(), String> {
+    let mut session = lock.session()?;
 
+    let metadata = PublicMetadata::load(&session.vault_dir)?;
+    if metadata.keyfile && keyfile.is_none() {
+        return Err("The keyfile is required to set a duress password".into());
+    }
+    let secret = keyfile::secret(&password, keyfile.filter(|_| metadata.keyfile))?;
+    if derive_key(&secret, &session.key.salt, &metadata.kdf)? == session.key.key {
+        return Err("The duress password must differ from the real password".into());
+    }
+
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(&secret, &salt, &metadata.kdf)?;
+    let settings = DuressSettings { wipe_primary };
+
+    // The decoy goes through the same steps and keeps the settings to show them back, there is
+    // no third slot for the password to open.
+    if session.slot() == Slot::Alternate {
+        session.index.shown_duress = Some(settings);
+        return session.save_index();
+    }
+
+    // Each new decoy draws the slot files again. The real index is written to its file before the
+    // decoy replaces it, so an interruption leaves a copy of it in both.
+    let file = duress::random_file();
+    session.index.slot = Some(Slot::Primary);
+    session.index.duress = Some(settings);
+    session.key.file = file;
+    session.save_index()?;
+
+    let decoy = Session {
+        vault_dir: session.vault_dir.clone(),
+        cipher: cipher_from_key(&key[..], session.index.storage.cipher)?,
+        key: UnlockedKey {
+            file: duress::other_file(file),
+            salt: salt.to_vec(),
+            key,
+        },
+        index: RootIndex {
+            storage: session.index.storage,
+            totp: session.index.totp.clone(),
+            duress: Some(settings),
+            slot: Some(Slot::Alternate),
+            filler_folders: true,
+            ..Default::default()
+        },
+        teams: HashMap::new(),
+        writes: session.writes.clone(),
+        held: None,
+    };
+    decoy.save_index()?;
+
+    lock.unlock(session.key.clone(), session.index.lock);
+    Ok(())
+}
+
+#[tauri::command]
+fn remove_duress_password(lock: State<'_, AppLock>) -> Result<(), String> {
+    let mut session = lock.session()?;
+    if session.slot() == Slot::Alternate {
+        session.index.shown_duress = None;
+        return session.save_index();
+    }
+
+    duress::write_filler(&session.vault_dir, duress::other_file(session.key.file), session.index.storage)?;
+    session.index.duress = None;
+    session.save_index()
+}
+
+#[tauri::command]
+fn get_vaults(lock: State<'_, AppLock>) -> Result<String, String> {
+    let session = lock.session()?;
+    serde_json::to_string(&session.index.vaults).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn create_vault(lock: State<'_, AppLock>, new_vault: VaultEntry) -> Result<(), String> {
+    let mut session = lock.session()?;
+
+    let folder_id = new_vault_id(&session)?;
+    let new_entry = IdVaultEntry {
+        icon: new_vault.icon,
+        name: new_vault.name,
+        description: new_vault.description,
+        folder_id: folder_id.clone(),
+        team: None,
+    };
+    session.index.vaults.push(new_entry);
+    session.save_index()?;
+
+    let store = session.vault(&folder_id)?;
+    store.init()?;
+    if session.index.history.is_some() {
+        history::init(store.dir())?;
+    }
     Ok(())
 }
 
 #[tauri::command]
 fn update_vault(
-    password: &str,
+    lock: State<'_, AppLock>,
     id: &str,
     name: String,
     description: String,
     icon: String,
 ) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER);
-    let index_path = vault_dir.join("index.json");
-
-    let data = fs::read(&index_path).map_err(|e| e.to_string())?;
-    if data.len() < MIN_DATA_LEN {
-        return Err("Corrupted vault file".into());
-    }
-
-    let salt_bytes = &data[0..SALT_LEN];
-    let nonce_bytes = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let ciphertext = &data[SALT_LEN + NONCE_LEN..];
-
-    let cipher = derive_cipher(password, salt_bytes)?;
-    let decrypted_data = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
-
-    let mut vaults: Vec<IdVaultEntry> =
-        serde_json::from_slice(&decrypted_data).map_err(|e| format!("Invalid vault JSON"))?;
+    let mut session = lock.session()?;
 
     let mut updated = false;
-    for vault in &mut vaults {
+    for vault in &mut session.index.vaults {
         if vault.folder_id == id {
             vault.name = name;
             vault.description = description;
@@ -216,437 +738,1178 @@ fn update_vault(
         return Err("Vault not found".into());
     }
 
-    let plaintext = serde_json::to_vec_pretty(&vaults).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_ciphertext) = encrypt_data(&cipher, &plaintext)?;
+    session.save_index()
+}
+
+#[tauri::command]
+fn delete_vault(lock: State<'_, AppLock>, folder_id: &str) -> Result<(), String> {
+    let mut session = lock.session()?;
+
+    let initial_len = session.index.vaults.len();
+    session.index.vaults.retain(|v| v.folder_id != folder_id);
+
+    if session.index.vaults.len() == initial_len {
+        return Err("Vault not found in index".into());
+    }
+
+    if let Some(history) = session.index.history.as_mut() {
+        history.removed.insert(folder_id.to_string());
+    }
+    session.save_index()?;
 
-    let mut output_data = Vec::new();
-    output_data.extend_from_slice(salt_bytes);
-    output_data.extend_from_slice(&new_nonce_bytes);
-    output_data.extend_from_slice(&new_ciphertext);
-    fs::write(&index_path, &output_data).map_err(|e| e.to_string())?;
+    let vault_path = session.vault_dir.join(folder_id);
+    if vault_path.exists() {
+        shred::remove_dir_all(&vault_path, session.index.storage.secure_delete)
+            .map_err(|_| "Failed to delete vault folder".to_string())?;
+    }
 
     Ok(())
 }
 
 #[tauri::command]
-fn delete_vault(password: &str, folder_id: &str) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER);
-    let index_path = vault_dir.join("index.json");
+fn get_vault_stats(lock: State<'_, AppLock>, foldername: &str) -> Result<VaultStats, String> {
+    let session = lock.session()?;
+    check_vault(&session, foldername)?;
+
+    let store = session.vault(foldername)?;
+    let mut stats = VaultStats::default();
+
+    for entry in read_notes_list(&store)? {
+        let filename = match entry.get("filename").and_then(|f| f.as_str()) {
+            Some(filename) => filename,
+            None => continue,
+        };
+
+        let decrypted = read_note(&store, &entry, filename)?;
+        let document: Value = serde_json::from_slice(&decrypted).unwrap_or(Value::Null);
+        stats.add_note(&document);
+        stats.track_modified(&entry);
+    }
 
-    let data = fs::read(&index_path).map_err(|e| e.to_string())?;
-    if data.len() < MIN_DATA_LEN {
-        return Err("Corrupted vault index file".into());
+    stats.storage_bytes = stats::dir_size(store.dir())?;
+
+    Ok(stats)
+}
+
+#[tauri::command]
+fn get_storage_settings(lock: State<'_, AppLock>) -> Result<StorageSettings, String> {
+    let session = lock.session()?;
+    Ok(session.index.storage)
+}
+
+#[tauri::command]
+fn set_storage_settings(lock: State<'_, AppLock>, mut settings: StorageSettings) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let previous = session.index.storage;
+    if settings.secure_delete && !previous.secure_delete && session.index.history.is_some() {
+        return Err("Turn off history first, it keeps every earlier version of each note".into());
     }
+    // The cipher only changes through reencrypt_data, which rewrites every layer.
+    settings.cipher = previous.cipher;
+
+    // Every vault is converted in a copy of the store that then replaces it whole, so a failure
+    // part way leaves the store as it was.
+    let secure = settings.secure_delete || previous.secure_delete;
+    let tmp_dir = copy_dir(&session.vault_dir, secure)?;
+    session.index.storage = settings;
+    let converted = convert_vaults(&session, &tmp_dir, previous).and_then(|_| swap_dir(&session.vault_dir, &tmp_dir));
+    match converted {
+        Ok(backup_dir) => shred::remove_dir_all(&backup_dir, secure),
+        Err(e) => {
+            session.index.storage = previous;
+            let _ = shred::remove_dir_all(&tmp_dir, secure);
+            Err(e)
+        }
+    }
+}
 
-    let salt_bytes = &data[0..SALT_LEN];
-    let nonce_bytes = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let ciphertext = &data[SALT_LEN + NONCE_LEN..];
+fn convert_vaults(session: &Session, dir: &Path, previous: StorageSettings) -> Result<(), String> {
+    let settings = session.index.storage;
+    for vault in &session.index.vaults {
+        // A team vault this store cannot open keeps its layout, reading handles either one.
+        let Ok(cipher) = session.vault_cipher(&vault.folder_id) else {
+            continue;
+        };
+        let store = VaultStore::new(dir.join(&vault.folder_id), cipher, settings);
+
+        let mut notes_list = read_notes_list(&store)?;
+        if settings.hardened && !previous.hardened && !store.is_packed() {
+            for note in notes_list.iter_mut() {
+                if note.get("modified").is_some() {
+                    continue;
+                }
+                let filename = note["filename"].as_str().unwrap_or_default();
+                if let Ok(modified) = fs::metadata(store.dir().join(filename)).and_then(|m| m.modified()) {
+                    let secs = modified.duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs();
+                    note["modified"] = json!(secs);
+                }
+            }
+            write_notes_list(&store, &notes_list)?;
+        }
 
-    let cipher = derive_cipher(password, salt_bytes)?;
-    let decrypted_data = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+        if settings.compress != previous.compress {
+            for note in &notes_list {
+                reseal_note(&store, note, note["filename"].as_str().unwrap_or_default())?;
+            }
+        }
 
-    let mut vaults: Vec<IdVaultEntry> =
-        serde_json::from_slice(&decrypted_data).map_err(|e| format!("Invalid vault JSON"))?;
+        store.migrate()?;
+    }
 
-    let initial_len = vaults.len();
-    vaults.retain(|v| v.folder_id != folder_id);
+    session.write_index(dir)?;
 
-    if vaults.len() == initial_len {
-        return Err("Vault not found in index".into());
+    if settings.hardened {
+        storage::normalize_tree(dir)?;
     }
+    Ok(())
+}
 
-    let plaintext = serde_json::to_vec_pretty(&vaults).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_ciphertext) = encrypt_data(&cipher, &plaintext)?;
+// A copy of `dir` to change and then swap in whole, replacing whatever an interrupted run left.
+fn copy_dir(dir: &Path, secure: bool) -> Result<PathBuf, String> {
+    let tmp_dir = sibling_path(dir, ".tmp");
+    if tmp_dir.exists() {
+        shred::remove_dir_all(&tmp_dir, secure)?;
+    }
+    fs_extra::dir::copy(dir, &tmp_dir, &fs_extra::dir::CopyOptions::new().content_only(true))
+        .map_err(|e| format!("Failed to copy {}: {}", dir.display(), e))?;
+    Ok(tmp_dir)
+}
 
-    let mut output_data = Vec::new();
-    output_data.extend_from_slice(salt_bytes);
-    output_data.extend_from_slice(&new_nonce_bytes);
-    output_data.extend_from_slice(&new_ciphertext);
-    fs::write(&index_path, &output_data).map_err(|e| e.to_string())?;
+// Renames `tmp_dir` over `dir`, so a crash leaves one or the other whole. Returns where the
+// previous contents went, for the caller to remove once nothing refers to them.
+fn swap_dir(dir: &Path, tmp_dir: &Path) -> Result<PathBuf, String> {
+    let backup_dir = sibling_path(dir, ".bak");
+    if backup_dir.exists() {
+        shred::remove_dir_all(&backup_dir, true)?;
+    }
+    rename(dir, &backup_dir).map_err(|e| format!("Failed to move {} aside: {}", dir.display(), e))?;
+    if let Err(e) = rename(tmp_dir, dir) {
+        let _ = rename(&backup_dir, dir);
+        return Err(format!("Failed to move the new copy into place: {}. Reverted.", e));
+    }
+    Ok(backup_dir)
+}
 
-    let vault_path = vault_dir.join(&folder_id);
-    if vault_path.exists() {
-        fs::remove_dir_all(vault_path).map_err(|e| format!("Failed to delete vault folder"))?;
+fn gen_id(base_dir: &Path) -> Result<String, String> {
+    loop {
+        let uuid = Uuid::new_v4().to_string();
+        let candidate_path = base_dir.join(&uuid);
+
+        if !candidate_path.exists() {
+            return Ok(uuid);
+        }
+    }
+}
+
+// Every vault comes with a filler folder, whichever slot creates it.
+fn new_vault_id(session: &Session) -> Result<String, String> {
+    duress::write_filler_folder(&session.vault_dir, session.index.storage)?;
+    gen_id(&session.vault_dir)
+}
+
+fn read_notes_list(store: &VaultStore) -> Result<Vec<Value>, String> {
+    let decrypted = store.read(INDEX_FILE)?;
+    if decrypted.is_empty() {
+        return Ok(vec![]);
     }
+    serde_json::from_slice(&decrypted).map_err(|_| "Invalid JSON data".to_string())
+}
+
+fn write_notes_list(store: &VaultStore, notes_list: &[Value]) -> Result<(), String> {
+    let updated_json = Zeroizing::new(serde_json::to_vec(notes_list).map_err(|e| e.to_string())?);
+    store.write(INDEX_FILE, &updated_json)
+}
+
+// Moves the bytes into a string without leaving an unwiped copy behind.
+fn plaintext_string(mut data: Plaintext) -> Result<Zeroizing<String>, String> {
+    String::from_utf8(std::mem::take(&mut *data))
+        .map(Zeroizing::new)
+        .map_err(|e| {
+            drop(Zeroizing::new(e.into_bytes()));
+            "UTF-8 error.".to_string()
+        })
+}
+
+fn find_note<'a>(notes_list: &'a [Value], filename: &str) -> Result<&'a Value, String> {
+    notes_list
+        .iter()
+        .find(|note| note.get("filename").and_then(|f| f.as_str()) == Some(filename))
+        .ok_or_else(|| "Note not found".to_string())
+}
+
+fn read_note(store: &VaultStore, entry: &Value, filename: &str) -> Result<Plaintext, String> {
+    open_note_data(entry, store.read(filename)?)
+}
 
+// Notes created before per-note keys are only sealed with the vault key.
+fn open_note_data(entry: &Value, data: Plaintext) -> Result<Plaintext, String> {
+    match shred::note_key(entry) {
+        Some(key) => storage::decompress(shred::open_note(key, &data)?),
+        None => Ok(data),
+    }
+}
+
+// Notes with their own key are compressed before sealing, the vault layer only sees ciphertext.
+fn write_note(store: &VaultStore, entry: &Value, filename: &str, plaintext: &[u8]) -> Result<(), String> {
+    let settings = store.settings();
+    match shred::note_key(entry) {
+        Some(key) if settings.compress => {
+            store.write(filename, &shred::seal_note(key, settings.cipher, &storage::compress(plaintext)?)?)
+        }
+        Some(key) => store.write(filename, &shred::seal_note(key, settings.cipher, plaintext)?),
+        None => store.write(filename, plaintext),
+    }
+}
+
+// Records the document in the note's CRDT log and stores what the log materializes to, so the
+// note file always matches its log.
+fn save_document(store: &VaultStore, entry: &Value, filename: &str, content: &[u8]) -> Result<(), String> {
+    let document: Value = serde_json::from_slice(content).map_err(|_| "Invalid note data".to_string())?;
+    let mut log = crdt::load(store, entry, filename)?.unwrap_or_default();
+    let materialized = log.record(&document)?;
+    // No other device holds a copy of a note in a vault that never synced.
+    if !store.contains(sync::SYNC_FILE)? {
+        log.compact(&[]);
+    }
+    crdt::save(store, entry, filename, &log)?;
+    write_note(store, entry, filename, &materialized)
+}
+
+// Rewrites a note and its log with the current settings.
+fn reseal_note(store: &VaultStore, entry: &Value, filename: &str) -> Result<(), String> {
+    for name in [filename.to_string(), crdt::log_name(filename)] {
+        if store.contains(&name)? {
+            let data = read_note(store, entry, &name)?;
+            write_note(store, entry, &name, &data)?;
+        }
+    }
     Ok(())
 }
 
+fn read_document(store: &VaultStore, entry: &Value, filename: &str) -> Result<Plaintext, String> {
+    match crdt::load(store, entry, filename)? {
+        Some(log) => log.materialize(),
+        None => read_note(store, entry, filename),
+    }
+}
+
 #[tauri::command]
-fn get_vault_notes_number(foldername: &str) -> Result<usize, String> {
-    let base_dir = config_dir()
-        .ok_or("No config directory found")?
-        .join(VAULTS_FOLDER)
-        .join(foldername);
+fn create_note(lock: State<'_, AppLock>, vaultfolder: &str, icon: &str) -> Result<String, String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
 
-    if !base_dir.exists() || !base_dir.is_dir() {
-        return Err("Folder does not exist or is not a directory".into());
+    let mut notes_list = read_notes_list(&store)?;
+
+    let note_id = gen_id(store.dir())?;
+    let note_filename = format!("{}.json", note_id);
+    let note_content = b"{}";
+
+    let note_entry = json!({
+        "notetitle": "",
+        "filename": note_filename,
+        "icon": icon,
+        "modified": Utc::now().timestamp(),
+        "key": shred::new_note_key()
+    });
+    write_note(&store, &note_entry, &note_filename, note_content)?;
+    notes_list.push(note_entry);
+
+    write_notes_list(&store, &notes_list)?;
+
+    Ok(note_filename)
+}
+
+#[tauri::command]
+fn get_notes_index(lock: State<'_, AppLock>, vaultfolder: &str) -> Result<Zeroizing<String>, String> {
+    let session = lock.session()?;
+    let mut notes_list = read_notes_list(&session.vault(vaultfolder)?)?;
+
+    // Per-note keys never leave the backend.
+    for note in notes_list.iter_mut() {
+        if let Some(entry) = note.as_object_mut() {
+            entry.remove("key");
+        }
     }
 
-    let count = fs::read_dir(&base_dir)
-        .map_err(|e| format!("Failed to read directory"))?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            let path = entry.path();
-            path.is_file()
-                && path.extension().map(|ext| ext == "json").unwrap_or(false)
-                && path
-                    .file_name()
-                    .map(|name| name != "index.json")
-                    .unwrap_or(true)
-        })
-        .count();
+    let json = Zeroizing::new(serde_json::to_vec(&notes_list).map_err(|e| e.to_string())?);
+    plaintext_string(json)
+}
+
+#[tauri::command]
+fn save_note_data(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    filename: &str,
+    content: Zeroizing<String>,
+) -> Result<(), String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+
+    let mut notes_list = read_notes_list(&store)?;
+    save_document(&store, find_note(&notes_list, filename)?, filename, content.as_bytes())?;
+
+    for note in notes_list.iter_mut() {
+        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
+            note["modified"] = json!(Utc::now().timestamp());
+            break;
+        }
+    }
+    write_notes_list(&store, &notes_list)?;
 
-    Ok(count)
+    Ok(())
 }
 
-fn gen_id(base_dir: &PathBuf) -> Result<String, String> {
-    loop {
-        let uuid = Uuid::new_v4().to_string();
-        let candidate_path = base_dir.join(&uuid);
+#[tauri::command]
+fn get_note_data(lock: State<'_, AppLock>, vaultfolder: &str, filename: &str) -> Result<Zeroizing<String>, String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+    let notes_list = read_notes_list(&store)?;
+    plaintext_string(read_document(&store, find_note(&notes_list, filename)?, filename)?)
+}
+
+#[tauri::command]
+fn update_note_icon(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    filename: &str,
+    new_icon: &str,
+) -> Result<(), String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+
+    let mut notes_list = read_notes_list(&store)?;
+
+    let mut found = false;
+    for note in notes_list.iter_mut() {
+        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
+            note["icon"] = Value::String(new_icon.to_string());
+            found = true;
+            break;
+        }
+    }
+
+    if !found {
+        return Err("Note not found".to_string());
+    }
+
+    write_notes_list(&store, &notes_list)
+}
+
+#[tauri::command]
+fn update_note_title(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    filename: &str,
+    new_title: &str,
+) -> Result<(), String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+
+    let mut notes_list = read_notes_list(&store)?;
 
-        if !candidate_path.exists() {
-            return Ok(uuid);
+    let mut found = false;
+    for note in notes_list.iter_mut() {
+        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
+            note["notetitle"] = Value::String(new_title.to_string());
+            found = true;
+            break;
         }
     }
+    if !found {
+        return Err("Note not found.".to_string());
+    }
+
+    write_notes_list(&store, &notes_list)
 }
 
 #[tauri::command]
-fn create_note(password: &str, vaultfolder: &str, icon: &str) -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER).join(vaultfolder);
-    let notes_index_path = vault_dir.join("index.json");
+fn delete_note(lock: State<'_, AppLock>, note_id: String, vault_folder: String) -> Result<(), String> {
+    let session = lock.session()?;
+    let store = session.vault(&vault_folder)?;
 
-    let encrypted_data = fs::read(&notes_index_path).map_err(|e| e.to_string())?;
-    if encrypted_data.len() < NONCE_LEN {
-        return Err("Corrupted index.json".into());
-    }
+    let mut notes_list = read_notes_list(&store)?;
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted global vault index".into());
+    let initial_len = notes_list.len();
+    notes_list.retain(|entry| entry["filename"] != note_id);
+    if notes_list.len() == initial_len {
+        return Err("Note not found in index.json".into());
     }
-    let salt_bytes = &root_data[0..SALT_LEN];
 
-    let cipher = derive_cipher(password, salt_bytes)?;
+    // The previous index still holds the note's key, so it goes the way of the note itself.
+    let store = store.shredding();
+    write_notes_list(&store, &notes_list)?;
 
-    let nonce_bytes = &encrypted_data[0..NONCE_LEN];
-    let ciphertext = &encrypted_data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+    if !store.remove(&note_id)? {
+        return Err("Note not found".into());
+    }
+    store.remove(&crdt::log_name(&note_id))?;
 
-    let mut notes_list: Vec<Value> = if decrypted.is_empty() {
-        vec![]
-    } else {
-        serde_json::from_slice(&decrypted).map_err(|e| format!("Invalid JSON data"))?
-    };
+    // The note is gone either way, a failed sweep is retried by the next one.
+    let _ = attachments::collect_garbage(&store);
 
-    let note_id = gen_id(&vault_dir)?;
-    let note_filename = format!("{}.json", note_id);
-    let note_path = vault_dir.join(&note_filename);
-    let note_content = b"{}";
+    Ok(())
+}
 
-    let (note_nonce_bytes, encrypted_note) = encrypt_data(&cipher, note_content)?;
+fn shared_note(store: &VaultStore, entry: &Value) -> Result<SharedNote, String> {
+    let filename = entry.get("filename").and_then(|f| f.as_str()).ok_or("Invalid note entry")?;
+    let content = read_document(store, entry, filename)?;
+    let document: Value = serde_json::from_slice(&content).map_err(|_| "Invalid note data".to_string())?;
+    SharedNote::new(store, entry, document)
+}
 
-    let mut output_note = Vec::new();
-    output_note.extend_from_slice(&note_nonce_bytes);
-    output_note.extend_from_slice(&encrypted_note);
-    fs::write(note_path, output_note).map_err(|e| format!("Failed to write note file"))?;
+// Adds the note to the list, the caller writes the list once it is done.
+fn add_shared_note(
+    store: &VaultStore,
+    store_key: &[u8],
+    vaultfolder: &str,
+    notes_list: &mut Vec<Value>,
+    mut shared: SharedNote,
+) -> Result<String, String> {
+    shared.import_attachments(store, store_key, vaultfolder)?;
 
+    let note_filename = format!("{}.json", gen_id(store.dir())?);
     let note_entry = json!({
-        "notetitle": "",
+        "notetitle": shared.title,
         "filename": note_filename,
-        "icon": icon
+        "icon": shared.icon,
+        "modified": Utc::now().timestamp(),
+        "key": shred::new_note_key()
     });
+    let content = Zeroizing::new(serde_json::to_vec(&shared.document).map_err(|e| e.to_string())?);
+    save_document(store, &note_entry, &note_filename, &content)?;
     notes_list.push(note_entry);
+    Ok(note_filename)
+}
 
-    let updated_json = serde_json::to_vec(&notes_list).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_encrypted_index) = encrypt_data(&cipher, &updated_json)?;
+// Stores get their identity the first time they need one.
+fn store_identity(session: &mut Session) -> Result<Identity, String> {
+    if let Some(identity) = &session.index.identity {
+        return Ok(identity.clone());
+    }
+    let identity = Identity::generate();
+    session.index.identity = Some(identity.clone());
+    session.save_index()?;
+    Ok(identity)
+}
 
-    let mut final_output = Vec::new();
-    final_output.extend_from_slice(&new_nonce_bytes);
-    final_output.extend_from_slice(&new_encrypted_index);
-    fs::write(notes_index_path, final_output).map_err(|e| format!("Failed to write index.json"))?;
+// Like the identity, made the first time an attachment is stored.
+fn attachment_key(session: &mut Session) -> Result<Plaintext, String> {
+    let key = match &session.index.attachment_key {
+        Some(key) => key.clone(),
+        None => {
+            let key = Zeroizing::new(shred::new_note_key());
+            session.index.attachment_key = Some(key.clone());
+            session.save_index()?;
+            key
+        }
+    };
+    Ok(Zeroizing::new(hex::decode(key.as_str()).map_err(|_| "Invalid attachment key".to_string())?))
+}
 
-    Ok(note_filename)
+// Files are only sealed to keys the user added, and checked the fingerprint of, as contacts.
+fn contact_keys(session: &Session, recipients: &[String]) -> Result<Vec<PublicKey>, String> {
+    recipients
+        .iter()
+        .map(|recipient| {
+            let key = identity::decode_public_key(recipient)?;
+            session
+                .index
+                .contacts
+                .iter()
+                .any(|c| identity::decode_public_key(&c.public_key).ok() == Some(key))
+                .then_some(key)
+                .ok_or_else(|| "Share only with your contacts".to_string())
+        })
+        .collect()
 }
 
 #[tauri::command]
-fn get_notes_index(password: &str, vaultfolder: &str) -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER).join(vaultfolder);
-    let notes_index_path = vault_dir.join("index.json");
-
-    let data = fs::read(&notes_index_path).map_err(|e| format!("Error reading index.json"))?;
-    if data.len() < NONCE_LEN {
-        return Err("Corrupted index.json".into());
-    }
+fn share_note(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    filename: &str,
+    share_password: Zeroizing<String>,
+    path: &str,
+) -> Result<(), String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+    let notes_list = read_notes_list(&store)?;
+    let note = shared_note(&store, find_note(&notes_list, filename)?)?;
+    share::write(Path::new(path), &SharedContent::Note(note), Protection::Password(&share_password))
+}
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted index.json".into());
-    }
-    let salt_bytes = &root_data[0..SALT_LEN];
+#[tauri::command]
+fn share_note_with_contacts(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    filename: &str,
+    recipients: Vec<String>,
+    path: &str,
+) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let identity = store_identity(&mut session)?;
+    let keys = contact_keys(&session, &recipients)?;
+    let store = session.vault(vaultfolder)?;
+    let notes_list = read_notes_list(&store)?;
+    let note = shared_note(&store, find_note(&notes_list, filename)?)?;
+    share::write(Path::new(path), &SharedContent::Note(note), Protection::Recipients(&identity, &keys))
+}
 
-    let cipher = derive_cipher(password, salt_bytes)?;
+#[tauri::command]
+fn share_vault_with_contacts(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    recipients: Vec<String>,
+    path: &str,
+) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let identity = store_identity(&mut session)?;
+    let keys = contact_keys(&session, &recipients)?;
+    let vault = session
+        .index
+        .vaults
+        .iter()
+        .find(|v| v.folder_id == vaultfolder)
+        .ok_or("Vault not found in index")?;
+    let store = session.vault(vaultfolder)?;
+
+    let notes = read_notes_list(&store)?
+        .iter()
+        .map(|entry| shared_note(&store, entry))
+        .collect::<Result<Vec<_>, _>>()?;
+    let shared = SharedVault {
+        name: vault.name.clone(),
+        icon: vault.icon.clone(),
+        description: vault.description.clone(),
+        notes,
+    };
+    share::write(Path::new(path), &SharedContent::Vault(shared), Protection::Recipients(&identity, &keys))
+}
 
-    let nonce_bytes = &data[0..NONCE_LEN];
-    let ciphertext = &data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+#[tauri::command]
+fn describe_shared_file(lock: State<'_, AppLock>, path: &str) -> Result<SharedFileInfo, String> {
+    let session = lock.session()?;
+    share::describe(Path::new(path), session.index.identity.as_ref(), &session.index.contacts)
+}
 
-    let json_str = String::from_utf8(decrypted).map_err(|e| format!("UTF-8 error."))?;
+// The file is opened before the vault is touched, so a wrong password leaves nothing behind.
+#[tauri::command]
+fn import_shared_note(
+    lock: State<'_, AppLock>,
+    vaultfolder: &str,
+    path: &str,
+    share_password: Option<Zeroizing<String>>,
+) -> Result<String, String> {
+    let mut session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    let password = share_password.as_deref().map(|p| p.as_str());
+    let shared = match share::read(Path::new(path), password, session.index.identity.as_ref())? {
+        SharedContent::Note(note) => note,
+        SharedContent::Vault(_) => return Err("This file holds a whole vault".into()),
+    };
 
-    Ok(json_str)
+    let attachment_key = attachment_key(&mut session)?;
+    let store = session.vault(vaultfolder)?;
+    let mut notes_list = read_notes_list(&store)?;
+    let filename = add_shared_note(&store, &attachment_key, vaultfolder, &mut notes_list, shared)?;
+    write_notes_list(&store, &notes_list)?;
+    Ok(filename)
 }
 
 #[tauri::command]
-fn save_note_data(
-    password: &str,
-    vaultfolder: &str,
-    filename: &str,
-    content: &str,
-) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let file_path: PathBuf = base_dir
-        .join(VAULTS_FOLDER)
-        .join(vaultfolder)
-        .join(filename);
+fn import_shared_vault(
+    lock: State<'_, AppLock>,
+    path: &str,
+    share_password: Option<Zeroizing<String>>,
+) -> Result<String, String> {
+    let mut session = lock.session()?;
+    let password = share_password.as_deref().map(|p| p.as_str());
+    let shared = match share::read(Path::new(path), password, session.index.identity.as_ref())? {
+        SharedContent::Vault(vault) => vault,
+        SharedContent::Note(_) => return Err("This file holds a single note".into()),
+    };
+
+    let folder_id = new_vault_id(&session)?;
+    session.index.vaults.push(IdVaultEntry {
+        icon: shared.icon,
+        name: shared.name,
+        description: shared.description,
+        folder_id: folder_id.clone(),
+        team: None,
+    });
+    session.save_index()?;
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted index.json".into());
+    let attachment_key = attachment_key(&mut session)?;
+    let store = session.vault(&folder_id)?;
+    store.init()?;
+    if session.index.history.is_some() {
+        history::init(store.dir())?;
+    }
+    let mut notes_list = Vec::new();
+    for note in shared.notes {
+        add_shared_note(&store, &attachment_key, &folder_id, &mut notes_list, note)?;
     }
-    let salt_bytes = &root_data[0..SALT_LEN];
+    write_notes_list(&store, &notes_list)?;
+    Ok(folder_id)
+}
+
+#[tauri::command]
+fn get_identity(lock: State<'_, AppLock>) -> Result<IdentityInfo, String> {
+    store_identity(&mut lock.session()?)?.info()
+}
 
-    let cipher = derive_cipher(password, salt_bytes)?;
+#[tauri::command]
+fn export_identity(lock: State<'_, AppLock>, name: &str, path: &str) -> Result<(), String> {
+    let identity = store_identity(&mut lock.session()?)?;
+    identity::write_card(Path::new(path), name, &identity)
+}
 
-    let plaintext = content.as_bytes();
-    let (nonce_bytes, encrypted) = encrypt_data(&cipher, plaintext)?;
+#[tauri::command]
+fn read_contact_card(path: &str) -> Result<ContactInfo, String> {
+    identity::read_card(Path::new(path))
+}
 
-    let mut output = Vec::new();
-    output.extend_from_slice(&nonce_bytes);
-    output.extend_from_slice(&encrypted);
+#[tauri::command]
+fn get_contacts(lock: State<'_, AppLock>) -> Result<Vec<ContactInfo>, String> {
+    lock.session()?.index.contacts.iter().map(Contact::info).collect()
+}
 
-    fs::write(&file_path, output).map_err(|e| format!("Error writing file"))?;
+#[tauri::command]
+fn add_contact(lock: State<'_, AppLock>, name: &str, public_key: &str) -> Result<ContactInfo, String> {
+    let mut session = lock.session()?;
+    let name = identity::check_name(name)?;
+    let key = identity::decode_public_key(public_key)?;
+    if session.index.identity.as_ref().map(Identity::public_key).transpose()? == Some(key) {
+        return Err("This is the public key of this store".into());
+    }
+    if let Some(existing) = session
+        .index
+        .contacts
+        .iter()
+        .find(|c| identity::decode_public_key(&c.public_key).ok() == Some(key))
+    {
+        return Err(format!("This key is already saved as {}", existing.name));
+    }
 
-    Ok(())
+    let contact = Contact {
+        name,
+        public_key: identity::encode_public_key(&key),
+        added: Utc::now().timestamp(),
+    };
+    let info = contact.info()?;
+    session.index.contacts.push(contact);
+    session.save_index()?;
+    Ok(info)
 }
 
 #[tauri::command]
-fn get_note_data(password: &str, vaultfolder: &str, filename: &str) -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let file_path: PathBuf = base_dir
-        .join(VAULTS_FOLDER)
-        .join(vaultfolder)
-        .join(filename);
+fn remove_contact(lock: State<'_, AppLock>, public_key: &str) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let before = session.index.contacts.len();
+    session.index.contacts.retain(|c| c.public_key != public_key);
+    if session.index.contacts.len() == before {
+        return Err("Contact not found".into());
+    }
+    session.save_index()
+}
+
+fn open_team(session: &Session, folder_id: &str) -> Result<(Identity, Team), String> {
+    check_vault(session, folder_id)?;
+    let state = session
+        .index
+        .vaults
+        .iter()
+        .find(|v| v.folder_id == folder_id)
+        .and_then(|v| v.team.as_ref())
+        .ok_or("This is not a team vault")?;
+    let identity = session.index.identity.clone().ok_or("This store has no identity")?;
+    let team = Team::open(&session.vault_dir.join(folder_id), folder_id, &identity, Some(state), &session.index.contacts)?;
+    Ok((identity, team))
+}
+
+fn save_team(session: &mut Session, folder_id: &str, identity: &Identity, team: Team) -> Result<(), String> {
+    team.save(&session.vault_dir.join(folder_id), folder_id, identity)?;
+    record_team(session, folder_id, team)
+}
 
-    let data = fs::read(&file_path).map_err(|e| format!("Error reading note"))?;
-    if data.len() < NONCE_LEN {
-        return Err("Corrupted file".into());
+// The saved list becomes the one this store trusts.
+fn record_team(session: &mut Session, folder_id: &str, team: Team) -> Result<(), String> {
+    session.teams.insert(folder_id.to_string(), team.cipher(session.index.storage.cipher)?);
+    if let Some(vault) = session.index.vaults.iter_mut().find(|v| v.folder_id == folder_id) {
+        vault.team = Some(team.state);
     }
+    session.save_index()
+}
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted index.json".into());
+// Team vaults reach the other members through the history folder, so they need history.
+#[tauri::command]
+fn create_team_vault(lock: State<'_, AppLock>, new_vault: VaultEntry, member_name: &str) -> Result<String, String> {
+    let mut session = lock.session()?;
+    if session.index.history.is_none() {
+        return Err("Turn on history first, team vaults are shared through its folder".into());
     }
-    let salt_bytes = &root_data[0..SALT_LEN];
+    let identity = store_identity(&mut session)?;
+
+    let folder_id = new_vault_id(&session)?;
+    let dir = session.vault_dir.join(&folder_id);
+    fs::create_dir_all(&dir).map_err(|_| "Failed to create vault folder".to_string())?;
+    let team = Team::create(&dir, &folder_id, &identity, member_name)?;
+    session.teams.insert(folder_id.clone(), team.cipher(session.index.storage.cipher)?);
+    session.index.vaults.push(IdVaultEntry {
+        icon: new_vault.icon,
+        name: new_vault.name,
+        description: new_vault.description,
+        folder_id: folder_id.clone(),
+        team: Some(team.state),
+    });
+    session.save_index()?;
 
-    let cipher = derive_cipher(password, salt_bytes)?;
+    let store = session.vault(&folder_id)?;
+    store.init()?;
+    history::init(store.dir())?;
+    Ok(folder_id)
+}
 
-    let nonce_bytes = &data[0..NONCE_LEN];
-    let ciphertext = &data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+#[tauri::command]
+fn get_team_members(lock: State<'_, AppLock>, vaultfolder: &str) -> Result<Vec<MemberInfo>, String> {
+    let session = lock.session()?;
+    let (identity, team) = open_team(&session, vaultfolder)?;
+    team.member_info(&identity)
+}
 
-    String::from_utf8(decrypted).map_err(|e| e.to_string())
+// Members join under the name, and the key, the user checked when adding them as a contact.
+#[tauri::command]
+fn add_team_member(lock: State<'_, AppLock>, vaultfolder: &str, public_key: &str) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let (identity, mut team) = open_team(&session, vaultfolder)?;
+    let key = identity::decode_public_key(public_key)?;
+    let contact = session
+        .index
+        .contacts
+        .iter()
+        .find(|c| identity::decode_public_key(&c.public_key).ok() == Some(key))
+        .ok_or("Add them as a contact first")?;
+    team.add(&contact.name, key)?;
+    save_team(&mut session, vaultfolder, &identity, team)
 }
 
+// Everything in the vault is sealed again under the new key. What the removed member already
+// pulled stays readable to them, they just cannot follow anything written from now on.
+// The new list and data go into a copy that replaces the vault whole, so a crash leaves either
+// the old vault or the new one, never data under a key no list holds.
 #[tauri::command]
-fn update_note_icon(
-    password: &str,
-    vaultfolder: &str,
-    filename: &str,
-    new_icon: &str,
-) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER).join(vaultfolder);
-    let notes_index_path = vault_dir.join("index.json");
+fn remove_team_member(lock: State<'_, AppLock>, vaultfolder: &str, public_key: &str) -> Result<(), String> {
+    let mut session = lock.session()?;
+    let (identity, mut team) = open_team(&session, vaultfolder)?;
+    team.remove(&identity, &identity::decode_public_key(public_key)?)?;
+
+    let dir = session.vault_dir.join(vaultfolder);
+    let secure = session.index.storage.secure_delete;
+    let tmp_dir = copy_dir(&dir, secure)?;
+    team.save(&tmp_dir, vaultfolder, &identity)?;
+    VaultStore::new(tmp_dir.clone(), session.vault_cipher(vaultfolder)?, session.index.storage)
+        .rekey(&team.cipher(session.index.storage.cipher)?)?;
+
+    let backup_dir = swap_dir(&dir, &tmp_dir)?;
+    record_team(&mut session, vaultfolder, team)?;
+    // The copy sealed under the key the removed member knows.
+    shred::remove_dir_all(&backup_dir, secure)
+}
 
-    let encrypted_data = fs::read(&notes_index_path).map_err(|e| e.to_string())?;
-    if encrypted_data.len() < NONCE_LEN {
-        return Err("Corrupted index.json".into());
-    }
+#[tauri::command]
+fn take_opened_files(files: State<'_, OpenedFiles>) -> Vec<String> {
+    files.take().into_iter().map(|path| path.to_string_lossy().into_owned()).collect()
+}
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted index.json".into());
-    }
-    let salt_bytes = &root_data[0..SALT_LEN];
+#[tauri::command]
+fn get_sync_status(lock: State<'_, AppLock>, monitor: State<'_, SyncMonitor>) -> Result<SyncStatus, String> {
+    let session = lock.session()?;
+    Ok(sync::status(session.index.sync.as_ref(), &monitor))
+}
 
-    let cipher = derive_cipher(password, salt_bytes)?;
+// Pairing with a LAN host happens here too, so it runs off the main thread.
+#[tauri::command(async)]
+fn set_sync_remote(
+    lock: State<'_, AppLock>,
+    host: State<'_, LanHost>,
+    remote: RemoteConfig,
+    password: Zeroizing<String>,
+) -> Result<(), String> {
+    let mut session = lock.unheld_session()?;
+    let config = sync::connect(remote, &password, &sync::cache_dir(&session))?;
+    host.stop();
+    session.update_index(|session| {
+        session.index.sync = Some(config);
+        Ok(())
+    })
+}
 
-    let nonce_bytes = &encrypted_data[0..NONCE_LEN];
-    let ciphertext = &encrypted_data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+#[tauri::command]
+fn disable_sync(lock: State<'_, AppLock>, host: State<'_, LanHost>) -> Result<(), String> {
+    let mut session = lock.session()?;
+    session.index.sync = None;
+    host.stop();
+    session.save_index()
+}
 
-    let mut notes_list: Vec<Value> = if decrypted.is_empty() {
-        vec![]
-    } else {
-        serde_json::from_slice(&decrypted).map_err(|e| format!("Invalid JSON data"))?
+// Serves this device's sync objects to paired devices until stopped. After each peer's sync this
+// device syncs too, so their changes show up here right away.
+#[tauri::command]
+fn start_lan_host(app: tauri::AppHandle, lock: State<'_, AppLock>, host: State<'_, LanHost>) -> Result<(), String> {
+    let session = lock.session()?;
+    let Some(RemoteConfig::LanHost { port, peers }) = session.index.sync.as_ref().map(|c| &c.remote) else {
+        return Err("Set up this device to host LAN sync first".into());
     };
 
-    let mut found = false;
-    for note in notes_list.iter_mut() {
-        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
-            note["icon"] = Value::String(new_icon.to_string());
-            found = true;
-            break;
-        }
-    }
+    let (pairing_app, serving_app) = (app.clone(), app.clone());
+    let events = HostEvents {
+        paired: Box::new(move |peer| {
+            let mut session = pairing_app.state::<AppLock>().session()?;
+            sync::add_lan_peer(session.index.sync.as_mut(), peer)?;
+            session.save_index()
+        }),
+        served: Box::new(move || {
+            let monitor = serving_app.state::<SyncMonitor>();
+            if monitor.start().is_err() {
+                return;
+            }
+            let result = serving_app
+                .state::<AppLock>()
+                .background_session()
+                .and_then(|mut session| sync::run(&mut session));
+            monitor.finish(&result);
+            if result.is_ok() {
+                let _ = serving_app.emit("synced", ());
+            }
+        }),
+    };
+    host.start(*port, peers.clone(), &sync::cache_dir(&session), events)
+}
 
-    if !found {
-        return Err(format!("Note not found"));
-    }
+#[tauri::command]
+fn stop_lan_host(host: State<'_, LanHost>) {
+    host.stop();
+}
 
-    let updated_json = serde_json::to_vec(&notes_list).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_encrypted_index) = encrypt_data(&cipher, &updated_json)?;
+#[tauri::command]
+fn open_lan_pairing(lock: State<'_, AppLock>, host: State<'_, LanHost>) -> Result<String, String> {
+    lock.session()?;
+    host.open_pairing()
+}
 
-    let mut final_output = Vec::new();
-    final_output.extend_from_slice(&new_nonce_bytes);
-    final_output.extend_from_slice(&new_encrypted_index);
-    fs::write(notes_index_path, final_output).map_err(|e| format!("Failed to write index.json"))?;
+#[tauri::command]
+fn get_lan_host_status(lock: State<'_, AppLock>, host: State<'_, LanHost>) -> Result<LanHostStatus, String> {
+    let session = lock.session()?;
+    let configured = matches!(session.index.sync.as_ref().map(|c| &c.remote), Some(RemoteConfig::LanHost { .. }));
+    Ok(host.status(configured))
+}
 
-    Ok(())
+// Runs off the main thread, a first sync can move a lot of data.
+#[tauri::command(async)]
+fn sync_now(
+    app: tauri::AppHandle,
+    lock: State<'_, AppLock>,
+    monitor: State<'_, SyncMonitor>,
+) -> Result<SyncReport, String> {
+    monitor.start()?;
+    let result = lock.unheld_session().and_then(|mut session| sync::run(&mut session));
+    monitor.finish(&result);
+
+    let report = result?;
+    let _ = app.emit("synced", ());
+    Ok(report)
 }
 
 #[tauri::command]
-fn update_note_title(
-    password: String,
-    vaultfolder: &str,
-    filename: &str,
-    new_title: &str,
-) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER).join(vaultfolder);
-    let notes_index_path = vault_dir.join("index.json");
+fn get_history_settings(lock: State<'_, AppLock>) -> Result<HistoryStatus, String> {
+    let session = lock.session()?;
+    Ok(history::status(session.index.history.as_ref()))
+}
 
-    let encrypted_data = fs::read(&notes_index_path).map_err(|e| e.to_string())?;
-    if encrypted_data.len() < NONCE_LEN {
-        return Err("Corrupted index.json".into());
+#[tauri::command]
+fn set_history_enabled(lock: State<'_, AppLock>, enabled: bool) -> Result<(), String> {
+    let mut session = lock.session()?;
+    if enabled && session.index.storage.secure_delete {
+        return Err("Turn off secure delete first, history keeps every earlier version of each note".into());
     }
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < SALT_LEN {
-        return Err("Corrupted index.json".into());
+    for vault in &session.index.vaults {
+        let dir = session.vault_dir.join(&vault.folder_id);
+        if enabled {
+            history::init(&dir)?;
+        } else {
+            history::remove(&dir)?;
+        }
     }
-    let salt_bytes = &root_data[0..SALT_LEN];
-
-    let cipher = derive_cipher(&password, salt_bytes)?;
-    let nonce_bytes = &encrypted_data[0..NONCE_LEN];
-    let ciphertext = &encrypted_data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
-
-    let mut notes_list: Vec<Value> = if decrypted.is_empty() {
-        vec![]
+    session.index.history = if enabled {
+        Some(session.index.history.take().unwrap_or_default())
     } else {
-        serde_json::from_slice(&decrypted).map_err(|e| format!("Invalid JSON data"))?
+        None
     };
+    session.save_index()
+}
 
-    let mut found = false;
-    for note in notes_list.iter_mut() {
-        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
-            note["notetitle"] = Value::String(new_title.to_string());
-            found = true;
-            break;
-        }
-    }
-    if !found {
-        return Err(format!("Note not found."));
+#[tauri::command]
+fn set_history_remote(lock: State<'_, AppLock>, remote: Option<String>) -> Result<(), String> {
+    let mut session = lock.session()?;
+    if let Some(remote) = &remote {
+        history::check_remote(remote)?;
     }
+    let config = session.index.history.as_mut().ok_or("History is turned off")?;
+    config.remote = remote;
+    session.save_index()
+}
 
-    let updated_json = serde_json::to_vec(&notes_list).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_encrypted_index) = encrypt_data(&cipher, &updated_json)?;
-
-    let mut final_output = Vec::new();
-    final_output.extend_from_slice(&new_nonce_bytes);
-    final_output.extend_from_slice(&new_encrypted_index);
-    fs::write(notes_index_path, final_output).map_err(|e| format!("Failed to write index.json"))?;
+fn history_remote(session: &Session) -> Result<String, String> {
+    session
+        .index
+        .history
+        .as_ref()
+        .ok_or("History is turned off")?
+        .remote
+        .clone()
+        .ok_or_else(|| "Choose a history folder first".to_string())
+}
 
+// Both run off the main thread, the first push copies every vault's whole history.
+#[tauri::command(async)]
+fn push_history(lock: State<'_, AppLock>) -> Result<(), String> {
+    let session = lock.unheld_session()?;
+    let remote = history_remote(&session)?;
+    for vault in &session.index.vaults {
+        let Ok(store) = session.vault(&vault.folder_id) else {
+            continue;
+        };
+        // Each member trusts the member list they last saw, only the details are shared.
+        let vault = &IdVaultEntry { team: None, ..vault.clone() };
+        // Sealing again would change the file, and make a commit, on every push.
+        let stored: Option<IdVaultEntry> = store
+            .read(history::VAULT_FILE)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok());
+        if stored.as_ref() != Some(vault) {
+            let json = Zeroizing::new(serde_json::to_vec(vault).map_err(|e| e.to_string())?);
+            let _writing = session.writing();
+            store.write(history::VAULT_FILE, &json)?;
+        }
+        history::push(store.dir(), &remote, &vault.folder_id)?;
+    }
     Ok(())
 }
 
-#[tauri::command]
-fn delete_note(password: String, note_id: String, vault_folder: String) -> Result<(), String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vault_dir = base_dir.join(VAULTS_FOLDER).join(&vault_folder);
-    let notes_index_path = vault_dir.join("index.json");
-
-    let encrypted_data = fs::read(&notes_index_path).map_err(|e| e.to_string())?;
-    if encrypted_data.len() < NONCE_LEN {
-        return Err("Corrupted index.json".into());
+#[tauri::command(async)]
+fn pull_history(app: tauri::AppHandle, lock: State<'_, AppLock>) -> Result<usize, String> {
+    let mut session = lock.unheld_session()?;
+    let remote = history_remote(&session)?;
+    let mut updated = 0;
+    for vault in &session.index.vaults {
+        let dir = session.vault_dir.join(&vault.folder_id);
+        if !history::fetch(&dir, &remote, &vault.folder_id)? {
+            continue;
+        }
+        let _writing = session.writing();
+        if history::fast_forward(&dir)? {
+            updated += 1;
+        }
     }
+    session.open_teams()?;
 
-    let root_index_path = base_dir.join(VAULTS_FOLDER).join("index.json");
-    let root_data = fs::read(&root_index_path).map_err(|e| e.to_string())?;
-    if root_data.len() < MIN_DATA_LEN {
-        return Err("Corrupted index.json".into());
+    let removed = session.index.history.as_ref().map(|h| h.removed.clone()).unwrap_or_default();
+    for folder_id in history::remote_vaults(&remote)? {
+        let dir = session.vault_dir.join(&folder_id);
+        if removed.contains(&folder_id) || session.index.vaults.iter().any(|v| v.folder_id == folder_id) || dir.exists() {
+            continue;
+        }
+        history::clone(&remote, &folder_id, &dir)?;
+        match cloned_vault(&mut session, &folder_id) {
+            Some(entry) => session.update_index(|session| {
+                session.index.vaults.push(entry);
+                Ok(())
+            })?,
+            None => {
+                let _ = fs::remove_dir_all(&dir);
+                continue;
+            }
+        }
+        updated += 1;
     }
-    let salt_bytes = &root_data[0..SALT_LEN];
 
-    let cipher = derive_cipher(&password, salt_bytes)?;
-    let nonce_bytes = &encrypted_data[0..NONCE_LEN];
-    let ciphertext = &encrypted_data[NONCE_LEN..];
-    let decrypted = decrypt_data(&cipher, nonce_bytes, ciphertext)?;
+    if updated > 0 {
+        let _ = app.emit("synced", ());
+    }
+    Ok(updated)
+}
 
-    let mut notes_list: Vec<Value> =
-        serde_json::from_slice(&decrypted).map_err(|e| format!("Invalid index.json"))?;
+// A history folder shared with a team also holds the members' own vaults. This store keeps the
+// ones it pushed itself and the team vaults whose member list names it.
+fn cloned_vault(session: &mut Session, folder_id: &str) -> Option<IdVaultEntry> {
+    let dir = session.vault_dir.join(folder_id);
+    let team = match &session.index.identity {
+        Some(identity) if dir.join(team::MEMBERS_FILE).exists() => {
+            Some(Team::open(&dir, folder_id, identity, None, &session.index.contacts).ok()?)
+        }
+        _ => None,
+    };
+    let cipher = match &team {
+        Some(team) => Some(team.cipher(session.index.storage.cipher).ok()?),
+        None => None,
+    };
 
-    let initial_len = notes_list.len();
-    notes_list.retain(|entry| entry["filename"] != note_id);
-    if notes_list.len() == initial_len {
-        return Err("Note not found in index.json".into());
+    let store = VaultStore::new(dir, cipher.as_ref().unwrap_or(&session.cipher), session.index.storage);
+    let data = store.read(history::VAULT_FILE).ok()?;
+    let entry: IdVaultEntry = serde_json::from_slice(&data).ok()?;
+    if let Some(cipher) = cipher {
+        session.teams.insert(folder_id.to_string(), cipher);
     }
+    Some(IdVaultEntry {
+        folder_id: folder_id.to_string(),
+        team: team.map(|team| team.state),
+        ..entry
+    })
+}
 
-    let updated_json = serde_json::to_vec(&notes_list).map_err(|e| e.to_string())?;
-    let (new_nonce_bytes, new_encrypted_index) = encrypt_data(&cipher, &updated_json)?;
+#[tauri::command]
+fn get_note_history(lock: State<'_, AppLock>, vaultfolder: &str, filename: &str) -> Result<Vec<NoteVersion>, String> {
+    let session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    history::versions(session.vault(vaultfolder)?.dir(), filename)
+}
 
-    let mut final_output = Vec::new();
-    final_output.extend_from_slice(&new_nonce_bytes);
-    final_output.extend_from_slice(&new_encrypted_index);
-    fs::write(&notes_index_path, final_output).map_err(|e| format!("Error writing index.json"))?;
+// Brings back a note's content as it was in an earlier commit, or the whole note if it has been
+// deleted since. The restored content is saved as a new version.
+#[tauri::command]
+fn restore_note_version(lock: State<'_, AppLock>, vaultfolder: &str, filename: &str, commit: &str) -> Result<(), String> {
+    let session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    let store = session.vault(vaultfolder)?;
+
+    let mut old = history::read_version(store.dir(), session.vault_cipher(vaultfolder)?, commit, &[INDEX_FILE, filename])?;
+    let old_data = old.pop().ok_or("Version not found")?;
+    let old_list: Vec<Value> = serde_json::from_slice(&old.pop().ok_or("Version not found")?)
+        .map_err(|_| "Invalid JSON data".to_string())?;
+    let old_entry = find_note(&old_list, filename)?;
+    let content = open_note_data(old_entry, old_data)?;
+
+    let mut notes_list = read_notes_list(&store)?;
+    if find_note(&notes_list, filename).is_err() {
+        notes_list.push(old_entry.clone());
+    }
+    for note in notes_list.iter_mut() {
+        if note.get("filename").and_then(|f| f.as_str()) == Some(filename) {
+            save_document(&store, note, filename, &content)?;
+            note["modified"] = json!(Utc::now().timestamp());
+            break;
+        }
+    }
+    write_notes_list(&store, &notes_list)
+}
 
-    let note_path = vault_dir.join(&note_id);
-    if note_path.exists() {
-        fs::remove_file(&note_path).map_err(|e| format!("Error deleting note"))?;
-    } else {
-        return Err("Note not found".into());
+fn check_vault(session: &Session, folder_id: &str) -> Result<(), String> {
+    if !session.index.vaults.iter().any(|v| v.folder_id == folder_id) {
+        return Err("Vault not found in index".into());
     }
+    session.vault_cipher(folder_id).map(|_| ())
+}
 
-    Ok(())
+// The body is the raw file, the vault, name and type travel percent-encoded in headers.
+#[tauri::command]
+fn upload_attachment(lock: State<'_, AppLock>, request: tauri::ipc::Request<'_>) -> Result<AttachmentInfo, String> {
+    let tauri::ipc::InvokeBody::Raw(data) = request.body() else {
+        return Err("Expected raw attachment data".into());
+    };
+    let header = |name: &str| -> Result<String, String> {
+        let value = request
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| format!("Missing {} header", name))?;
+        attachments::percent_decode(value)
+    };
+    let vaultfolder = header("x-vault")?;
+    let name = header("x-name")?;
+    let mime = header("x-mime")?;
+
+    let mut session = lock.session()?;
+    check_vault(&session, &vaultfolder)?;
+    let attachment_key = attachment_key(&mut session)?;
+    attachments::store(&session.vault(&vaultfolder)?, &attachment_key, &vaultfolder, &name, &mime, data)
+}
+
+#[tauri::command]
+fn get_attachment(lock: State<'_, AppLock>, vaultfolder: &str, id: &str) -> Result<tauri::ipc::Response, String> {
+    let session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    let (_, data) = attachments::load(&session.vault(vaultfolder)?, id)?;
+    Ok(tauri::ipc::Response::new(data.to_vec()))
+}
+
+#[tauri::command]
+fn delete_attachment(lock: State<'_, AppLock>, vaultfolder: &str, id: &str) -> Result<(), String> {
+    let session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    attachments::remove(&session.vault(vaultfolder)?, id)
 }
 
 #[tauri::command]
-fn get_note_edit_date(vaultfolder: &str, filename: &str) -> Result<String, String> {
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let file_path: PathBuf = base_dir
-        .join(VAULTS_FOLDER)
-        .join(vaultfolder)
-        .join(filename);
+fn collect_attachments(lock: State<'_, AppLock>, vaultfolder: &str) -> Result<usize, String> {
+    let session = lock.session()?;
+    check_vault(&session, vaultfolder)?;
+    attachments::collect_garbage(&session.vault(vaultfolder)?)
+}
 
-    let metadata = fs::metadata(&file_path).map_err(|e| format!("Error getting metadata"))?;
-    let modified_time = metadata
-        .modified()
-        .map_err(|e| format!("Error getting date"))?;
+// Serves decrypted attachments to the webview as <protocol>://localhost/<vault>/<id>, only while
+// the store is unlocked.
+fn attachment_response(app: &tauri::AppHandle, path: &str) -> tauri::http::Response<Vec<u8>> {
+    use tauri::http::{header, Response, StatusCode};
+
+    let result = (|| {
+        let path = attachments::percent_decode(path.trim_start_matches('/'))?;
+        let (vaultfolder, id) = path.split_once('/').ok_or("Invalid attachment path")?;
+        let session = app.state::<AppLock>().session()?;
+        check_vault(&session, vaultfolder)?;
+        attachments::load(&session.vault(vaultfolder)?, id)
+    })();
+
+    match result {
+        Ok((entry, data)) => Response::builder()
+            .header(header::CONTENT_TYPE, entry.served_mime())
+            .header(header::CACHE_CONTROL, "no-store")
+            .header("X-Content-Type-Options", "nosniff")
+            .body(data.to_vec()),
+        Err(e) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(e.into_bytes()),
+    }
+    .unwrap_or_default()
+}
 
-    let duration = modified_time
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| e.to_string())?;
-    let datetime: DateTime<Utc> = Utc.timestamp_opt(duration.as_secs() as i64, 0).unwrap();
+#[tauri::command]
+fn get_note_edit_date(lock: State<'_, AppLock>, vaultfolder: &str, filename: &str) -> Result<String, String> {
+    let session = lock.session()?;
+    let store = session.vault(vaultfolder)?;
+
+    let modified = read_notes_list(&store)?
+        .iter()
+        .find(|note| note.get("filename").and_then(|f| f.as_str()) == Some(filename))
+        .and_then(|note| note.get("modified").and_then(|m| m.as_i64()));
+
+    let secs = match modified {
+        Some(secs) => secs,
+        None => {
+            let metadata = fs::metadata(store.dir().join(filename))
+                .map_err(|_| "Error getting metadata".to_string())?;
+            let modified_time = metadata
+                .modified()
+                .map_err(|_| "Error getting date".to_string())?;
+
+            let duration = modified_time
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| e.to_string())?;
+            duration.as_secs() as i64
+        }
+    };
+    // The list may come from another device through sync, so its value is not trusted to be a date.
+    let datetime: DateTime<Utc> = Utc.timestamp_opt(secs, 0).single().ok_or("Invalid date")?;
     let datetime_local = datetime.with_timezone(&Local);
 
     let now = Local::now();
@@ -673,156 +1936,203 @@ fn get_note_edit_date(vaultfolder: &str, filename: &str) -> Result<String, Strin
 
 #[tauri::command]
 fn reencrypt_data(
-    old_password: &str,
-    new_password: &str,
-    new_hint: &str,
+    lock: State<'_, AppLock>,
+    old_password: Zeroizing<String>,
+    old_keyfile: Option<&str>,
+    new_password: Zeroizing<String>,
+    new_keyfile: Option<&str>,
+    new_hint: HintInput,
+    cipher: Option<CipherSuite>,
 ) -> Result<(), String> {
-    use std::fs::{self, rename};
+    use std::fs;
     use std::path::Path;
 
-    let base_dir = config_dir().ok_or("No config directory found")?;
-    let vaults_dir = base_dir.join(VAULTS_FOLDER);
-    let tmp_vaults_dir = base_dir.join(format!("{}.tmp", VAULTS_FOLDER));
-    let public_path = vaults_dir.join("public.json");
+    // Only reachable from an unlocked session, so a second factor cannot be sidestepped here. It
+    // holds the store lock until the new copy is in place, so nothing written meanwhile is lost.
+    let session = lock.session()?;
+    let session_key = &session.key;
+    let slot = session.slot();
+    let shows_duress = session.shown_duress().is_some();
 
-    if tmp_vaults_dir.exists() {
-        fs::remove_dir_all(&tmp_vaults_dir).map_err(|e| e.to_string())?;
+    let base_dir = base_dir()?;
+    let vaults_dir = base_dir.join(VAULTS_FOLDER);
+    let mut metadata = PublicMetadata::load(&vaults_dir)?;
+    let old_kdf = metadata.kdf;
+    if metadata.keyfile && old_keyfile.is_none() {
+        return Err("The current keyfile is required to change the password".into());
+    }
+    let old_secret = keyfile::secret(&old_password, old_keyfile.filter(|_| metadata.keyfile))?;
+    // Checked against the unlocked session rather than as an unlock attempt, so a typo here never
+    // counts toward wiping the store.
+    if derive_key(&old_secret, &session_key.salt, &old_kdf)? != session_key.key {
+        return Err("The current password is incorrect".into());
     }
+    let new_secret = keyfile::secret(&new_password, new_keyfile)?;
 
-    fs_extra::dir::copy(&vaults_dir, &tmp_vaults_dir, &fs_extra::dir::CopyOptions::new().content_only(true))
-        .map_err(|e| format!("Failed to copy vault dir to tmp: {}", e))?;
+    let tmp_vaults_dir = copy_dir(&vaults_dir, session.index.storage.secure_delete)?;
 
-    let tmp_index_path = tmp_vaults_dir.join("index.json");
+    let tmp_index_path = tmp_vaults_dir.join(session_key.file);
 
     let data = fs::read(&tmp_index_path).map_err(|e| format!("Failed to read tmp index.json: {}", e))?;
     if data.len() < SALT_LEN + NONCE_LEN + 1 {
         return Err("Corrupted global index.json (too small)".into());
     }
-    let old_salt = &data[0..SALT_LEN];
-    let old_nonce = &data[SALT_LEN..SALT_LEN + NONCE_LEN];
-    let old_ciphertext = &data[SALT_LEN + NONCE_LEN..];
+    if data[0..SALT_LEN] != session_key.salt[..] {
+        return Err("Vault key no longer matches the store".into());
+    }
+
+    let old_cipher = cipher_from_key(&session_key.key[..], CipherSuite::default())?;
+    let mut root_index = parse_root_index(&storage::open(&old_cipher, &data[SALT_LEN..])?)?;
 
-    let old_cipher = derive_cipher(old_password, old_salt)?;
-    let decrypted_data = decrypt_data(&old_cipher, old_nonce, old_ciphertext)
-        .map_err(|e| format!("Failed decrypting global index with old password: {}", e))?;
-    let vaults: Vec<IdVaultEntry> =
-        serde_json::from_slice(&decrypted_data).map_err(|_| "Invalid global vault JSON".to_string())?;
+    let strength = strength::evaluate(&new_password, new_hint.text(), &root_index.password_policy);
+    if !strength.meets_policy {
+        return Err(strength.feedback.join(". "));
+    }
 
     let mut new_salt = [0u8; SALT_LEN];
     OsRng.fill_bytes(&mut new_salt);
     let new_salt_slice = &new_salt[..];
 
-    let new_cipher = derive_cipher(new_password, new_salt_slice)?;
+    // Both slots share the public metadata, so its KDF and keyfile settings stay put while the
+    // other slot may hold a decoy that was derived with them.
+    let shares_metadata = slot == Slot::Alternate || root_index.duress.is_some();
+    if shares_metadata && new_keyfile.is_some() != metadata.keyfile {
+        return Err(match shows_duress {
+            true => "Remove the duress password before adding or removing a keyfile",
+            false => "The keyfile cannot be added or removed right now",
+        }
+        .into());
+    }
+    // The slot files carry their suite in the header, so both slots have to switch together.
+    let new_suite = cipher.unwrap_or(root_index.storage.cipher);
+    let changes_cipher = new_suite != root_index.storage.cipher;
+    if shares_metadata && changes_cipher {
+        return Err(match shows_duress {
+            true => "Remove the duress password before changing the cipher",
+            false => "The cipher cannot be changed right now",
+        }
+        .into());
+    }
+    root_index.storage.cipher = new_suite;
+    if slot == Slot::Primary {
+        // Changing the password also moves older stores onto the current format and KDF parameters.
+        metadata.format_version = FORMAT_VERSION;
+        if !shares_metadata {
+            metadata.kdf = KdfParams::default();
+        }
+        metadata.set_hint(&new_hint)?;
+        metadata.keyfile = new_keyfile.is_some();
+    }
+
+    let new_key = derive_key(&new_secret, new_salt_slice, &metadata.kdf)?;
+    let new_cipher = cipher_from_key(&new_key[..], new_suite)?;
 
-    let process_file = |path: &Path, nonce_offset: usize| -> Result<(), String> {
+    let process_file = |path: &Path| -> Result<(), String> {
         let file_data = fs::read(path).map_err(|e| format!("Failed to read file {}: {}", path.display(), e))?;
-        if file_data.len() <= nonce_offset {
+        if file_data.len() <= NONCE_LEN {
             return Ok(());
         }
-        let nonce_bytes = &file_data[0..nonce_offset];
-        let ciphertext = &file_data[nonce_offset..];
 
-        let decrypted = decrypt_data(&old_cipher, nonce_bytes, ciphertext)
+        let decrypted = old_cipher
+            .open(&file_data)
             .map_err(|e| format!("Decrypt failed for {}: {}", path.display(), e))?;
 
-        let (new_nonce, new_ciphertext) = encrypt_data(&new_cipher, &decrypted)?;
-        let mut new_file_data = Vec::with_capacity(new_nonce.len() + new_ciphertext.len());
-        new_file_data.extend_from_slice(&new_nonce);
-        new_file_data.extend_from_slice(&new_ciphertext);
-
-        atomic_write(path, &new_file_data)
+        atomic_write(path, &new_cipher.seal(&decrypted)?)
     };
 
-    let plaintext = serde_json::to_vec_pretty(&vaults).map_err(|e| e.to_string())?;
-    let (index_nonce, index_ciphertext) = encrypt_data(&new_cipher, &plaintext)?;
-    let mut new_index_bytes = Vec::with_capacity(SALT_LEN + index_nonce.len() + index_ciphertext.len());
+    let plaintext = Zeroizing::new(serde_json::to_vec(&root_index).map_err(|e| e.to_string())?);
+    let sealed_index = storage::seal(&new_cipher, &plaintext, root_index.storage)?;
+    let mut new_index_bytes = Vec::with_capacity(SALT_LEN + sealed_index.len());
     new_index_bytes.extend_from_slice(new_salt_slice);
-    new_index_bytes.extend_from_slice(&index_nonce);
-    new_index_bytes.extend_from_slice(&index_ciphertext);
+    new_index_bytes.extend_from_slice(&sealed_index);
 
     atomic_write(&tmp_index_path, &new_index_bytes)?;
 
-    for vault in &vaults {
+    // Team vaults have a key of their own, the password never reached their data.
+    let own_vaults = || root_index.vaults.iter().filter(|v| v.team.is_none());
+    for vault in own_vaults() {
         let vault_path = tmp_vaults_dir.join(&vault.folder_id);
         let vault_index_path = vault_path.join("index.json");
 
         if vault_index_path.exists() {
-            process_file(&vault_index_path, NONCE_LEN)?;
+            process_file(&vault_index_path)?;
         }
 
         for entry in fs::read_dir(&vault_path).map_err(|e| format!("Failed to read vault folder {}: {}", vault_path.display(), e))? {
             let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
             let path = entry.path();
+            let file_name = path.file_name().and_then(OsStr::to_str);
             if path.is_file()
-                && path.extension().and_then(OsStr::to_str).map(|ext| ext == "json").unwrap_or(false)
-                && path.file_name().and_then(OsStr::to_str) != Some("index.json")
+                && (file_name == Some(PACK_FILE)
+                    || path.extension().and_then(OsStr::to_str).map(|ext| ext == "json").unwrap_or(false)
+                        && file_name != Some("index.json"))
             {
-                process_file(&path, NONCE_LEN)?;
+                process_file(&path)?;
             }
         }
     }
 
-    let tmp_public_path = tmp_vaults_dir.join("public.json");
-    if tmp_public_path.exists() {
-        let mut public_data: serde_json::Value =
-            serde_json::from_slice(&fs::read(&tmp_public_path).map_err(|e| format!("Failed to read tmp public.json: {}", e))?)
-                .map_err(|e| format!("Failed to parse tmp public.json: {}", e))?;
-        public_data["hint"] = serde_json::Value::String(new_hint.to_string());
-        let new_public_json = serde_json::to_vec_pretty(&public_data).map_err(|e| format!("Failed to serialize public.json: {}", e))?;
-        atomic_write(&tmp_public_path, &new_public_json)?;
-    }
-
-    let backup_dir = base_dir.join(format!("{}.bak", VAULTS_FOLDER));
-    if backup_dir.exists() {
-        fs::remove_dir_all(&backup_dir).map_err(|e| format!("Failed to remove existing backup: {}", e))?;
+    // Note and attachment keys stay the same, only the suite their data is sealed with changes.
+    if changes_cipher {
+        for vault in own_vaults() {
+            let store = VaultStore::new(tmp_vaults_dir.join(&vault.folder_id), &new_cipher, root_index.storage);
+            for note in read_notes_list(&store)? {
+                if let Some(filename) = note.get("filename").and_then(|f| f.as_str()) {
+                    reseal_note(&store, &note, filename)?;
+                }
+            }
+            attachments::reseal(&store)?;
+        }
+        duress::write_filler(&tmp_vaults_dir, duress::other_file(session_key.file), root_index.storage)?;
     }
 
-    rename(&vaults_dir, &backup_dir).map_err(|e| format!("Failed to rename vaults to backup: {}", e))?;
+    let new_public_json = serde_json::to_vec_pretty(&metadata).map_err(|e| format!("Failed to serialize public.json: {}", e))?;
+    atomic_write(&tmp_vaults_dir.join(PUBLIC_FILE), &new_public_json)?;
 
-    if let Err(e) = rename(&tmp_vaults_dir, &vaults_dir) {
-        let _ = rename(&backup_dir, &vaults_dir);
-        return Err(format!("Failed to move tmp into place: {}. Reverted.", e));
+    if root_index.storage.hardened {
+        storage::normalize_tree(&tmp_vaults_dir)?;
     }
 
-    fs::remove_dir_all(&backup_dir).map_err(|e| format!("Failed to remove backup dir: {}", e))?;
-
-    Ok(())
+    let backup_dir = swap_dir(&vaults_dir, &tmp_vaults_dir)?;
+    lock.unlock(
+        UnlockedKey {
+            file: session_key.file,
+            salt: new_salt.to_vec(),
+            key: new_key,
+        },
+        root_index.lock,
+    );
+
+    // The backup still holds everything encrypted under the old password.
+    let removed = shred::remove_dir_all(&backup_dir, root_index.storage.secure_delete)
+        .map_err(|e| format!("Failed to remove backup dir: {}", e));
+    drop(session);
+    removed
 }
 
 
 
-fn derive_cipher(password: &str, salt_bytes: &[u8]) -> Result<Aes256Gcm, String> {
-    let mut key_bytes = [0u8; 32];
-    make_argon2()?
-        .hash_password_into(password.as_bytes(), salt_bytes, &mut key_bytes)
+fn derive_key(secret: &[u8], salt_bytes: &[u8], kdf: &KdfParams) -> Result<SecretKey, String> {
+    let mut key_bytes = SecretKey::zeroed();
+    kdf.argon2()?
+        .hash_password_into(secret, salt_bytes, key_bytes.as_mut())
         .map_err(|e| e.to_string())?;
-    Ok(Aes256Gcm::new_from_slice(&key_bytes).map_err(|e| e.to_string())?)
-}
-
-fn decrypt_data(
-    cipher: &Aes256Gcm,
-    nonce_bytes: &[u8],
-    ciphertext: &[u8],
-) -> Result<Vec<u8>, String> {
-    let nonce = Nonce::from_slice(nonce_bytes);
-    cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|_| "Decrypt failed".to_string())
-}
-
-fn encrypt_data(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
-    let mut nonce_bytes = [0u8; NONCE_LEN];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext)
-        .map_err(|e| e.to_string())?;
-    Ok((nonce_bytes.to_vec(), ciphertext))
+    Ok(key_bytes)
+}
+
+fn cipher_from_key(key_bytes: &[u8], suite: CipherSuite) -> Result<Cipher, String> {
+    Cipher::new(key_bytes, suite)
 }
 
+// index.json and index.alt share a stem, so helper files keep the whole name.
+pub(crate) fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
 
 fn atomic_write(path: &Path, data: &[u8]) -> Result<(), String> {
-    let tmp_path = path.with_extension("tmp");
+    let tmp_path = sibling_path(path, ".tmp");
 
     let mut f = File::create(&tmp_path).map_err(|e| e.to_string())?;
     f.write_all(data).map_err(|e| e.to_string())?;
@@ -832,21 +2142,108 @@ fn atomic_write(path: &Path, data: &[u8]) -> Result<(), String> {
     Ok(())
 }
 
+// macOS hands files opened with the app to the running instance instead of its arguments.
+#[cfg_attr(not(any(target_os = "macos", target_os = "ios")), allow(unused_variables))]
+fn handle_run_event(app: &tauri::AppHandle, event: tauri::RunEvent) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let tauri::RunEvent::Opened { urls } = event {
+        let files = app.state::<OpenedFiles>();
+        let mut opened = false;
+        for url in urls {
+            if let Ok(path) = url.to_file_path() {
+                opened |= files.push(path);
+            }
+        }
+        if opened {
+            let _ = app.emit(share::OPENED_EVENT, ());
+        }
+    }
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let opened_files = OpenedFiles::default();
+    for arg in std::env::args_os().skip(1) {
+        opened_files.push(PathBuf::from(arg));
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(AppLock::new())
+        .manage(SyncMonitor::default())
+        .manage(LanHost::default())
+        .manage(opened_files)
+        .setup(|app| {
+            os_lock::watch(app.handle().clone());
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let mut since_commit = std::time::Duration::ZERO;
+                loop {
+                    std::thread::sleep(lock::TICK_INTERVAL);
+                    if handle.state::<AppLock>().tick() {
+                        let _ = handle.emit("locked", ());
+                    }
+
+                    since_commit += lock::TICK_INTERVAL;
+                    if since_commit >= history::COMMIT_INTERVAL {
+                        since_commit = std::time::Duration::ZERO;
+                        if let Ok(base_dir) = base_dir() {
+                            // Not while a command swaps in a converted copy of the store.
+                            let _writing = handle.state::<AppLock>().store_lock().acquire();
+                            history::commit_pending(&base_dir.join(VAULTS_FOLDER));
+                        }
+                    }
+                }
+            });
+            Ok(())
+        })
+        .register_uri_scheme_protocol(attachments::PROTOCOL, |ctx, request| {
+            attachment_response(ctx.app_handle(), request.uri().path())
+        })
+        .on_window_event(|window, event| {
+            let app_lock = window.state::<AppLock>();
+            let settings = app_lock.settings();
+            let should_lock = match event {
+                WindowEvent::Focused(false) => settings.lock_on_blur,
+                WindowEvent::Resized(_) => {
+                    settings.lock_on_minimize && window.is_minimized().unwrap_or(false)
+                }
+                _ => false,
+            };
+            if should_lock && app_lock.lock() {
+                let _ = window.emit("locked", ());
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             read_public,
+            reveal_hint,
             set_password,
+            unlock,
+            lock_vaults,
+            evaluate_password,
+            get_password_policy,
+            set_password_policy,
+            get_wipe_policy,
+            set_wipe_policy,
+            get_totp_status,
+            begin_totp_enrollment,
+            confirm_totp_enrollment,
+            disable_totp,
+            regenerate_backup_codes,
+            get_duress_status,
+            set_duress_password,
+            remove_duress_password,
+            report_activity,
+            get_lock_settings,
+            set_lock_settings,
             get_vaults,
             create_vault,
             update_vault,
             delete_vault,
-            get_vault_notes_number,
+            get_vault_stats,
             create_note,
             get_notes_index,
             save_note_data,
@@ -854,9 +2251,48 @@ pub fn run() {
             update_note_icon,
             update_note_title,
             delete_note,
+            upload_attachment,
+            get_attachment,
+            delete_attachment,
+            collect_attachments,
+            get_sync_status,
+            set_sync_remote,
+            disable_sync,
+            sync_now,
+            start_lan_host,
+            stop_lan_host,
+            open_lan_pairing,
+            get_lan_host_status,
+            get_history_settings,
+            set_history_enabled,
+            set_history_remote,
+            push_history,
+            pull_history,
+            get_note_history,
+            restore_note_version,
             get_note_edit_date,
-            reencrypt_data
+            share_note,
+            share_note_with_contacts,
+            share_vault_with_contacts,
+            describe_shared_file,
+            import_shared_note,
+            import_shared_vault,
+            get_identity,
+            export_identity,
+            read_contact_card,
+            get_contacts,
+            add_contact,
+            remove_contact,
+            create_team_vault,
+            get_team_members,
+            add_team_member,
+            remove_team_member,
+            take_opened_files,
+            reencrypt_data,
+            get_storage_settings,
+            set_storage_settings
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
 }
\ No newline at end of file
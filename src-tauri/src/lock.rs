@@ -0,0 +1,194 @@
+use crate::secret::SecretKey;
+use crate::Session;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+pub(crate) const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+// The monotonic clock stops while the machine sleeps and the wall clock does not, so a
+// gap between both across one tick means the system was suspended in between.
+const SUSPEND_GAP: Duration = Duration::from_secs(30);
+
+fn default_idle_timeout() -> u64 {
+    10 * 60
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub(crate) struct LockSettings {
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout_secs: u64,
+    #[serde(default)]
+    pub lock_on_blur: bool,
+    #[serde(default = "enabled")]
+    pub lock_on_minimize: bool,
+    // Also covers the OS locking the screen.
+    #[serde(default = "enabled")]
+    pub lock_on_suspend: bool,
+}
+
+impl Default for LockSettings {
+    fn default() -> Self {
+        LockSettings {
+            idle_timeout_secs: default_idle_timeout(),
+            lock_on_blur: false,
+            lock_on_minimize: true,
+            lock_on_suspend: true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct UnlockedKey {
+    // The index file this key opens, either slot may be in either one.
+    pub file: &'static str,
+    pub salt: Vec<u8>,
+    pub key: SecretKey,
+}
+
+// Serializes changes to the index files. Commands hold it for as long as their session lives,
+// work that runs off the main thread only around each change, so it never waits on the network.
+#[derive(Clone, Default)]
+pub(crate) struct StoreLock(Arc<(Mutex<bool>, Condvar)>);
+
+pub(crate) struct StoreGuard(StoreLock);
+
+impl StoreLock {
+    pub fn acquire(&self) -> StoreGuard {
+        let (busy, free) = &*self.0;
+        let mut busy = busy.lock().unwrap_or_else(|e| e.into_inner());
+        while *busy {
+            busy = free.wait(busy).unwrap_or_else(|e| e.into_inner());
+        }
+        *busy = true;
+        StoreGuard(self.clone())
+    }
+}
+
+impl Drop for StoreGuard {
+    fn drop(&mut self) {
+        let (busy, free) = &*(self.0).0;
+        *busy.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        free.notify_one();
+    }
+}
+
+struct LockInner {
+    key: Option<UnlockedKey>,
+    settings: LockSettings,
+    last_activity: Instant,
+    last_tick: (Instant, SystemTime),
+}
+
+pub(crate) struct AppLock {
+    inner: Mutex<LockInner>,
+    writes: StoreLock,
+}
+
+impl AppLock {
+    pub fn new() -> Self {
+        AppLock {
+            inner: Mutex::new(LockInner {
+                key: None,
+                settings: LockSettings::default(),
+                last_activity: Instant::now(),
+                last_tick: (Instant::now(), SystemTime::now()),
+            }),
+            writes: StoreLock::default(),
+        }
+    }
+
+    fn inner(&self) -> MutexGuard<'_, LockInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn unlock(&self, key: UnlockedKey, settings: LockSettings) {
+        let mut inner = self.inner();
+        inner.key = Some(key);
+        inner.settings = settings;
+        inner.last_activity = Instant::now();
+    }
+
+    // Returns whether the store was unlocked, so callers only announce real transitions.
+    pub fn lock(&self) -> bool {
+        self.inner().key.take().is_some()
+    }
+
+    pub fn store_lock(&self) -> StoreLock {
+        self.writes.clone()
+    }
+
+    pub fn session(&self) -> Result<Session, String> {
+        let key = {
+            let mut inner = self.inner();
+            inner.last_activity = Instant::now();
+            inner.key.clone().ok_or("Vaults are locked")?
+        };
+        Session::from_key(key, self.store_lock(), true)
+    }
+
+    // For commands that run off the main thread long enough that holding the store lock would
+    // stall every other one. They change the store through `Session::writing` instead.
+    pub fn unheld_session(&self) -> Result<Session, String> {
+        let key = {
+            let mut inner = self.inner();
+            inner.last_activity = Instant::now();
+            inner.key.clone().ok_or("Vaults are locked")?
+        };
+        Session::from_key(key, self.store_lock(), false)
+    }
+
+    // For work that runs without the user, which must not keep the store from locking. It holds
+    // no store lock either.
+    pub fn background_session(&self) -> Result<Session, String> {
+        let key = self.inner().key.clone().ok_or("Vaults are locked")?;
+        Session::from_key(key, self.store_lock(), false)
+    }
+
+    pub fn touch(&self) {
+        self.inner().last_activity = Instant::now();
+    }
+
+    pub fn settings(&self) -> LockSettings {
+        self.inner().settings
+    }
+
+    pub fn set_settings(&self, settings: LockSettings) {
+        self.inner().settings = settings;
+    }
+
+    // The OS locked the screen or is about to sleep, returns true when that locked the store.
+    pub fn system_locked(&self) -> bool {
+        let mut inner = self.inner();
+        inner.settings.lock_on_suspend && inner.key.take().is_some()
+    }
+
+    // Called periodically, returns true when this tick locked the store.
+    pub fn tick(&self) -> bool {
+        let mut inner = self.inner();
+
+        let (last_instant, last_wall) = inner.last_tick;
+        let monotonic = last_instant.elapsed();
+        let wall = SystemTime::now()
+            .duration_since(last_wall)
+            .unwrap_or_default();
+        inner.last_tick = (Instant::now(), SystemTime::now());
+
+        if inner.key.is_none() {
+            return false;
+        }
+
+        let suspended = inner.settings.lock_on_suspend && wall > monotonic + SUSPEND_GAP;
+        let idle = inner.settings.idle_timeout_secs > 0
+            && inner.last_activity.elapsed() >= Duration::from_secs(inner.settings.idle_timeout_secs);
+
+        if suspended || idle {
+            inner.key = None;
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,240 @@
+use crate::cipher::CipherSuite;
+use crate::{cipher_from_key, SALT_LEN};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use crate::secret::SecretKey;
+use zeroize::Zeroizing;
+
+pub(crate) const PUBLIC_FILE: &str = "public.json";
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+const DEFAULT_STORE_NAME: &str = "Noetiq";
+
+// Upper bounds for parameters read from the unauthenticated metadata file.
+const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub(crate) struct KdfParams {
+    pub algorithm: KdfAlgorithm,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum KdfAlgorithm {
+    Argon2id,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: crate::ARGON2_MEMORY_KIB,
+            iterations: crate::ARGON2_ITERATIONS,
+            parallelism: crate::ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl KdfParams {
+    pub fn argon2(&self) -> Result<Argon2<'static>, String> {
+        if self.memory_kib > MAX_MEMORY_KIB
+            || self.iterations > MAX_ITERATIONS
+            || self.parallelism > MAX_PARALLELISM
+        {
+            return Err("Unsupported key derivation parameters".into());
+        }
+
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| e.to_string())?;
+        Ok(match self.algorithm {
+            KdfAlgorithm::Argon2id => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub(crate) enum StoredHint {
+    #[default]
+    None,
+    Plain {
+        hint: String,
+    },
+    Encrypted {
+        question: String,
+        salt: String,
+        data: String,
+    },
+}
+
+// Hint settings as sent by the frontend when the password is set or changed.
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub(crate) enum HintInput {
+    None,
+    Plain { hint: String },
+    Encrypted { hint: String, question: String, answer: Zeroizing<String> },
+}
+
+impl HintInput {
+    pub fn text(&self) -> &str {
+        match self {
+            HintInput::None => "",
+            HintInput::Plain { hint } | HintInput::Encrypted { hint, .. } => hint,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct PublicMetadata {
+    pub format_version: u32,
+    #[serde(default)]
+    pub store_name: String,
+    #[serde(default)]
+    pub kdf: KdfParams,
+    #[serde(default)]
+    pub hint: StoredHint,
+    #[serde(default)]
+    pub keyfile: bool,
+    // Only tells the login screen to ask for a code, the encrypted index decides whether one is needed.
+    #[serde(default)]
+    pub totp: bool,
+}
+
+impl Default for PublicMetadata {
+    fn default() -> Self {
+        PublicMetadata {
+            format_version: FORMAT_VERSION,
+            store_name: DEFAULT_STORE_NAME.to_string(),
+            kdf: KdfParams::default(),
+            hint: StoredHint::None,
+            keyfile: false,
+            totp: false,
+        }
+    }
+}
+
+// What the login screen is allowed to see before the store is unlocked.
+#[derive(serde::Serialize)]
+pub(crate) struct PublicInfo {
+    pub format_version: u32,
+    pub store_name: String,
+    pub hint_mode: &'static str,
+    pub hint: Option<String>,
+    pub hint_question: Option<String>,
+    pub keyfile: bool,
+    pub totp: bool,
+}
+
+fn normalize_answer(answer: &str) -> Zeroizing<String> {
+    Zeroizing::new(answer.trim().to_lowercase())
+}
+
+fn answer_key(answer: &str, salt: &[u8], kdf: &KdfParams) -> Result<SecretKey, String> {
+    let mut key_bytes = SecretKey::zeroed();
+    kdf.argon2()?
+        .hash_password_into(normalize_answer(answer).as_bytes(), salt, key_bytes.as_mut())
+        .map_err(|e| e.to_string())?;
+    Ok(key_bytes)
+}
+
+impl PublicMetadata {
+    pub fn load(vault_dir: &Path) -> Result<PublicMetadata, String> {
+        let data = match fs::read(vault_dir.join(PUBLIC_FILE)) {
+            Ok(data) => data,
+            Err(_) => return Ok(PublicMetadata::default()),
+        };
+        let value: Value = serde_json::from_slice(&data).map_err(|_| "Invalid public.json".to_string())?;
+
+        // Stores created before the metadata format only wrote `{ "hint": "..." }`.
+        if value.get("format_version").is_none() {
+            let hint = value.get("hint").and_then(|h| h.as_str()).unwrap_or_default();
+            return Ok(PublicMetadata {
+                format_version: 0,
+                hint: if hint.is_empty() {
+                    StoredHint::None
+                } else {
+                    StoredHint::Plain { hint: hint.to_string() }
+                },
+                ..Default::default()
+            });
+        }
+
+        let metadata: PublicMetadata = serde_json::from_value(value).map_err(|_| "Invalid public.json".to_string())?;
+        if metadata.format_version > FORMAT_VERSION {
+            return Err("This store was created by a newer version of Noetiq".into());
+        }
+        Ok(metadata)
+    }
+
+    pub fn save(&self, vault_dir: &Path) -> Result<(), String> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(vault_dir.join(PUBLIC_FILE), data).map_err(|e| e.to_string())
+    }
+
+    pub fn set_hint(&mut self, input: &HintInput) -> Result<(), String> {
+        self.hint = match input {
+            HintInput::None => StoredHint::None,
+            HintInput::Plain { hint } if hint.is_empty() => StoredHint::None,
+            HintInput::Plain { hint } => StoredHint::Plain { hint: hint.clone() },
+            HintInput::Encrypted { hint, question, answer } => {
+                if question.trim().is_empty() || normalize_answer(answer).is_empty() {
+                    return Err("An encrypted hint needs a question and an answer".into());
+                }
+
+                let mut salt = [0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let key = answer_key(answer, &salt, &self.kdf)?;
+                let data = cipher_from_key(&key[..], CipherSuite::default())?.seal(hint.as_bytes())?;
+                StoredHint::Encrypted {
+                    question: question.clone(),
+                    salt: hex::encode(salt),
+                    data: hex::encode(data),
+                }
+            }
+        };
+        Ok(())
+    }
+
+    pub fn reveal_hint(&self, answer: &str) -> Result<String, String> {
+        let (salt, data) = match &self.hint {
+            StoredHint::Encrypted { salt, data, .. } => (salt, data),
+            _ => return Err("The hint is not encrypted".into()),
+        };
+
+        let salt = hex::decode(salt).map_err(|_| "Invalid public.json".to_string())?;
+        let data = hex::decode(data).map_err(|_| "Invalid public.json".to_string())?;
+
+        let key = answer_key(answer, &salt, &self.kdf)?;
+        let hint = cipher_from_key(&key[..], CipherSuite::default())?
+            .open(&data)
+            .map_err(|_| "Wrong answer".to_string())?;
+        String::from_utf8(hint.to_vec()).map_err(|e| e.to_string())
+    }
+
+    pub fn info(&self) -> PublicInfo {
+        let (hint_mode, hint, hint_question) = match &self.hint {
+            StoredHint::None => ("none", None, None),
+            StoredHint::Plain { hint } => ("plain", Some(hint.clone()), None),
+            StoredHint::Encrypted { question, .. } => ("encrypted", None, Some(question.clone())),
+        };
+
+        PublicInfo {
+            format_version: self.format_version,
+            store_name: self.store_name.clone(),
+            hint_mode,
+            hint,
+            hint_question,
+            keyfile: self.keyfile,
+            totp: self.totp,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::lock::AppLock;
+use tauri::{AppHandle, Emitter, Manager};
+
+// Locks the store as the OS locks the screen or gets ready to sleep, rather than after waking up
+// when the lock tick notices the gap. Where none of this is available the tick still applies.
+pub(crate) fn watch(app: AppHandle) {
+    platform::watch(app);
+}
+
+fn notify(app: &AppHandle) {
+    if app.state::<AppLock>().system_locked() {
+        let _ = app.emit("locked", ());
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::notify;
+    use tauri::AppHandle;
+    use zbus::blocking::{Connection, MessageIterator, Proxy};
+    use zbus::message::Type;
+    use zbus::zvariant::OwnedObjectPath;
+    use zbus::MatchRule;
+
+    const LOGIND: &str = "org.freedesktop.login1";
+
+    pub fn watch(app: AppHandle) {
+        std::thread::spawn(move || {
+            let _ = listen(&app);
+        });
+    }
+
+    fn listen(app: &AppHandle) -> zbus::Result<()> {
+        let connection = Connection::system()?;
+        let manager = Proxy::new(&connection, LOGIND, "/org/freedesktop/login1", "org.freedesktop.login1.Manager")?;
+        // Lock is sent to a single session, this process's. Outside of one, any session locking counts.
+        let session: Option<OwnedObjectPath> = manager.call("GetSessionByPID", &(std::process::id())).ok();
+
+        let rule = MatchRule::builder().msg_type(Type::Signal).sender(LOGIND)?.build();
+        for message in MessageIterator::for_match_rule(rule, &connection, None)? {
+            let Ok(message) = message else { continue };
+            let header = message.header();
+            let locks = match header.member().map(|m| m.as_str()) {
+                Some("PrepareForSleep") => message.body().deserialize::<bool>().unwrap_or(false),
+                Some("Lock") => session
+                    .as_ref()
+                    .is_none_or(|session| header.path().map(|p| p.as_str()) == Some(session.as_str())),
+                _ => false,
+            };
+            if locks {
+                notify(app);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Distributed notifications arrive on the main run loop, so this has to be set up from there.
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::notify;
+    use block2::RcBlock;
+    use objc2_foundation::{ns_string, NSDistributedNotificationCenter, NSNotification};
+    use std::ptr::NonNull;
+    use tauri::AppHandle;
+
+    pub fn watch(app: AppHandle) {
+        let block: RcBlock<dyn Fn(NonNull<NSNotification>)> = RcBlock::new(move |_: NonNull<NSNotification>| notify(&app));
+        let center = NSDistributedNotificationCenter::defaultCenter();
+        let observer = unsafe {
+            center.addObserverForName_object_queue_usingBlock(Some(ns_string!("com.apple.screenIsLocked")), None, None, &block)
+        };
+        // Observes for as long as the app runs.
+        std::mem::forget(observer);
+    }
+}
+
+// Session changes are only posted to windows, a hidden message-only one on a thread of its own.
+#[cfg(windows)]
+mod platform {
+    use super::notify;
+    use std::sync::OnceLock;
+    use tauri::AppHandle;
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::System::RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, HWND_MESSAGE, MSG,
+        WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK,
+    };
+
+    // The window procedure gets no context of its own.
+    static APP: OnceLock<AppHandle> = OnceLock::new();
+
+    unsafe extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if msg == WM_WTSSESSION_CHANGE && wparam == WTS_SESSION_LOCK as WPARAM {
+            if let Some(app) = APP.get() {
+                notify(app);
+            }
+            return 0;
+        }
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+
+    pub fn watch(app: AppHandle) {
+        if APP.set(app).is_err() {
+            return;
+        }
+        std::thread::spawn(|| unsafe {
+            let class: Vec<u16> = "NoetiqSessionWatcher\0".encode_utf16().collect();
+            let instance = GetModuleHandleW(std::ptr::null());
+            let mut window_class: WNDCLASSW = std::mem::zeroed();
+            window_class.lpfnWndProc = Some(window_proc);
+            window_class.hInstance = instance;
+            window_class.lpszClassName = class.as_ptr();
+            if RegisterClassW(&window_class) == 0 {
+                return;
+            }
+
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                std::ptr::null(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                std::ptr::null_mut(),
+                instance,
+                std::ptr::null(),
+            );
+            if hwnd.is_null() || WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) == 0 {
+                return;
+            }
+
+            let mut msg: MSG = std::mem::zeroed();
+            while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
+                DispatchMessageW(&msg);
+            }
+        });
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use tauri::AppHandle;
+
+    pub fn watch(_app: AppHandle) {}
+}
@@ -0,0 +1,77 @@
+use std::ops::Deref;
+use zeroize::{Zeroize, Zeroizing};
+
+pub const KEY_LEN: usize = 32;
+
+// Decrypted data and serialized plaintext, wiped when dropped.
+pub type Plaintext = Zeroizing<Vec<u8>>;
+
+// Keeps the pages holding key material out of swap. Best effort: it fails quietly when the
+// RLIMIT_MEMLOCK budget is exhausted, and unlocking one key also unlocks any other key that
+// happens to share its page.
+#[cfg(unix)]
+fn lock_memory(ptr: *const u8, len: usize) {
+    unsafe {
+        libc::mlock(ptr as *const libc::c_void, len);
+    }
+}
+
+#[cfg(unix)]
+fn unlock_memory(ptr: *const u8, len: usize) {
+    unsafe {
+        libc::munlock(ptr as *const libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_ptr: *const u8, _len: usize) {}
+
+#[cfg(not(unix))]
+fn unlock_memory(_ptr: *const u8, _len: usize) {}
+
+// A 32 byte key on the heap, so it never moves once locked, wiped when dropped.
+pub struct SecretKey(Box<[u8; KEY_LEN]>);
+
+impl SecretKey {
+    pub fn zeroed() -> Self {
+        let key = Box::new([0u8; KEY_LEN]);
+        lock_memory(key.as_ptr(), KEY_LEN);
+        SecretKey(key)
+    }
+}
+
+impl AsMut<[u8; KEY_LEN]> for SecretKey {
+    fn as_mut(&mut self) -> &mut [u8; KEY_LEN] {
+        &mut self.0
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = [u8; KEY_LEN];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Clone for SecretKey {
+    fn clone(&self) -> Self {
+        let mut key = SecretKey::zeroed();
+        key.as_mut().copy_from_slice(&self.0[..]);
+        key
+    }
+}
+
+impl PartialEq for SecretKey {
+    // Constant time, so comparing keys does not leak where they first differ.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.iter().zip(other.0.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        unlock_memory(self.0.as_ptr(), KEY_LEN);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::attachments::{self, AttachmentInfo};
+use crate::cipher::{Cipher, CipherSuite};
+use crate::identity::{self, Contact, Identity, PublicKey};
+use crate::metadata::KdfParams;
+use crate::secret::{Plaintext, SecretKey, KEY_LEN};
+use crate::storage::{self, VaultStore};
+use crate::{cipher_from_key, derive_key, SALT_LEN};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use data_encoding::BASE64;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+pub(crate) const EXTENSION: &str = "noetiq";
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) const OPENED_EVENT: &str = "shared-note-opened";
+
+const FORMAT: &str = "noetiq-note";
+const VERSION: u32 = 2;
+const MIN_PASSWORD_LEN: usize = 8;
+const INVALID_FILE: &str = "Not a shared Noetiq note";
+const NOT_FOR_YOU: &str = "This file was not shared with you";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ContentKind {
+    #[default]
+    Note,
+    Vault,
+}
+
+// A file is sealed either with a share password, which has its own salt and knows nothing of the
+// vault it came from, or to contacts, with the content key wrapped once for each. Recipients are
+// not named, a store tries every wrapped key with its own identity.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SharedFile {
+    format: String,
+    version: u32,
+    #[serde(default)]
+    content: ContentKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sender: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ephemeral: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    recipients: Vec<String>,
+    data: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct SharedNote {
+    pub title: String,
+    pub icon: String,
+    pub document: Value,
+    #[serde(default)]
+    attachments: Vec<SharedAttachment>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SharedAttachment {
+    #[serde(flatten)]
+    info: AttachmentInfo,
+    data: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct SharedVault {
+    pub name: String,
+    pub icon: String,
+    pub description: String,
+    pub notes: Vec<SharedNote>,
+}
+
+pub(crate) enum SharedContent {
+    Note(SharedNote),
+    Vault(SharedVault),
+}
+
+pub(crate) enum Protection<'a> {
+    Password(&'a str),
+    Recipients(&'a Identity, &'a [PublicKey]),
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct SenderInfo {
+    pub name: Option<String>,
+    pub fingerprint: String,
+}
+
+// What the import dialog needs before asking for anything.
+#[derive(serde::Serialize)]
+pub(crate) struct SharedFileInfo {
+    pub content: ContentKind,
+    pub password: bool,
+    pub sender: Option<SenderInfo>,
+    pub readable: bool,
+}
+
+// Files the system asked us to open, held until the frontend is unlocked and asks for them.
+#[derive(Default)]
+pub(crate) struct OpenedFiles(Mutex<Vec<PathBuf>>);
+
+impl OpenedFiles {
+    pub fn push(&self, path: PathBuf) -> bool {
+        if path.extension() != Some(OsStr::new(EXTENSION)) || !path.is_file() {
+            return false;
+        }
+        let mut files = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !files.contains(&path) {
+            files.push(path);
+        }
+        true
+    }
+
+    pub fn take(&self) -> Vec<PathBuf> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl SharedNote {
+    // Attachments the document refers to travel with it, one that is gone stays as broken as it
+    // is in the vault.
+    pub fn new(store: &VaultStore, entry: &Value, document: Value) -> Result<Self, String> {
+        let mut ids = HashSet::new();
+        attachments::collect_references(&document, &mut ids);
+
+        let mut shared = Vec::new();
+        for id in ids {
+            let Ok((attachment, data)) = attachments::load(store, &id) else {
+                continue;
+            };
+            shared.push(SharedAttachment {
+                info: attachment.info(&id),
+                data: BASE64.encode(&data),
+            });
+        }
+
+        Ok(SharedNote {
+            title: entry.get("notetitle").and_then(|t| t.as_str()).unwrap_or_default().to_string(),
+            icon: entry.get("icon").and_then(|i| i.as_str()).unwrap_or_default().to_string(),
+            document,
+            attachments: shared,
+        })
+    }
+
+    // The sender's attachment ids are keyed to their store, so each one is stored under this
+    // vault's own id and the document pointed at it.
+    pub fn import_attachments(&mut self, store: &VaultStore, store_key: &[u8], vault: &str) -> Result<(), String> {
+        let mut ids = HashMap::new();
+        for attachment in self.attachments.drain(..) {
+            let data = Zeroizing::new(
+                BASE64
+                    .decode(attachment.data.as_bytes())
+                    .map_err(|_| "Invalid shared note".to_string())?,
+            );
+            let info = attachments::store(store, store_key, vault, &attachment.info.name, &attachment.info.mime, &data)?;
+            ids.insert(attachment.info.id, info.id);
+        }
+        attachments::replace_references(&mut self.document, &ids);
+        Ok(())
+    }
+}
+
+impl SharedContent {
+    fn kind(&self) -> ContentKind {
+        match self {
+            SharedContent::Note(_) => ContentKind::Note,
+            SharedContent::Vault(_) => ContentKind::Vault,
+        }
+    }
+
+    fn to_payload(&self) -> Result<Plaintext, String> {
+        match self {
+            SharedContent::Note(note) => serde_json::to_vec(note),
+            SharedContent::Vault(vault) => serde_json::to_vec(vault),
+        }
+        .map(Zeroizing::new)
+        .map_err(|e| e.to_string())
+    }
+}
+
+impl SharedFile {
+    fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|_| "Failed to read the shared note".to_string())?;
+        let file: SharedFile = serde_json::from_slice(&data).map_err(|_| INVALID_FILE.to_string())?;
+        if file.format != FORMAT {
+            return Err(INVALID_FILE.into());
+        }
+        if file.version > VERSION {
+            return Err("This note was shared from a newer version of Noetiq".into());
+        }
+        Ok(file)
+    }
+
+    fn has_password(&self) -> bool {
+        self.salt.is_some()
+    }
+
+    fn sender(&self) -> Result<Option<PublicKey>, String> {
+        self.sender.as_deref().map(identity::decode_public_key).transpose()
+    }
+
+    fn content_cipher(&self, password: Option<&str>, identity: Option<&Identity>) -> Result<Cipher, String> {
+        if let Some(salt) = &self.salt {
+            let password = password.filter(|p| !p.is_empty()).ok_or("Enter the share password")?;
+            let salt = hex::decode(salt).map_err(|_| INVALID_FILE.to_string())?;
+            let kdf = self.kdf.as_ref().ok_or(INVALID_FILE)?;
+            return cipher_from_key(&derive_key(password.as_bytes(), &salt, kdf)?[..], CipherSuite::default());
+        }
+
+        let identity = identity.ok_or(NOT_FOR_YOU)?;
+        let sender = self.sender()?.ok_or(INVALID_FILE)?;
+        let ephemeral = identity::decode_public_key(self.ephemeral.as_deref().ok_or(INVALID_FILE)?)?;
+        let unwrapping = cipher_from_key(
+            &identity::unwrapping_key(identity, &ephemeral, &sender)?[..],
+            CipherSuite::default(),
+        )?;
+        for wrapped in &self.recipients {
+            let Ok(sealed) = BASE64.decode(wrapped.as_bytes()) else {
+                continue;
+            };
+            if let Ok(key) = unwrapping.open(&sealed) {
+                if key.len() == KEY_LEN {
+                    return cipher_from_key(&key, CipherSuite::default());
+                }
+            }
+        }
+        Err(NOT_FOR_YOU.into())
+    }
+}
+
+pub(crate) fn write(path: &Path, content: &SharedContent, protection: Protection) -> Result<(), String> {
+    let payload = content.to_payload()?;
+    let mut file = SharedFile {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        content: content.kind(),
+        salt: None,
+        kdf: None,
+        sender: None,
+        ephemeral: None,
+        recipients: Vec::new(),
+        data: String::new(),
+    };
+
+    let cipher = match protection {
+        Protection::Password(password) => {
+            if password.chars().count() < MIN_PASSWORD_LEN {
+                return Err(format!("The share password needs at least {} characters", MIN_PASSWORD_LEN));
+            }
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let kdf = KdfParams::default();
+            let key = derive_key(password.as_bytes(), &salt, &kdf)?;
+            file.salt = Some(hex::encode(salt));
+            file.kdf = Some(kdf);
+            cipher_from_key(&key[..], CipherSuite::default())?
+        }
+        Protection::Recipients(sender, recipients) => {
+            if recipients.is_empty() {
+                return Err("Choose at least one contact to share with".into());
+            }
+            let mut key = SecretKey::zeroed();
+            OsRng.fill_bytes(key.as_mut());
+            let ephemeral = Identity::generate();
+            for recipient in recipients {
+                let wrapping = cipher_from_key(
+                    &identity::wrapping_key(&ephemeral, sender, recipient)?[..],
+                    CipherSuite::default(),
+                )?;
+                file.recipients.push(BASE64.encode(&wrapping.seal(&key[..])?));
+            }
+            file.sender = Some(identity::encode_public_key(&sender.public_key()?));
+            file.ephemeral = Some(identity::encode_public_key(&ephemeral.public_key()?));
+            cipher_from_key(&key[..], CipherSuite::default())?
+        }
+    };
+
+    file.data = BASE64.encode(&cipher.seal(&storage::compress(&payload)?)?);
+    crate::atomic_write(path, &serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?)
+}
+
+pub(crate) fn describe(path: &Path, identity: Option<&Identity>, contacts: &[Contact]) -> Result<SharedFileInfo, String> {
+    let file = SharedFile::load(path)?;
+    let sender = file.sender()?.map(|key| SenderInfo {
+        name: contacts
+            .iter()
+            .find(|c| identity::decode_public_key(&c.public_key).ok() == Some(key))
+            .map(|c| c.name.clone()),
+        fingerprint: identity::fingerprint(&key),
+    });
+    Ok(SharedFileInfo {
+        content: file.content,
+        password: file.has_password(),
+        sender,
+        readable: file.has_password() || file.content_cipher(None, identity).is_ok(),
+    })
+}
+
+pub(crate) fn read(path: &Path, password: Option<&str>, identity: Option<&Identity>) -> Result<SharedContent, String> {
+    let file = SharedFile::load(path)?;
+    let cipher = file.content_cipher(password, identity)?;
+    let sealed = BASE64.decode(file.data.as_bytes()).map_err(|_| INVALID_FILE.to_string())?;
+    let payload = cipher.open(&sealed).map_err(|_| {
+        if file.has_password() {
+            "Wrong share password".to_string()
+        } else {
+            INVALID_FILE.to_string()
+        }
+    })?;
+    let payload = storage::decompress(payload)?;
+    match file.content {
+        ContentKind::Note => serde_json::from_slice(&payload).map(SharedContent::Note),
+        ContentKind::Vault => serde_json::from_slice(&payload).map(SharedContent::Vault),
+    }
+    .map_err(|_| "Invalid shared note".to_string())
+}
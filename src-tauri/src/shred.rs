@@ -0,0 +1,92 @@
+use crate::cipher::{Cipher, CipherSuite};
+use crate::secret::Plaintext;
+use crate::{atomic_write, cipher_from_key};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const OVERWRITE_CHUNK: usize = 64 * 1024;
+
+// Overwriting is best effort: SSD wear levelling and copy-on-write filesystems may keep the old
+// blocks around. Per-note keys cover those cases, deleting a note drops its key from the
+// encrypted vault index and leaves any surviving ciphertext undecryptable. The index written
+// before the deletion is overwritten whatever the storage settings, but history, when turned on,
+// keeps every earlier version of it.
+fn overwrite(path: &Path) -> Result<(), String> {
+    let len = fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let mut file = File::options().write(true).open(path).map_err(|e| e.to_string())?;
+
+    let mut chunk = vec![0u8; OVERWRITE_CHUNK];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(OVERWRITE_CHUNK as u64) as usize;
+        OsRng.fill_bytes(&mut chunk[..n]);
+        file.write_all(&chunk[..n]).map_err(|e| e.to_string())?;
+        remaining -= n as u64;
+    }
+    file.sync_all().map_err(|e| e.to_string())
+}
+
+pub(crate) fn remove_file(path: &Path, secure: bool) -> Result<(), String> {
+    if secure {
+        overwrite(path)?;
+    }
+    fs::remove_file(path).map_err(|e| e.to_string())
+}
+
+pub(crate) fn remove_dir_all(path: &Path, secure: bool) -> Result<(), String> {
+    if secure {
+        for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+            let entry_path = entry.map_err(|e| e.to_string())?.path();
+            if entry_path.is_dir() {
+                remove_dir_all(&entry_path, true)?;
+            } else {
+                remove_file(&entry_path, true)?;
+            }
+        }
+    }
+    fs::remove_dir_all(path).map_err(|e| e.to_string())
+}
+
+// Atomically replaces a file. In secure mode the previous version is overwritten once the new
+// one is in place, instead of being left behind in freed blocks.
+pub(crate) fn replace(path: &Path, data: &[u8], secure: bool) -> Result<(), String> {
+    if !secure || !path.exists() {
+        return atomic_write(path, data);
+    }
+
+    let old_path = crate::sibling_path(path, ".old");
+    fs::rename(path, &old_path).map_err(|e| e.to_string())?;
+    if let Err(e) = atomic_write(path, data) {
+        let _ = fs::rename(&old_path, path);
+        return Err(e);
+    }
+    remove_file(&old_path, true)
+}
+
+pub(crate) fn new_note_key() -> String {
+    let mut key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(&mut key[..]);
+    hex::encode(&key[..])
+}
+
+pub(crate) fn note_key(entry: &Value) -> Option<&str> {
+    entry.get("key").and_then(|k| k.as_str())
+}
+
+pub(crate) fn note_cipher(key: &str, suite: CipherSuite) -> Result<Cipher, String> {
+    let key = Zeroizing::new(hex::decode(key).map_err(|_| "Invalid note key".to_string())?);
+    cipher_from_key(&key, suite)
+}
+
+pub(crate) fn seal_note(key: &str, suite: CipherSuite, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    note_cipher(key, suite)?.seal(plaintext)
+}
+
+// The suite only matters for writing, data records the one it was sealed with.
+pub(crate) fn open_note(key: &str, data: &[u8]) -> Result<Plaintext, String> {
+    note_cipher(key, CipherSuite::default())?.open(data)
+}
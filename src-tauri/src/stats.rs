@@ -0,0 +1,120 @@
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Block data keys holding configuration rather than user text. Image and file blocks keep their
+// attachment's id, name and type under "file".
+const NON_TEXT_KEYS: [&str; 12] = [
+    "style", "alignment", "align", "url", "link", "service", "source", "embed", "file", "attachment", "mime",
+    "extension",
+];
+
+#[derive(serde::Serialize, Default)]
+pub(crate) struct LastModifiedNote {
+    pub filename: String,
+    pub notetitle: String,
+    pub icon: String,
+    pub modified: i64,
+}
+
+#[derive(serde::Serialize, Default)]
+pub(crate) struct VaultStats {
+    pub notes: usize,
+    pub words: usize,
+    pub characters: usize,
+    pub block_types: BTreeMap<String, usize>,
+    pub storage_bytes: u64,
+    pub last_modified: Option<LastModifiedNote>,
+}
+
+impl VaultStats {
+    pub fn add_note(&mut self, document: &Value) {
+        self.notes += 1;
+
+        let blocks = match document.get("blocks").and_then(|b| b.as_array()) {
+            Some(blocks) => blocks,
+            None => return,
+        };
+
+        for block in blocks {
+            let block_type = block.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
+            *self.block_types.entry(block_type.to_string()).or_insert(0) += 1;
+
+            let mut texts = Vec::new();
+            if let Some(data) = block.get("data") {
+                collect_text(data, &mut texts);
+            }
+            for text in texts {
+                let text = strip_html(&text);
+                self.words += text.split_whitespace().count();
+                self.characters += text.chars().count();
+            }
+        }
+    }
+
+    pub fn track_modified(&mut self, entry: &Value) {
+        let modified = match entry.get("modified").and_then(|m| m.as_i64()) {
+            Some(modified) => modified,
+            None => return,
+        };
+        if self.last_modified.as_ref().is_some_and(|last| last.modified >= modified) {
+            return;
+        }
+
+        let field = |key: &str| entry.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        self.last_modified = Some(LastModifiedNote {
+            filename: field("filename"),
+            notetitle: field("notetitle"),
+            icon: field("icon"),
+            modified,
+        });
+    }
+}
+
+fn collect_text(value: &Value, texts: &mut Vec<String>) {
+    match value {
+        Value::String(text) => texts.push(text.clone()),
+        Value::Array(items) => items.iter().for_each(|item| collect_text(item, texts)),
+        Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| !NON_TEXT_KEYS.contains(&key.as_str()))
+            .for_each(|(_, item)| collect_text(item, texts)),
+        _ => {}
+    }
+}
+
+fn strip_html(text: &str) -> String {
+    let text = text.replace("<br>", " ").replace("<br/>", " ");
+
+    let mut output = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => output.push(c),
+            _ => {}
+        }
+    }
+
+    output
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+pub(crate) fn dir_size(path: &Path) -> Result<u64, String> {
+    let mut total = 0;
+    for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        total += if path.is_dir() {
+            dir_size(&path)?
+        } else {
+            fs::metadata(&path).map_err(|e| e.to_string())?.len()
+        };
+    }
+    Ok(total)
+}
@@ -0,0 +1,408 @@
+use crate::secret::Plaintext;
+use crate::shred;
+use crate::cipher::{Cipher, CipherSuite};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// Padded plaintexts start with a NUL byte, which no legacy JSON plaintext can.
+const PAD_MAGIC: &[u8; 4] = b"\0NQP";
+const PAD_HEADER_LEN: usize = PAD_MAGIC.len() + 8;
+const MIN_BUCKET: usize = 4 * 1024;
+
+// Compressed plaintexts carry their original length, so decompression allocates exactly once.
+const ZSTD_MAGIC: &[u8; 4] = b"\0NQZ";
+const ZSTD_HEADER_LEN: usize = ZSTD_MAGIC.len() + 8;
+const ZSTD_LEVEL: i32 = 3;
+
+// 2000-01-01T00:00:00Z, used for every file and folder in hardened mode.
+const NORMALIZED_MTIME_SECS: u64 = 946_684_800;
+
+pub(crate) const INDEX_FILE: &str = "index.json";
+pub(crate) const PACK_FILE: &str = "vault.pack";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Default, PartialEq)]
+pub(crate) struct StorageSettings {
+    #[serde(default)]
+    pub hardened: bool,
+    #[serde(default)]
+    pub pack_vaults: bool,
+    #[serde(default)]
+    pub secure_delete: bool,
+    #[serde(default)]
+    pub compress: bool,
+    #[serde(default)]
+    pub cipher: CipherSuite,
+}
+
+impl StorageSettings {
+    pub fn packs_vaults(&self) -> bool {
+        self.hardened && self.pack_vaults
+    }
+}
+
+// The header and the number of zero bytes that pad `len` bytes of plaintext to its bucket.
+pub(crate) fn padding(len: usize) -> (Vec<u8>, usize) {
+    let bucket = (PAD_HEADER_LEN + len).next_power_of_two().max(MIN_BUCKET);
+
+    let mut header = Vec::with_capacity(PAD_HEADER_LEN);
+    header.extend_from_slice(PAD_MAGIC);
+    header.extend_from_slice(&(len as u64).to_le_bytes());
+    (header, bucket - PAD_HEADER_LEN - len)
+}
+
+pub(crate) fn pad(plaintext: &[u8]) -> Plaintext {
+    let (header, fill) = padding(plaintext.len());
+
+    let bucket = header.len() + plaintext.len() + fill;
+
+    let mut padded = Zeroizing::new(Vec::with_capacity(bucket));
+    padded.extend_from_slice(&header);
+    padded.extend_from_slice(plaintext);
+    padded.resize(bucket, 0);
+    padded
+}
+
+pub(crate) fn unpad(data: Plaintext) -> Result<Plaintext, String> {
+    if !data.starts_with(PAD_MAGIC) {
+        return Ok(data);
+    }
+    if data.len() < PAD_HEADER_LEN {
+        return Err("Corrupted padded data".into());
+    }
+
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(&data[PAD_MAGIC.len()..PAD_HEADER_LEN]);
+    let len = u64::from_le_bytes(len_bytes) as usize;
+    if len > data.len() - PAD_HEADER_LEN {
+        return Err("Corrupted padded data".into());
+    }
+
+    Ok(Zeroizing::new(data[PAD_HEADER_LEN..PAD_HEADER_LEN + len].to_vec()))
+}
+
+// Compression runs before padding, so in hardened mode a note's size only shows up as the bucket
+// its compressed form falls into.
+pub(crate) fn compress(plaintext: &[u8]) -> Result<Plaintext, String> {
+    let mut frame = Zeroizing::new(Vec::with_capacity(zstd::zstd_safe::compress_bound(plaintext.len())));
+    zstd::bulk::Compressor::new(ZSTD_LEVEL)
+        .and_then(|mut compressor| compressor.compress_to_buffer(plaintext, &mut *frame))
+        .map_err(|e| e.to_string())?;
+
+    // Data that does not shrink, such as an already encrypted note, is stored as is.
+    if ZSTD_HEADER_LEN + frame.len() >= plaintext.len() {
+        return Ok(Zeroizing::new(plaintext.to_vec()));
+    }
+
+    let mut output = Zeroizing::new(Vec::with_capacity(ZSTD_HEADER_LEN + frame.len()));
+    output.extend_from_slice(ZSTD_MAGIC);
+    output.extend_from_slice(&(plaintext.len() as u64).to_le_bytes());
+    output.extend_from_slice(&frame);
+    Ok(output)
+}
+
+pub(crate) fn decompress(data: Plaintext) -> Result<Plaintext, String> {
+    if !data.starts_with(ZSTD_MAGIC) {
+        return Ok(data);
+    }
+    if data.len() < ZSTD_HEADER_LEN {
+        return Err("Corrupted compressed data".into());
+    }
+
+    let mut len_bytes = [0u8; 8];
+    len_bytes.copy_from_slice(&data[ZSTD_MAGIC.len()..ZSTD_HEADER_LEN]);
+    let len = u64::from_le_bytes(len_bytes) as usize;
+
+    let mut output = Zeroizing::new(Vec::with_capacity(len));
+    zstd::bulk::Decompressor::new()
+        .and_then(|mut decompressor| decompressor.decompress_to_buffer(&data[ZSTD_HEADER_LEN..], &mut *output))
+        .map_err(|_| "Corrupted compressed data".to_string())?;
+    if output.len() != len {
+        return Err("Corrupted compressed data".into());
+    }
+    Ok(output)
+}
+
+pub(crate) fn seal(
+    cipher: &Cipher,
+    plaintext: &[u8],
+    settings: StorageSettings,
+) -> Result<Vec<u8>, String> {
+    let compressed;
+    let plaintext = if settings.compress {
+        compressed = compress(plaintext)?;
+        &compressed[..]
+    } else {
+        plaintext
+    };
+
+    let padded;
+    let data = if settings.hardened {
+        padded = pad(plaintext);
+        &padded[..]
+    } else {
+        plaintext
+    };
+
+    cipher.seal(data)
+}
+
+pub(crate) fn open(cipher: &Cipher, data: &[u8]) -> Result<Plaintext, String> {
+    decompress(unpad(cipher.open(data)?)?)
+}
+
+pub(crate) fn normalize_mtime(path: &Path) -> Result<(), String> {
+    let time = UNIX_EPOCH + Duration::from_secs(NORMALIZED_MTIME_SECS);
+    let file = if path.is_dir() {
+        File::open(path)
+    } else {
+        File::options().write(true).open(path)
+    }
+    .map_err(|e| e.to_string())?;
+
+    file.set_modified(time).map_err(|e| e.to_string())
+}
+
+pub(crate) fn normalize_tree(dir: &Path) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            normalize_tree(&path)?;
+        } else {
+            normalize_mtime(&path)?;
+        }
+    }
+
+    // Folder times are best effort, some platforms refuse to open directories for writing.
+    let _ = normalize_mtime(dir);
+    Ok(())
+}
+
+fn encode_pack(entries: &BTreeMap<String, Plaintext>) -> Plaintext {
+    // Sized up front, a reallocation would leave an unwiped copy of the plaintext behind.
+    let len = entries.iter().map(|(name, data)| 12 + name.len() + data.len()).sum();
+    let mut output = Zeroizing::new(Vec::with_capacity(len));
+    for (name, data) in entries {
+        output.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        output.extend_from_slice(name.as_bytes());
+        output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        output.extend_from_slice(data);
+    }
+    output
+}
+
+pub(crate) fn decode_pack(mut data: &[u8]) -> Result<BTreeMap<String, Plaintext>, String> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+        if data.len() < len {
+            return Err("Corrupted vault container".into());
+        }
+        let (head, tail) = data.split_at(len);
+        *data = tail;
+        Ok(head)
+    }
+
+    let mut entries = BTreeMap::new();
+    while !data.is_empty() {
+        let mut name_len = [0u8; 4];
+        name_len.copy_from_slice(take(&mut data, 4)?);
+        let name = take(&mut data, u32::from_le_bytes(name_len) as usize)?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| "Corrupted vault container")?;
+
+        let mut data_len = [0u8; 8];
+        data_len.copy_from_slice(take(&mut data, 8)?);
+        let entry = take(&mut data, u64::from_le_bytes(data_len) as usize)?;
+
+        entries.insert(name, Zeroizing::new(entry.to_vec()));
+    }
+    Ok(entries)
+}
+
+pub(crate) struct VaultStore<'a> {
+    dir: PathBuf,
+    cipher: &'a Cipher,
+    settings: StorageSettings,
+}
+
+impl<'a> VaultStore<'a> {
+    pub fn new(dir: PathBuf, cipher: &'a Cipher, settings: StorageSettings) -> Self {
+        VaultStore { dir, cipher, settings }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn settings(&self) -> StorageSettings {
+        self.settings
+    }
+
+    // For changes that drop a note key, whose previous version must not stay behind on disk.
+    pub fn shredding(&self) -> VaultStore<'a> {
+        let settings = StorageSettings { secure_delete: true, ..self.settings };
+        VaultStore::new(self.dir.clone(), self.cipher, settings)
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.dir.join(PACK_FILE).exists()
+    }
+
+    pub fn init(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|_| "Failed to create vault folder".to_string())?;
+        if self.settings.packs_vaults() {
+            self.write_pack(&BTreeMap::new())?;
+        }
+        self.write(INDEX_FILE, b"[]")
+    }
+
+    fn read_pack(&self) -> Result<BTreeMap<String, Plaintext>, String> {
+        let data = fs::read(self.dir.join(PACK_FILE)).map_err(|_| "Error reading vault container".to_string())?;
+        decode_pack(&open(self.cipher, &data)?)
+    }
+
+    fn write_pack(&self, entries: &BTreeMap<String, Plaintext>) -> Result<(), String> {
+        let path = self.dir.join(PACK_FILE);
+        shred::replace(&path, &seal(self.cipher, &encode_pack(entries), self.settings)?, self.settings.secure_delete)?;
+        self.touch(&path)
+    }
+
+    fn touch(&self, path: &Path) -> Result<(), String> {
+        if self.settings.hardened {
+            normalize_mtime(path)?;
+            let _ = normalize_mtime(&self.dir);
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, name: &str) -> Result<Plaintext, String> {
+        if self.is_packed() {
+            return self
+                .read_pack()?
+                .remove(name)
+                .ok_or_else(|| format!("Error reading {}", name));
+        }
+
+        let data = fs::read(self.dir.join(name)).map_err(|_| format!("Error reading {}", name))?;
+        open(self.cipher, &data)
+    }
+
+    pub fn contains(&self, name: &str) -> Result<bool, String> {
+        if self.is_packed() {
+            return Ok(self.read_pack()?.contains_key(name));
+        }
+        Ok(self.dir.join(name).exists())
+    }
+
+    pub fn write(&self, name: &str, plaintext: &[u8]) -> Result<(), String> {
+        if self.is_packed() {
+            let mut entries = self.read_pack()?;
+            entries.insert(name.to_string(), Zeroizing::new(plaintext.to_vec()));
+            return self.write_pack(&entries);
+        }
+
+        self.write_file(name, plaintext)
+    }
+
+    fn write_file(&self, name: &str, plaintext: &[u8]) -> Result<(), String> {
+        let path = self.dir.join(name);
+        let sealed = seal(self.cipher, plaintext, self.settings)?;
+        shred::replace(&path, &sealed, self.settings.secure_delete).map_err(|_| format!("Error writing {}", name))?;
+        self.touch(&path)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        if self.is_packed() {
+            let mut entries = self.read_pack()?;
+            if entries.remove(name).is_none() {
+                return Ok(false);
+            }
+            self.write_pack(&entries)?;
+            return Ok(true);
+        }
+
+        let path = self.dir.join(name);
+        if !path.exists() {
+            return Ok(false);
+        }
+        shred::remove_file(&path, self.settings.secure_delete).map_err(|_| format!("Error deleting {}", name))?;
+        let _ = self.touch(&self.dir);
+        Ok(true)
+    }
+
+    pub fn note_names(&self) -> Result<Vec<String>, String> {
+        let names: Vec<String> = if self.is_packed() {
+            self.read_pack()?.into_keys().collect()
+        } else {
+            fs::read_dir(&self.dir)
+                .map_err(|_| "Failed to read directory".to_string())?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .filter_map(|path| path.file_name().and_then(|n| n.to_str()).map(String::from))
+                .collect()
+        };
+
+        Ok(names
+            .into_iter()
+            .filter(|name| name.ends_with(".json") && name != INDEX_FILE)
+            .collect())
+    }
+
+    fn entries(&self) -> Result<BTreeMap<String, Plaintext>, String> {
+        let mut names = self.note_names()?;
+        names.push(INDEX_FILE.to_string());
+
+        let mut entries = BTreeMap::new();
+        for name in names {
+            let data = self.read(&name)?;
+            entries.insert(name, data);
+        }
+        Ok(entries)
+    }
+
+    // Rewrites every entry with the current settings, packing or unpacking the vault as needed.
+    pub fn migrate(&self) -> Result<(), String> {
+        let entries = self.entries()?;
+
+        if self.settings.packs_vaults() {
+            self.write_pack(&entries)?;
+            for name in entries.keys() {
+                let path = self.dir.join(name);
+                if path.exists() {
+                    shred::remove_file(&path, self.settings.secure_delete).map_err(|_| format!("Error deleting {}", name))?;
+                }
+            }
+        } else {
+            for (name, data) in &entries {
+                self.write_file(name, data)?;
+            }
+            let pack_path = self.dir.join(PACK_FILE);
+            if pack_path.exists() {
+                shred::remove_file(&pack_path, self.settings.secure_delete).map_err(|_| "Error deleting vault container".to_string())?;
+            }
+        }
+
+        if self.settings.hardened {
+            normalize_tree(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    // Seals every entry again under another key, keeping the layout the vault has.
+    pub fn rekey(&self, cipher: &Cipher) -> Result<(), String> {
+        let entries = self.entries()?;
+        let target = VaultStore::new(self.dir.clone(), cipher, self.settings);
+        if self.is_packed() {
+            target.write_pack(&entries)?;
+        } else {
+            for (name, data) in &entries {
+                target.write_file(name, data)?;
+            }
+        }
+        if self.settings.hardened {
+            normalize_tree(&self.dir)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+use crate::cipher::{Cipher, CipherSuite};
+use crate::secret::Plaintext;
+use crate::DECRYPT_FAILED;
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{rand_core::RngCore, OsRng, Payload};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::XChaCha20Poly1305;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+// STREAM construction: every chunk gets its own nonce made of a random per-file prefix, a
+// big-endian chunk counter and a last-chunk flag, so chunks cannot be reordered, dropped or
+// truncated without failing authentication. The header is bound to every chunk as associated data.
+// Only attachments use it so far, share files are still sealed whole.
+const AES_MAGIC: &[u8; 4] = b"NQS1";
+const XCHACHA_MAGIC: &[u8; 4] = b"NQS2";
+const MAGIC_LEN: usize = 4;
+const PREFIX_OFFSET: usize = MAGIC_LEN + 4;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+fn invalid_data() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, DECRYPT_FAILED)
+}
+
+fn encryption_failed() -> io::Error {
+    io::Error::other("Encryption failed")
+}
+
+fn magic(suite: CipherSuite) -> &'static [u8; 4] {
+    match suite {
+        CipherSuite::Aes256Gcm => AES_MAGIC,
+        CipherSuite::XChaCha20Poly1305 => XCHACHA_MAGIC,
+    }
+}
+
+// The nonce minus the 4 byte counter and the 1 byte flag.
+fn prefix_len(suite: CipherSuite) -> usize {
+    match suite {
+        CipherSuite::Aes256Gcm => 7,
+        CipherSuite::XChaCha20Poly1305 => 19,
+    }
+}
+
+enum Encryptor {
+    Aes(Box<EncryptorBE32<Aes256Gcm>>),
+    XChaCha(Box<EncryptorBE32<XChaCha20Poly1305>>),
+}
+
+impl Encryptor {
+    fn new(cipher: &Cipher, prefix: &[u8]) -> Self {
+        match cipher.suite() {
+            CipherSuite::Aes256Gcm => {
+                Encryptor::Aes(Box::new(EncryptorBE32::from_aead(cipher.aes.clone(), GenericArray::from_slice(prefix))))
+            }
+            CipherSuite::XChaCha20Poly1305 => {
+                Encryptor::XChaCha(Box::new(EncryptorBE32::from_aead(cipher.xchacha.clone(), GenericArray::from_slice(prefix))))
+            }
+        }
+    }
+
+    fn next(&mut self, payload: Payload) -> io::Result<Vec<u8>> {
+        match self {
+            Encryptor::Aes(e) => e.encrypt_next(payload),
+            Encryptor::XChaCha(e) => e.encrypt_next(payload),
+        }
+        .map_err(|_| encryption_failed())
+    }
+
+    fn last(self, payload: Payload) -> io::Result<Vec<u8>> {
+        match self {
+            Encryptor::Aes(e) => e.encrypt_last(payload),
+            Encryptor::XChaCha(e) => e.encrypt_last(payload),
+        }
+        .map_err(|_| encryption_failed())
+    }
+}
+
+enum Decryptor {
+    Aes(Box<DecryptorBE32<Aes256Gcm>>),
+    XChaCha(Box<DecryptorBE32<XChaCha20Poly1305>>),
+}
+
+impl Decryptor {
+    fn new(cipher: &Cipher, suite: CipherSuite, prefix: &[u8]) -> Self {
+        match suite {
+            CipherSuite::Aes256Gcm => {
+                Decryptor::Aes(Box::new(DecryptorBE32::from_aead(cipher.aes.clone(), GenericArray::from_slice(prefix))))
+            }
+            CipherSuite::XChaCha20Poly1305 => {
+                Decryptor::XChaCha(Box::new(DecryptorBE32::from_aead(cipher.xchacha.clone(), GenericArray::from_slice(prefix))))
+            }
+        }
+    }
+
+    fn next(&mut self, payload: Payload) -> io::Result<Vec<u8>> {
+        match self {
+            Decryptor::Aes(d) => d.decrypt_next(payload),
+            Decryptor::XChaCha(d) => d.decrypt_next(payload),
+        }
+        .map_err(|_| invalid_data())
+    }
+
+    fn last(self, payload: Payload) -> io::Result<Vec<u8>> {
+        match self {
+            Decryptor::Aes(d) => d.decrypt_last(payload),
+            Decryptor::XChaCha(d) => d.decrypt_last(payload),
+        }
+        .map_err(|_| invalid_data())
+    }
+}
+
+pub(crate) struct StreamWriter<W: Write> {
+    inner: W,
+    encryptor: Encryptor,
+    header: Vec<u8>,
+    buffer: Plaintext,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(cipher: &Cipher, mut inner: W) -> io::Result<Self> {
+        let suite = cipher.suite();
+        let mut header = vec![0u8; PREFIX_OFFSET + prefix_len(suite)];
+        header[..MAGIC_LEN].copy_from_slice(magic(suite));
+        header[MAGIC_LEN..PREFIX_OFFSET].copy_from_slice(&(CHUNK_SIZE as u32).to_le_bytes());
+        OsRng.fill_bytes(&mut header[PREFIX_OFFSET..]);
+        inner.write_all(&header)?;
+
+        Ok(StreamWriter {
+            inner,
+            encryptor: Encryptor::new(cipher, &header[PREFIX_OFFSET..]),
+            header,
+            buffer: Zeroizing::new(Vec::with_capacity(CHUNK_SIZE)),
+        })
+    }
+
+    fn seal_chunk(&mut self) -> io::Result<()> {
+        let payload = Payload { msg: &self.buffer, aad: &self.header };
+        let chunk = self.encryptor.next(payload)?;
+        self.inner.write_all(&chunk)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    // Seals the final chunk. A writer dropped without finishing leaves a stream that fails to
+    // decrypt instead of one that silently ends early.
+    pub fn finish(self) -> io::Result<W> {
+        let StreamWriter { mut inner, encryptor, header, buffer } = self;
+        let payload = Payload { msg: &buffer, aad: &header };
+        let chunk = encryptor.last(payload)?;
+        inner.write_all(&chunk)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for StreamWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // A full buffer is only sealed once more data arrives, the last chunk may be a full one.
+        if self.buffer.len() == CHUNK_SIZE && !data.is_empty() {
+            self.seal_chunk()?;
+        }
+        let n = data.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(crate) struct StreamReader<R: Read> {
+    inner: R,
+    decryptor: Option<Decryptor>,
+    header: Vec<u8>,
+    chunk_len: usize,
+    ciphertext: Vec<u8>,
+    plaintext: Plaintext,
+    pos: usize,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(cipher: &Cipher, mut inner: R) -> io::Result<Self> {
+        let mut header = vec![0u8; PREFIX_OFFSET];
+        inner.read_exact(&mut header).map_err(|_| invalid_data())?;
+        let suite = if header.starts_with(AES_MAGIC) {
+            CipherSuite::Aes256Gcm
+        } else if header.starts_with(XCHACHA_MAGIC) {
+            CipherSuite::XChaCha20Poly1305
+        } else {
+            return Err(invalid_data());
+        };
+
+        let mut chunk_len = [0u8; 4];
+        chunk_len.copy_from_slice(&header[MAGIC_LEN..PREFIX_OFFSET]);
+        let chunk_len = u32::from_le_bytes(chunk_len) as usize;
+        if chunk_len == 0 || chunk_len > MAX_CHUNK_SIZE {
+            return Err(invalid_data());
+        }
+
+        header.resize(PREFIX_OFFSET + prefix_len(suite), 0);
+        inner.read_exact(&mut header[PREFIX_OFFSET..]).map_err(|_| invalid_data())?;
+
+        Ok(StreamReader {
+            inner,
+            decryptor: Some(Decryptor::new(cipher, suite, &header[PREFIX_OFFSET..])),
+            header,
+            chunk_len,
+            ciphertext: Vec::with_capacity(chunk_len + TAG_LEN + 1),
+            plaintext: Zeroizing::new(Vec::new()),
+            pos: 0,
+        })
+    }
+
+    fn fill_ciphertext(&mut self, len: usize) -> io::Result<()> {
+        let mut filled = self.ciphertext.len();
+        self.ciphertext.resize(len, 0);
+        while filled < len {
+            match self.inner.read(&mut self.ciphertext[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        self.ciphertext.truncate(filled);
+        Ok(())
+    }
+
+    fn open_chunk(&mut self) -> io::Result<()> {
+        // Reads one byte past the chunk, it is the last one when nothing follows it.
+        let want = self.chunk_len + TAG_LEN + 1;
+        self.fill_ciphertext(want)?;
+        let last = self.ciphertext.len() < want;
+        let chunk_end = if last { self.ciphertext.len() } else { want - 1 };
+
+        let payload = Payload { msg: &self.ciphertext[..chunk_end], aad: &self.header };
+        let plaintext = if last {
+            self.decryptor.take().ok_or_else(invalid_data)?.last(payload)?
+        } else {
+            self.decryptor.as_mut().ok_or_else(invalid_data)?.next(payload)?
+        };
+
+        self.plaintext = Zeroizing::new(plaintext);
+        self.pos = 0;
+        self.ciphertext.drain(..chunk_end);
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+
+        let n = buf.len().min(self.plaintext.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Encrypts everything `source` yields into `path`, replacing it atomically.
+pub(crate) fn write_file(path: &Path, cipher: &Cipher, mut source: impl Read) -> Result<(), String> {
+    let tmp_path = crate::sibling_path(path, ".tmp");
+    let file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+    let mut writer = StreamWriter::new(cipher, BufWriter::new(file)).map_err(|e| e.to_string())?;
+
+    let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    loop {
+        let n = source.read(&mut buffer[..]).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n]).map_err(|e| e.to_string())?;
+    }
+
+    let file = writer
+        .finish()
+        .and_then(|w| w.into_inner().map_err(|e| e.into_error()))
+        .map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
+
+pub(crate) fn read_file(path: &Path, cipher: &Cipher) -> Result<Plaintext, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len() as usize;
+    let mut reader = StreamReader::new(cipher, BufReader::new(file)).map_err(|_| DECRYPT_FAILED.to_string())?;
+
+    // The ciphertext is always longer than the plaintext, so the buffer never reallocates and
+    // leaves no unwiped copy behind.
+    let mut plaintext = Zeroizing::new(Vec::with_capacity(len));
+    reader.read_to_end(&mut plaintext).map_err(|_| DECRYPT_FAILED.to_string())?;
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUITES: [CipherSuite; 2] = [CipherSuite::Aes256Gcm, CipherSuite::XChaCha20Poly1305];
+
+    fn cipher(suite: CipherSuite) -> Cipher {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Cipher::new(&key, suite).unwrap()
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn seal(cipher: &Cipher, plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = StreamWriter::new(cipher, Vec::new()).unwrap();
+        writer.write_all(plaintext).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn open(cipher: &Cipher, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        StreamReader::new(cipher, sealed)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    // The header and every full chunk as they sit in the stream, the last chunk is whatever follows.
+    fn split(suite: CipherSuite, sealed: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let header_len = PREFIX_OFFSET + prefix_len(suite);
+        let chunks = sealed[header_len..].chunks(CHUNK_SIZE + TAG_LEN).map(|c| c.to_vec()).collect();
+        (sealed[..header_len].to_vec(), chunks)
+    }
+
+    #[test]
+    fn round_trips_around_chunk_boundaries() {
+        for suite in SUITES {
+            let cipher = cipher(suite);
+            for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE + 17] {
+                let plaintext = data(len);
+                let sealed = seal(&cipher, &plaintext);
+                let chunks = len.div_ceil(CHUNK_SIZE).max(1);
+                assert_eq!(sealed.len(), PREFIX_OFFSET + prefix_len(suite) + len + chunks * TAG_LEN);
+                assert_eq!(open(&cipher, &sealed).unwrap(), plaintext, "{} bytes", len);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_a_stream_cut_at_a_chunk_boundary() {
+        for suite in SUITES {
+            let cipher = cipher(suite);
+            let sealed = seal(&cipher, &data(2 * CHUNK_SIZE + 5));
+            let (header, chunks) = split(suite, &sealed);
+            assert_eq!(chunks.len(), 3);
+
+            // Each remaining chunk was sealed as one that more follow, none passes for the last.
+            for keep in 0..chunks.len() {
+                let cut = [header.clone(), chunks[..keep].concat()].concat();
+                assert!(open(&cipher, &cut).is_err(), "{} chunks", keep);
+            }
+            assert!(open(&cipher, &sealed[..sealed.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_reordered_and_duplicated_chunks() {
+        for suite in SUITES {
+            let cipher = cipher(suite);
+            let sealed = seal(&cipher, &data(3 * CHUNK_SIZE + 9));
+            let (header, chunks) = split(suite, &sealed);
+            assert_eq!(open(&cipher, &[header.clone(), chunks.concat()].concat()).unwrap().len(), 3 * CHUNK_SIZE + 9);
+
+            let swapped = [&header, &chunks[1], &chunks[0], &chunks[2], &chunks[3]].map(|c| c.as_slice()).concat();
+            assert!(open(&cipher, &swapped).is_err());
+
+            let repeated = [&header, &chunks[0], &chunks[0], &chunks[2], &chunks[3]].map(|c| c.as_slice()).concat();
+            assert!(open(&cipher, &repeated).is_err());
+
+            let appended = [&header, &chunks[0], &chunks[1], &chunks[2], &chunks[2], &chunks[3]].map(|c| c.as_slice()).concat();
+            assert!(open(&cipher, &appended).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_a_tampered_header() {
+        for suite in SUITES {
+            let cipher = cipher(suite);
+            let sealed = seal(&cipher, &data(CHUNK_SIZE + 100));
+
+            let mut prefix = sealed.clone();
+            prefix[PREFIX_OFFSET] ^= 1;
+            assert!(open(&cipher, &prefix).is_err());
+
+            // A different chunk length moves every boundary and no longer matches the associated data.
+            for chunk_len in [CHUNK_SIZE / 2, CHUNK_SIZE * 2, 0, MAX_CHUNK_SIZE + 1] {
+                let mut resized = sealed.clone();
+                resized[MAGIC_LEN..PREFIX_OFFSET].copy_from_slice(&(chunk_len as u32).to_le_bytes());
+                assert!(open(&cipher, &resized).is_err(), "chunk length {}", chunk_len);
+            }
+
+            // Claiming the other suite changes the nonce layout and the key it is opened with.
+            let mut relabeled = sealed.clone();
+            relabeled[..MAGIC_LEN].copy_from_slice(magic(match suite {
+                CipherSuite::Aes256Gcm => CipherSuite::XChaCha20Poly1305,
+                CipherSuite::XChaCha20Poly1305 => CipherSuite::Aes256Gcm,
+            }));
+            assert!(open(&cipher, &relabeled).is_err());
+
+            let mut chunk = sealed.clone();
+            chunk[PREFIX_OFFSET + prefix_len(suite) + 10] ^= 1;
+            assert!(open(&cipher, &chunk).is_err());
+        }
+    }
+}
@@ -0,0 +1,339 @@
+const COMMON_WORDS: [&str; 96] = [
+    "password", "passw0rd", "qwerty", "letmein", "welcome", "admin", "login", "master",
+    "dragon", "monkey", "football", "baseball", "soccer", "hockey", "shadow", "sunshine",
+    "princess", "iloveyou", "love", "secret", "trustno1", "superman", "batman", "starwars",
+    "pokemon", "computer", "internet", "freedom", "whatever", "hello", "charlie", "michael",
+    "jessica", "ashley", "daniel", "thomas", "jordan", "hunter", "summer", "winter",
+    "spring", "autumn", "flower", "banana", "orange", "cookie", "cheese", "pepper",
+    "chocolate", "purple", "yellow", "silver", "golden", "diamond", "angel", "tigger",
+    "killer", "ninja", "mustang", "harley", "ranger", "buster", "jennifer", "maggie",
+    "ginger", "hannah", "samsung", "apple", "google", "facebook", "access", "default",
+    "guest", "root", "user", "test", "changeme", "private", "notes", "noetiq",
+    "vault", "money", "family", "friend", "forever", "matrix", "zxcvbn", "asdfgh",
+    "house", "music", "pass", "word", "god", "sex", "baby", "king",
+];
+
+const KEYBOARD_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+const MIN_HINT_OVERLAP: usize = 4;
+
+fn default_min_length() -> usize {
+    8
+}
+
+fn default_min_score() -> u8 {
+    2
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub(crate) struct PasswordPolicy {
+    #[serde(default = "default_min_length")]
+    pub min_length: usize,
+    #[serde(default = "default_min_score")]
+    pub min_score: u8,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: default_min_length(),
+            min_score: default_min_score(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Default)]
+pub(crate) struct PasswordStrength {
+    pub score: u8,
+    pub entropy_bits: f64,
+    pub feedback: Vec<String>,
+    pub hint_warning: Option<String>,
+    pub meets_policy: bool,
+}
+
+#[derive(PartialEq)]
+enum Pattern {
+    Word,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Year,
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '0' => 'o',
+        '1' | '!' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' => 't',
+        _ => c.to_ascii_lowercase(),
+    }
+}
+
+fn pool_size(password: &str) -> f64 {
+    let mut pool = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') {
+        pool += 33;
+    }
+    if !password.is_ascii() {
+        pool += 100;
+    }
+    pool.max(1) as f64
+}
+
+fn run_len(chars: &[char], start: usize, step: impl Fn(char, char) -> bool) -> usize {
+    let mut len = 1;
+    while start + len < chars.len() && step(chars[start + len - 1], chars[start + len]) {
+        len += 1;
+    }
+    len
+}
+
+fn keyboard_run(lower: &[char], start: usize) -> usize {
+    let mut best = 0;
+    for row in KEYBOARD_ROWS {
+        let row: Vec<char> = row.chars().collect();
+        for offset in 0..row.len() {
+            let len = lower[start..]
+                .iter()
+                .zip(&row[offset..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            best = best.max(len);
+        }
+    }
+    best
+}
+
+fn match_at(raw: &[char], normalized: &str, start: usize) -> Option<(Pattern, usize, f64)> {
+    let lower: Vec<char> = raw.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let rest: String = normalized.chars().skip(start).collect();
+
+    let word = COMMON_WORDS
+        .iter()
+        .filter(|word| word.len() >= 3 && rest.starts_with(*word))
+        .max_by_key(|word| word.len());
+    if let Some(word) = word {
+        let varied = raw[start..start + word.len()]
+            .iter()
+            .any(|c| c.is_ascii_uppercase() || !c.is_ascii_alphabetic());
+        let bits = (COMMON_WORDS.len() as f64).log2() + if varied { 2.0 } else { 0.0 };
+        return Some((Pattern::Word, word.len(), bits));
+    }
+
+    let year: String = raw[start..].iter().take(4).collect();
+    if year.len() == 4 && (year.starts_with("19") || year.starts_with("20")) && year.chars().all(|c| c.is_ascii_digit()) {
+        return Some((Pattern::Year, 4, 200f64.log2()));
+    }
+
+    let repeat = run_len(&lower, start, |a, b| a == b);
+    if repeat >= 3 {
+        return Some((Pattern::Repeat, repeat, pool_size(&raw[start].to_string()).log2() + (repeat as f64).log2()));
+    }
+
+    let ascending = run_len(&lower, start, |a, b| b as u32 == a as u32 + 1);
+    let descending = run_len(&lower, start, |a, b| a as u32 == b as u32 + 1);
+    let sequence = ascending.max(descending);
+    if sequence >= 3 {
+        return Some((Pattern::Sequence, sequence, 26f64.log2() + (sequence as f64).log2()));
+    }
+
+    let keyboard = keyboard_run(&lower, start);
+    if keyboard >= 4 {
+        return Some((Pattern::Keyboard, keyboard, 47f64.log2() + (keyboard as f64).log2()));
+    }
+
+    None
+}
+
+pub(crate) fn evaluate(password: &str, hint: &str, policy: &PasswordPolicy) -> PasswordStrength {
+    let raw: Vec<char> = password.chars().collect();
+    let normalized: String = raw.iter().map(|c| unleet(*c)).collect();
+    let char_bits = pool_size(password).log2();
+
+    let mut found = Vec::new();
+    let mut entropy_bits = 0.0;
+    let mut i = 0;
+    while i < raw.len() {
+        match match_at(&raw, &normalized, i) {
+            Some((pattern, len, bits)) => {
+                entropy_bits += bits;
+                i += len;
+                if !found.contains(&pattern) {
+                    found.push(pattern);
+                }
+            }
+            None => {
+                entropy_bits += char_bits;
+                i += 1;
+            }
+        }
+    }
+
+    let score = match entropy_bits {
+        b if b < 28.0 => 0,
+        b if b < 36.0 => 1,
+        b if b < 60.0 => 2,
+        b if b < 80.0 => 3,
+        _ => 4,
+    };
+
+    let mut feedback = Vec::new();
+    for pattern in &found {
+        feedback.push(
+            match pattern {
+                Pattern::Word => "Avoid common words and passwords, even with substitutions like @ for a",
+                Pattern::Sequence => "Avoid sequences like abc or 123",
+                Pattern::Repeat => "Avoid repeated characters like aaa",
+                Pattern::Keyboard => "Avoid keyboard patterns like qwerty",
+                Pattern::Year => "Avoid years, they are easy to guess",
+            }
+            .to_string(),
+        );
+    }
+    if raw.len() < 12 {
+        feedback.push("Longer passwords are stronger, consider a passphrase of several words".into());
+    }
+    if pool_size(password) < 62.0 {
+        feedback.push("Mix upper and lower case letters, digits and symbols".into());
+    }
+
+    let mut meets_policy = true;
+    if raw.len() < policy.min_length.max(1) {
+        meets_policy = false;
+        feedback.insert(0, format!("Use at least {} characters", policy.min_length.max(1)));
+    }
+    if score < policy.min_score {
+        meets_policy = false;
+        feedback.insert(0, "This password is too weak for the current policy".into());
+    }
+
+    PasswordStrength {
+        score,
+        entropy_bits,
+        feedback,
+        hint_warning: hint_warning(password, hint),
+        meets_policy,
+    }
+}
+
+fn longest_common_substring(a: &[char], b: &[char]) -> usize {
+    let mut best = 0;
+    let mut previous = vec![0; b.len() + 1];
+    for x in a {
+        let mut current = vec![0; b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            if x == y {
+                current[j + 1] = previous[j] + 1;
+                best = best.max(current[j + 1]);
+            }
+        }
+        previous = current;
+    }
+    best
+}
+
+pub(crate) fn hint_warning(password: &str, hint: &str) -> Option<String> {
+    if password.is_empty() || hint.is_empty() {
+        return None;
+    }
+
+    let password: Vec<char> = password.chars().map(unleet).collect();
+    let hint: Vec<char> = hint.chars().map(unleet).collect();
+
+    let overlap = longest_common_substring(&password, &hint);
+    if overlap == password.len() {
+        Some("The hint contains the password".into())
+    } else if overlap >= MIN_HINT_OVERLAP.max(password.len() / 2) {
+        Some(format!("The hint reveals {} of {} characters of the password", overlap, password.len()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strength(password: &str) -> PasswordStrength {
+        evaluate(password, "", &PasswordPolicy::default())
+    }
+
+    fn mentions(strength: &PasswordStrength, advice: &str) -> bool {
+        strength.feedback.iter().any(|f| f.starts_with(advice))
+    }
+
+    #[test]
+    fn common_words_are_weak_even_with_substitutions() {
+        for password in ["password", "P@ssw0rd", "letmein", "Dragon"] {
+            let result = strength(password);
+            assert_eq!(result.score, 0, "{}", password);
+            assert!(mentions(&result, "Avoid common words"), "{}", password);
+            assert!(!result.meets_policy, "{}", password);
+        }
+        assert!(strength("Sunshine2019").entropy_bits < strength("Vqnrhzlx2k7w").entropy_bits);
+    }
+
+    #[test]
+    fn repeats_and_sequences_count_for_little() {
+        let repeated = strength("aaaaaaaaaaaaaaaa");
+        assert_eq!(repeated.score, 0);
+        assert!(mentions(&repeated, "Avoid repeated characters"));
+
+        let sequential = strength("abcdefghijklmnop");
+        assert_eq!(sequential.score, 0);
+        assert!(mentions(&sequential, "Avoid sequences"));
+        assert!(mentions(&strength("9876543210"), "Avoid sequences"));
+        assert!(mentions(&strength("tyuiopghjkl"), "Avoid keyboard patterns"));
+
+        let random = strength("hT9#qLm2!vRx7&Kp");
+        assert!(random.score >= 3);
+        assert!(random.entropy_bits > 4.0 * sequential.entropy_bits);
+        assert!(random.meets_policy);
+    }
+
+    #[test]
+    fn hint_overlapping_the_password_is_flagged() {
+        assert_eq!(hint_warning("tigerlily42", "it is tigerlily42").as_deref(), Some("The hint contains the password"));
+        assert_eq!(
+            hint_warning("tigerlily42", "the flower, tigerlily").as_deref(),
+            Some("The hint reveals 9 of 11 characters of the password")
+        );
+        // Substitutions do not hide the overlap.
+        assert!(hint_warning("t1g3rl1ly42", "tigerlily").is_some());
+        assert!(hint_warning("tigerlily42", "my favourite flower").is_none());
+        assert!(hint_warning("tigerlily42", "").is_none());
+        assert!(evaluate("tigerlily42", "tigerlily42", &PasswordPolicy::default()).hint_warning.is_some());
+    }
+
+    #[test]
+    fn policy_rejects_passwords_below_its_minimums() {
+        let strict = PasswordPolicy { min_length: 12, min_score: 4 };
+        let fair = strength("Vqnrhzlx2k7w");
+        assert!(fair.meets_policy);
+        assert!(fair.score < 4);
+
+        let result = evaluate("Vqnrhzlx2k7w", "", &strict);
+        assert!(!result.meets_policy);
+        assert_eq!(result.feedback[0], "This password is too weak for the current policy");
+
+        let short = evaluate("hT9#qLm2!vR", "", &strict);
+        assert!(!short.meets_policy);
+        assert!(short.feedback.contains(&"Use at least 12 characters".to_string()));
+
+        assert!(evaluate("hT9#qLm2!vRx7&Kp@4zW", "", &strict).meets_policy);
+        // A policy of zero still asks for one character.
+        assert!(!evaluate("", "", &PasswordPolicy { min_length: 0, min_score: 0 }).meets_policy);
+    }
+}
@@ -0,0 +1,94 @@
+use super::remote::{Listing, Object, Remote};
+use crate::atomic_write;
+use std::fs;
+use std::io::ErrorKind;
+use std::fs::Metadata;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+const SYNC_FOLDER: &str = "noetiq-sync";
+
+pub(crate) struct FolderRemote {
+    root: PathBuf,
+}
+
+impl FolderRemote {
+    pub fn new(path: &str) -> Result<Self, String> {
+        let base = PathBuf::from(path);
+        if !base.is_dir() {
+            return Err("The sync folder does not exist".into());
+        }
+        let root = base.join(SYNC_FOLDER);
+        fs::create_dir_all(&root).map_err(|_| "Failed to create the sync folder".to_string())?;
+        Ok(FolderRemote { root })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        name.split('/').fold(self.root.clone(), |path, part| path.join(part))
+    }
+}
+
+// Size and modification time, like most web servers build theirs.
+fn etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("{:x}-{:x}", metadata.len(), modified.as_nanos())
+}
+
+impl Remote for FolderRemote {
+    fn list(&self, prefix: &str) -> Result<Vec<Listing>, String> {
+        let entries = match fs::read_dir(self.path(prefix)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let mut listings = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let metadata = entry.metadata().map_err(|e| e.to_string())?;
+            if !metadata.is_file() {
+                continue;
+            }
+            // Leftovers of interrupted writes, ours or the sync tool's.
+            if let Some(name) = entry.file_name().to_str().filter(|n| !n.ends_with(".tmp") && !n.starts_with('.')) {
+                listings.push(Listing { name: name.to_string(), etag: etag(&metadata) });
+            }
+        }
+        Ok(listings)
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Object>, String> {
+        let path = self.path(name);
+        // Taken before reading, a file replaced in between only costs a download next time.
+        let etag = match fs::metadata(&path) {
+            Ok(metadata) => etag(&metadata),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(Object { data, etag })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        atomic_write(&path, data)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
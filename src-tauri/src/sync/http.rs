@@ -0,0 +1,84 @@
+use std::thread;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(60);
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+pub(super) fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(TRANSFER_TIMEOUT)
+        .timeout_write(TRANSFER_TIMEOUT)
+        .build()
+}
+
+// Plain http is only accepted for a server on this machine, anywhere else it would expose the
+// credentials and the access pattern. Returns the host and port part of the address.
+pub(super) fn check_url(url: &str) -> Result<&str, String> {
+    let rest = match url.strip_prefix("https://") {
+        Some(rest) => rest,
+        None => url
+            .strip_prefix("http://")
+            .ok_or("The server address must start with https://")?,
+    };
+
+    let authority = rest.split('/').next().unwrap_or_default();
+    if authority.is_empty() {
+        return Err("The server address has no host".into());
+    }
+    if authority.contains('@') {
+        return Err("Enter the user name in its own field, not in the address".into());
+    }
+    if url.starts_with("http://") {
+        let host = if authority.starts_with('[') {
+            authority.split(']').next().unwrap_or_default().trim_start_matches('[')
+        } else {
+            authority.split(':').next().unwrap_or_default()
+        };
+        if !matches!(host, "localhost" | "127.0.0.1" | "::1") {
+            return Err("Only a server on this computer may use http://, use https://".into());
+        }
+    }
+    Ok(authority)
+}
+
+// Sends a request, retrying dropped connections and server hiccups. Any HTTP status is returned
+// to the caller.
+pub(super) fn send(
+    agent: &ureq::Agent,
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<ureq::Response, String> {
+    let mut attempt = 1;
+    loop {
+        let mut request = agent.request(method, url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+
+        let result = if body.is_empty() && method != "PUT" {
+            request.call()
+        } else {
+            request.send_bytes(body)
+        };
+        let retry = match &result {
+            Ok(_) => false,
+            Err(ureq::Error::Status(code, _)) => *code >= 500 || *code == 429,
+            Err(ureq::Error::Transport(_)) => true,
+        };
+        if retry && attempt < ATTEMPTS {
+            thread::sleep(RETRY_DELAY * attempt);
+            attempt += 1;
+            continue;
+        }
+
+        return match result {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(response),
+            Err(ureq::Error::Transport(e)) => Err(format!("Cannot reach the server: {}", e)),
+        };
+    }
+}
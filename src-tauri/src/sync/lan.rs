@@ -0,0 +1,654 @@
+use super::folder::FolderRemote;
+use super::is_safe_name;
+use super::remote::{Listing, Object, Remote};
+use crate::attachments;
+use crate::secret::Plaintext;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use hmac::{Hmac, Mac};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+pub(crate) const DEFAULT_PORT: u16 = 47474;
+const PROTOCOL: &str = "noetiq-lan-1";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+// A peer has this long to authenticate, and only a few can be connected at once.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_CONNECTIONS: usize = 8;
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(200);
+// Nothing is allocated for an unauthenticated peer beyond a small handshake message.
+const HELLO_LIMIT: usize = 4096;
+const FRAME_LIMIT: usize = attachments::MAX_SIZE + 1024 * 1024;
+const PAIRING_TIME: Duration = Duration::from_secs(5 * 60);
+// Each attempt is one online guess at the code, the exchange reveals nothing to test guesses
+// against offline.
+const PAIRING_ATTEMPTS: u32 = 3;
+// The objects the hosting device serves, next to the vault folders.
+const HUB_FOLDER: &str = "lan-sync";
+
+// A device that paired with this one. The secret came out of the pairing exchange and stands in
+// for the code from then on.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct LanPeer {
+    pub id: String,
+    pub secret: Zeroizing<String>,
+    pub paired: i64,
+}
+
+#[derive(Serialize)]
+pub(crate) struct LanHostStatus {
+    // Whether sync is set up to host on this device.
+    configured: bool,
+    running: bool,
+    address: Option<String>,
+    code: Option<String>,
+    peers: usize,
+}
+
+type PairedHandler = Box<dyn Fn(&LanPeer) -> Result<(), String> + Send + Sync>;
+
+// What the hosting device does when a peer pairs, which must be saved, and after a peer synced.
+pub(crate) struct HostEvents {
+    pub paired: PairedHandler,
+    pub served: Box<dyn Fn() + Send + Sync>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    protocol: String,
+    // Empty when pairing.
+    peer: String,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Answer {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    confirm: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Confirm {
+    confirm: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    List { prefix: String },
+    Get { name: String },
+    // The object follows in the next frame.
+    Put { name: String },
+    Delete { name: String },
+}
+
+// The object of a get follows in the next frame when `etag` is set.
+#[derive(Serialize, Deserialize, Default)]
+struct Reply {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    peer: Option<String>,
+    #[serde(default)]
+    listings: Vec<(String, String)>,
+    #[serde(default)]
+    etag: Option<String>,
+}
+
+pub(super) fn hub(cache_dir: &Path) -> Result<FolderRemote, String> {
+    let dir = cache_dir.with_file_name(HUB_FOLDER);
+    std::fs::create_dir_all(&dir).map_err(|_| "Failed to create the LAN sync folder".to_string())?;
+    FolderRemote::new(&dir.to_string_lossy())
+}
+
+fn write_frame(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+    let len = u32::try_from(data.len()).map_err(|_| "Too much data to send at once".to_string())?;
+    stream
+        .write_all(&len.to_be_bytes())
+        .and_then(|_| stream.write_all(data))
+        .map_err(|_| "The connection to the other device was lost".to_string())
+}
+
+fn read_frame(stream: &mut TcpStream, limit: usize) -> Result<Vec<u8>, String> {
+    let lost = |_| "The connection to the other device was lost".to_string();
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).map_err(lost)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > limit {
+        return Err("The other device sent too much data".into());
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).map_err(lost)?;
+    Ok(data)
+}
+
+fn write_json<T: Serialize>(stream: &mut TcpStream, value: &T) -> Result<(), String> {
+    write_frame(stream, &serde_json::to_vec(value).map_err(|e| e.to_string())?)
+}
+
+fn read_json<T: DeserializeOwned>(stream: &mut TcpStream) -> Result<T, String> {
+    serde_json::from_slice(&read_frame(stream, HELLO_LIMIT)?).map_err(|_| "Invalid answer from the other device".to_string())
+}
+
+// SPAKE2, so only a peer that knows the password ends up with the same key, and a wrong guess
+// teaches an eavesdropper nothing. The client is side A, the host side B.
+fn start_spake(password: &[u8], is_host: bool) -> (Spake2<Ed25519Group>, String) {
+    let password = Password::new(password);
+    let client = Identity::new(format!("{} client", PROTOCOL).as_bytes());
+    let host = Identity::new(format!("{} host", PROTOCOL).as_bytes());
+    let (spake, message) = if is_host {
+        Spake2::<Ed25519Group>::start_b(&password, &client, &host)
+    } else {
+        Spake2::<Ed25519Group>::start_a(&password, &client, &host)
+    };
+    (spake, hex::encode(message))
+}
+
+// The transcript every key is derived from. `client` and `host` are the two messages.
+fn finish_spake(spake: Spake2<Ed25519Group>, other: &str, peer: &str, client: &str, host: &str) -> Result<[u8; 32], String> {
+    let invalid = || "Invalid answer from the other device".to_string();
+    let other = hex::decode(other).map_err(|_| invalid())?;
+    let key = Zeroizing::new(spake.finish(&other).map_err(|_| invalid())?);
+
+    let mut hash = Sha256::new();
+    for part in [PROTOCOL.as_bytes(), peer.as_bytes(), client.as_bytes(), host.as_bytes(), &key[..]] {
+        hash.update((part.len() as u64).to_be_bytes());
+        hash.update(part);
+    }
+    Ok(hash.finalize().into())
+}
+
+fn derive(transcript: &[u8; 32], label: &str) -> Zeroizing<[u8; 32]> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(transcript).expect("HMAC accepts any key length");
+    mac.update(label.as_bytes());
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+fn confirmation(transcript: &[u8; 32], role: &str) -> Hmac<Sha256> {
+    let key = derive(transcript, "confirm");
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key[..]).expect("HMAC accepts any key length");
+    mac.update(role.as_bytes());
+    mac
+}
+
+fn confirm(transcript: &[u8; 32], role: &str) -> String {
+    hex::encode(confirmation(transcript, role).finalize().into_bytes())
+}
+
+fn check_confirm(transcript: &[u8; 32], role: &str, received: &str) -> bool {
+    let received = hex::decode(received).unwrap_or_default();
+    confirmation(transcript, role).verify_slice(&received).is_ok()
+}
+
+// Frames after the handshake, each direction with its own key and a counter as nonce, so frames
+// cannot be replayed, dropped or reordered unnoticed.
+struct Channel {
+    stream: TcpStream,
+    sender: ChaCha20Poly1305,
+    receiver: ChaCha20Poly1305,
+    sent: u64,
+    received: u64,
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    Nonce::from(nonce)
+}
+
+impl Channel {
+    fn new(stream: TcpStream, transcript: &[u8; 32], is_host: bool) -> Channel {
+        let to_host = ChaCha20Poly1305::new(derive(transcript, "client to host")[..].into());
+        let to_client = ChaCha20Poly1305::new(derive(transcript, "host to client")[..].into());
+        let (sender, receiver) = if is_host { (to_client, to_host) } else { (to_host, to_client) };
+        Channel { stream, sender, receiver, sent: 0, received: 0 }
+    }
+
+    fn send(&mut self, plaintext: &[u8]) -> Result<(), String> {
+        let sealed = self
+            .sender
+            .encrypt(&nonce(self.sent), plaintext)
+            .map_err(|_| "Encryption failed".to_string())?;
+        self.sent += 1;
+        write_frame(&mut self.stream, &sealed)
+    }
+
+    fn receive(&mut self) -> Result<Zeroizing<Vec<u8>>, String> {
+        let sealed = read_frame(&mut self.stream, FRAME_LIMIT)?;
+        let plaintext = self
+            .receiver
+            .decrypt(&nonce(self.received), sealed.as_slice())
+            .map_err(|_| "The connection to the other device was tampered with".to_string())?;
+        self.received += 1;
+        Ok(Zeroizing::new(plaintext))
+    }
+
+    fn send_json<T: Serialize>(&mut self, value: &T) -> Result<(), String> {
+        self.send(&serde_json::to_vec(value).map_err(|e| e.to_string())?)
+    }
+
+    fn receive_json<T: DeserializeOwned>(&mut self) -> Result<T, String> {
+        serde_json::from_slice(&self.receive()?).map_err(|_| "Invalid answer from the other device".to_string())
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream, String> {
+    let address = address.trim();
+    let with_port = if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    let unreachable = || format!("Cannot reach {}, check that it is hosting and on this network", with_port);
+    let target = with_port.to_socket_addrs().ok().and_then(|mut a| a.next()).ok_or_else(unreachable)?;
+    let stream = TcpStream::connect_timeout(&target, CONNECT_TIMEOUT).map_err(|_| unreachable())?;
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    Ok(stream)
+}
+
+// The client side of the handshake, ending with the host's welcome.
+fn open_channel(address: &str, peer: &str, password: &[u8]) -> Result<(Channel, [u8; 32], Reply), String> {
+    let mut stream = connect(address)?;
+    let (spake, message) = start_spake(password, false);
+    write_json(&mut stream, &Hello { protocol: PROTOCOL.into(), peer: peer.into(), message: message.clone() })?;
+
+    let answer: Answer = read_json(&mut stream)?;
+    if let Some(error) = answer.error {
+        return Err(error);
+    }
+    let transcript = finish_spake(spake, &answer.message, peer, &message, &answer.message)?;
+    if !check_confirm(&transcript, "host", &answer.confirm) {
+        return Err(if peer.is_empty() {
+            "Wrong pairing code".into()
+        } else {
+            "The other device no longer knows this one, pair them again".into()
+        });
+    }
+    write_json(&mut stream, &Confirm { confirm: confirm(&transcript, "client") })?;
+
+    let mut channel = Channel::new(stream, &transcript, false);
+    let welcome: Reply = channel.receive_json()?;
+    if let Some(error) = welcome.error {
+        return Err(error);
+    }
+    Ok((channel, transcript, welcome))
+}
+
+// Pairs with a hosting device showing `code`. Returns this device's peer id and secret there.
+pub(super) fn pair(address: &str, code: &str) -> Result<(String, Zeroizing<String>), String> {
+    let code: String = code.chars().filter(|c| c.is_ascii_digit()).collect();
+    let (_, transcript, welcome) = open_channel(address, "", code.as_bytes())?;
+    let peer = welcome.peer.filter(|p| is_safe_name(p)).ok_or("Invalid answer from the other device")?;
+    Ok((peer, Zeroizing::new(hex::encode(&derive(&transcript, "peer secret")[..]))))
+}
+
+// Syncs through a paired device on the same network, which keeps the objects.
+pub(crate) struct LanRemote {
+    channel: Mutex<Channel>,
+}
+
+impl LanRemote {
+    pub fn new(address: &str, peer: &str, secret: &str) -> Result<Self, String> {
+        if peer.is_empty() || secret.is_empty() {
+            return Err("Pair with the other device first".into());
+        }
+        let (channel, _, _) = open_channel(address, peer, secret.as_bytes())?;
+        Ok(LanRemote { channel: Mutex::new(channel) })
+    }
+
+    // Returns the reply, and the object a get found.
+    fn call(&self, request: &Request, data: Option<&[u8]>) -> Result<(Reply, Option<Plaintext>), String> {
+        let mut channel = self.channel.lock().unwrap_or_else(|e| e.into_inner());
+        channel.send_json(request)?;
+        if let Some(data) = data {
+            channel.send(data)?;
+        }
+        let reply: Reply = channel.receive_json()?;
+        if let Some(error) = reply.error {
+            return Err(error);
+        }
+        let object = match (request, &reply.etag) {
+            (Request::Get { .. }, Some(_)) => Some(channel.receive()?),
+            _ => None,
+        };
+        Ok((reply, object))
+    }
+}
+
+impl Remote for LanRemote {
+    fn list(&self, prefix: &str) -> Result<Vec<Listing>, String> {
+        let (reply, _) = self.call(&Request::List { prefix: prefix.into() }, None)?;
+        Ok(reply.listings.into_iter().map(|(name, etag)| Listing { name, etag }).collect())
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Object>, String> {
+        let (reply, object) = self.call(&Request::Get { name: name.into() }, None)?;
+        Ok(object.zip(reply.etag).map(|(mut data, etag)| Object { data: std::mem::take(&mut *data), etag }))
+    }
+
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.call(&Request::Put { name: name.into() }, Some(data)).map(|_| ())
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        self.call(&Request::Delete { name: name.into() }, None).map(|_| ())
+    }
+}
+
+struct Pairing {
+    code: Zeroizing<String>,
+    until: Instant,
+    attempts: u32,
+}
+
+struct Shared {
+    peers: Vec<LanPeer>,
+    pairing: Option<Pairing>,
+}
+
+impl Shared {
+    fn pairing(&mut self) -> Option<&mut Pairing> {
+        self.pairing = self.pairing.take().filter(|p| p.until > Instant::now() && p.attempts < PAIRING_ATTEMPTS);
+        self.pairing.as_mut()
+    }
+}
+
+struct Running {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+// The listener of the hosting device. Peers sync against the same objects this device syncs
+// with through a folder, so conflicts are handled exactly as with any other remote.
+#[derive(Default)]
+pub(crate) struct LanHost {
+    running: Mutex<Option<Running>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Every part of a name becomes a path component in the hub.
+fn check_name(name: &str) -> Result<&str, String> {
+    if name.split('/').all(is_safe_name) {
+        Ok(name)
+    } else {
+        Err("Invalid object name".into())
+    }
+}
+
+fn serve_requests(channel: &mut Channel, hub: &FolderRemote) -> Result<(), String> {
+    loop {
+        let request: Request = match channel.receive_json() {
+            Ok(request) => request,
+            // The peer closes the connection when its sync is done.
+            Err(_) => return Ok(()),
+        };
+        let mut reply = Reply::default();
+        let mut object = None;
+        let result = match &request {
+            Request::List { prefix } => check_name(prefix).and_then(|p| hub.list(p)).map(|listings| {
+                reply.listings = listings.into_iter().map(|l| (l.name, l.etag)).collect();
+            }),
+            Request::Get { name } => check_name(name).and_then(|n| hub.get(n)).map(|found| {
+                if let Some(found) = found {
+                    reply.etag = Some(found.etag);
+                    object = Some(found.data);
+                }
+            }),
+            Request::Put { name } => {
+                let data = channel.receive()?;
+                check_name(name).and_then(|n| hub.put(n, &data))
+            }
+            Request::Delete { name } => check_name(name).and_then(|n| hub.delete(n)),
+        };
+        if let Err(e) = result {
+            reply.error = Some(e);
+        }
+        channel.send_json(&reply)?;
+        if let Some(object) = object {
+            channel.send(&object)?;
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, shared: &Mutex<Shared>, hub: &FolderRemote, events: &HostEvents) -> Result<(), String> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let hello: Hello = read_json(&mut stream)?;
+    let pairing = hello.peer.is_empty();
+    let password = if hello.protocol != PROTOCOL {
+        Err("The other device runs a different version of Noetiq".to_string())
+    } else if pairing {
+        // Counted before the exchange, so connecting many times at once gets no extra guesses.
+        lock(shared)
+            .pairing()
+            .map(|p| {
+                p.attempts += 1;
+                p.code.clone()
+            })
+            .ok_or_else(|| "Start pairing on the other device first".to_string())
+    } else {
+        lock(shared)
+            .peers
+            .iter()
+            .find(|p| p.id == hello.peer)
+            .map(|p| p.secret.clone())
+            .ok_or_else(|| "The other device no longer knows this one, pair them again".to_string())
+    };
+    let password = match password {
+        Ok(password) => password,
+        Err(error) => return write_json(&mut stream, &Answer { error: Some(error), ..Answer::default() }),
+    };
+
+    let (spake, message) = start_spake(password.as_bytes(), true);
+    let transcript = finish_spake(spake, &hello.message, &hello.peer, &hello.message, &message)?;
+    write_json(&mut stream, &Answer { error: None, message, confirm: confirm(&transcript, "host") })?;
+
+    let confirmed = read_json::<Confirm>(&mut stream).is_ok_and(|c| check_confirm(&transcript, "client", &c.confirm));
+    if !confirmed {
+        return Err("A device failed to authenticate".into());
+    }
+    stream.set_read_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(IO_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut channel = Channel::new(stream, &transcript, true);
+    if pairing {
+        let peer = LanPeer {
+            id: Uuid::new_v4().to_string(),
+            secret: Zeroizing::new(hex::encode(&derive(&transcript, "peer secret")[..])),
+            paired: chrono::Utc::now().timestamp(),
+        };
+        if let Err(error) = (events.paired)(&peer) {
+            return channel.send_json(&Reply { error: Some(error), ..Reply::default() });
+        }
+        let mut shared = lock(shared);
+        shared.pairing = None;
+        shared.peers.push(peer.clone());
+        drop(shared);
+        return channel.send_json(&Reply { peer: Some(peer.id), ..Reply::default() });
+    }
+
+    channel.send_json(&Reply { peer: Some(hello.peer), ..Reply::default() })?;
+    let result = serve_requests(&mut channel, hub);
+    (events.served)();
+    result
+}
+
+// The address other devices on the network reach this one at. Connecting a UDP socket sends
+// nothing, it only picks the interface a packet would leave through.
+fn local_address() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    Some(socket.local_addr().ok()?.ip().to_string())
+}
+
+impl LanHost {
+    pub fn start(&self, port: u16, peers: Vec<LanPeer>, cache_dir: &Path, events: HostEvents) -> Result<(), String> {
+        self.stop();
+        let hub = hub(cache_dir)?;
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|_| format!("Port {} is already in use, choose another one", port))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::new(Mutex::new(Shared { peers, pairing: None }));
+        let (thread_stop, thread_shared) = (stop.clone(), shared.clone());
+        let (hub, events) = (Arc::new(hub), Arc::new(events));
+        let connections = Arc::new(AtomicUsize::new(0));
+        // Each peer on a thread of its own, so one that is slow or never authenticates does not
+        // hold up the others.
+        thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if connections.load(Ordering::Relaxed) >= MAX_CONNECTIONS || stream.set_nonblocking(false).is_err() {
+                            continue;
+                        }
+                        connections.fetch_add(1, Ordering::Relaxed);
+                        let (connections, shared, hub, events) =
+                            (connections.clone(), thread_shared.clone(), hub.clone(), events.clone());
+                        thread::spawn(move || {
+                            let _ = serve(stream, &shared, &hub, &events);
+                            connections.fetch_sub(1, Ordering::Relaxed);
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                    Err(_) => thread::sleep(ACCEPT_INTERVAL),
+                }
+            }
+        });
+
+        *lock(&self.running) = Some(Running { port, stop, shared });
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(running) = lock(&self.running).take() {
+            running.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // A six digit code for the next device to pair, valid for a few minutes and a few attempts.
+    pub fn open_pairing(&self) -> Result<String, String> {
+        let running = lock(&self.running);
+        let running = running.as_ref().ok_or("Start hosting first")?;
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        lock(&running.shared).pairing = Some(Pairing {
+            code: Zeroizing::new(code.clone()),
+            until: Instant::now() + PAIRING_TIME,
+            attempts: 0,
+        });
+        Ok(code)
+    }
+
+    pub fn status(&self, configured: bool) -> LanHostStatus {
+        let running = lock(&self.running);
+        match running.as_ref() {
+            Some(running) => {
+                let mut shared = lock(&running.shared);
+                LanHostStatus {
+                    configured,
+                    running: true,
+                    address: local_address().map(|ip| format!("{}:{}", ip, running.port)),
+                    code: shared.pairing().map(|p| p.code.to_string()),
+                    peers: shared.peers.len(),
+                }
+            }
+            None => LanHostStatus { configured, running: false, address: None, code: None, peers: 0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both ends of the exchange, returning the client's and the host's transcripts.
+    fn exchange(client_password: &[u8], host_password: &[u8]) -> ([u8; 32], [u8; 32]) {
+        let (client, client_message) = start_spake(client_password, false);
+        let (host, host_message) = start_spake(host_password, true);
+        let host_transcript = finish_spake(host, &client_message, "", &client_message, &host_message).unwrap();
+        let client_transcript = finish_spake(client, &host_message, "", &client_message, &host_message).unwrap();
+        (client_transcript, host_transcript)
+    }
+
+    #[test]
+    fn matching_codes_give_the_same_key() {
+        let (client, host) = exchange(b"123456", b"123456");
+        assert_eq!(client, host);
+        assert!(check_confirm(&client, "host", &confirm(&host, "host")));
+        assert!(check_confirm(&host, "client", &confirm(&client, "client")));
+    }
+
+    #[test]
+    fn wrong_code_fails_confirmation() {
+        let (client, host) = exchange(b"123456", b"123457");
+        assert_ne!(client, host);
+        assert!(!check_confirm(&client, "host", &confirm(&host, "host")));
+        assert!(!check_confirm(&host, "client", &confirm(&client, "client")));
+    }
+
+    #[test]
+    fn loopback_host_serves_paired_remotes() {
+        let dir = std::env::temp_dir().join(format!("noetiq-lan-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address = format!("127.0.0.1:{}", port);
+
+        let paired = Arc::new(Mutex::new(Vec::new()));
+        let saved = paired.clone();
+        let host = LanHost::default();
+        let events = HostEvents {
+            paired: Box::new(move |peer| {
+                lock(&saved).push(peer.id.clone());
+                Ok(())
+            }),
+            served: Box::new(|| {}),
+        };
+        host.start(port, Vec::new(), &dir.join("cache"), events).unwrap();
+
+        let code = host.open_pairing().unwrap();
+        let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+        assert_eq!(pair(&address, &wrong).err().as_deref(), Some("Wrong pairing code"));
+        let (peer, secret) = pair(&address, &code).unwrap();
+        assert_eq!(*lock(&paired), vec![peer.clone()]);
+        assert!(LanRemote::new(&address, "someone-else", &secret).is_err());
+
+        // The second connection is served while the first one is still open.
+        let first = LanRemote::new(&address, &peer, &secret).unwrap();
+        let second = LanRemote::new(&address, &peer, &secret).unwrap();
+        first.put("vault/note.json", b"note").unwrap();
+        assert_eq!(second.get("vault/note.json").unwrap().unwrap().data, b"note");
+        let listed: Vec<String> = second.list("vault").unwrap().into_iter().map(|l| l.name).collect();
+        assert_eq!(listed, vec!["note.json".to_string()]);
+        second.delete("vault/note.json").unwrap();
+        assert!(first.get("vault/note.json").unwrap().is_none());
+        assert!(first.get("../outside").is_err());
+
+        host.stop();
+        drop((first, second));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use crate::secret::Plaintext;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+const CONFLICT_MARK: &str = "<b>Sync conflict:</b> this block was changed on two devices, both versions follow.";
+
+pub(super) struct Merged {
+    pub content: Plaintext,
+    pub conflicts: usize,
+}
+
+// The document's blocks in order, or None when it is not an Editor.js document whose blocks all
+// carry distinct ids. A new note is an empty object.
+fn blocks(document: &Value) -> Option<Vec<(&str, &Value)>> {
+    let Some(blocks) = document.get("blocks") else {
+        return document.is_object().then(Vec::new);
+    };
+    let mut seen = HashSet::new();
+    blocks
+        .as_array()?
+        .iter()
+        .map(|block| {
+            let id = block.get("id")?.as_str()?;
+            seen.insert(id).then_some((id, block))
+        })
+        .collect()
+}
+
+fn ids<'a>(blocks: &[(&'a str, &Value)]) -> Vec<&'a str> {
+    blocks.iter().map(|(id, _)| *id).collect()
+}
+
+fn block_id() -> String {
+    Uuid::new_v4().simple().to_string()[..10].to_string()
+}
+
+// Whether the blocks both sides kept are still in the same relative order.
+fn same_order(a: &[&str], b: &[&str]) -> bool {
+    let in_a: HashSet<&str> = a.iter().copied().collect();
+    let in_b: HashSet<&str> = b.iter().copied().collect();
+    a.iter().filter(|id| in_b.contains(*id)).eq(b.iter().filter(|id| in_a.contains(*id)))
+}
+
+// Follows the side that reordered blocks, then slots in the blocks only the other side has right
+// after the block that precedes them there.
+fn merge_order<'a>(base: &[&'a str], local: &[&'a str], remote: &[&'a str]) -> Vec<&'a str> {
+    let (mut order, other) = if same_order(base, local) && !same_order(base, remote) {
+        (remote.to_vec(), local)
+    } else {
+        (local.to_vec(), remote)
+    };
+
+    let mut placed: HashSet<&str> = order.iter().copied().collect();
+    for (i, id) in other.iter().enumerate() {
+        if placed.contains(id) {
+            continue;
+        }
+        let position = other[..i]
+            .iter()
+            .rev()
+            .find_map(|previous| order.iter().position(|o| o == previous))
+            .map_or(0, |p| p + 1);
+        order.insert(position, id);
+        placed.insert(id);
+    }
+    order
+}
+
+// Three-way merge of two Editor.js documents at block granularity. Blocks changed on one side
+// only take that change, an edit beats a deletion, and blocks changed differently on both sides
+// are kept twice behind a conflict mark.
+pub(super) fn merge_note(base: &[u8], local: &[u8], remote: &[u8]) -> Option<Merged> {
+    let base_document: Value = serde_json::from_slice(base).ok()?;
+    let mut local_document: Value = serde_json::from_slice(local).ok()?;
+    let remote_document: Value = serde_json::from_slice(remote).ok()?;
+
+    let base_blocks = blocks(&base_document)?;
+    let local_blocks = blocks(&local_document)?;
+    let remote_blocks = blocks(&remote_document)?;
+
+    let base_map: HashMap<&str, &Value> = base_blocks.iter().copied().collect();
+    let local_map: HashMap<&str, &Value> = local_blocks.iter().copied().collect();
+    let remote_map: HashMap<&str, &Value> = remote_blocks.iter().copied().collect();
+    let order = merge_order(&ids(&base_blocks), &ids(&local_blocks), &ids(&remote_blocks));
+
+    let mut merged = Vec::new();
+    let mut conflicts = 0;
+    for id in order {
+        let kept = match (base_map.get(id), local_map.get(id), remote_map.get(id)) {
+            (_, Some(l), Some(r)) if l == r => Some(*l),
+            (Some(b), Some(l), Some(r)) if l == b => Some(*r),
+            (Some(b), Some(l), Some(r)) if r == b => Some(*l),
+            (Some(b), Some(l), None) | (Some(b), None, Some(l)) if l == b => None,
+            (_, Some(l), None) | (_, None, Some(l)) => Some(*l),
+            (_, Some(l), Some(r)) => {
+                let mut copy = (*r).clone();
+                copy["id"] = json!(block_id());
+                merged.push(json!({ "id": block_id(), "type": "paragraph", "data": { "text": CONFLICT_MARK } }));
+                merged.push((*l).clone());
+                merged.push(copy);
+                conflicts += 1;
+                None
+            }
+            (_, None, None) => None,
+        };
+        if let Some(block) = kept {
+            merged.push(block.clone());
+        }
+    }
+
+    local_document["blocks"] = Value::Array(merged);
+    let content = Zeroizing::new(serde_json::to_vec(&local_document).ok()?);
+    Some(Merged { content, conflicts })
+}
+
+// Title and icon take whichever side changed them, this device's when both did.
+pub(super) fn merge_meta(base: &Value, local: &Value, remote: &Value) -> Value {
+    let mut merged = local.clone();
+    for field in ["notetitle", "icon"] {
+        if local.get(field) == base.get(field) {
+            merged[field] = remote.get(field).cloned().unwrap_or(Value::Null);
+        }
+    }
+    let modified = |meta: &Value| meta.get("modified").and_then(Value::as_i64);
+    if let Some(latest) = modified(local).max(modified(remote)) {
+        merged["modified"] = json!(latest);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paragraph(id: &str, text: &str) -> Value {
+        json!({ "id": id, "type": "paragraph", "data": { "text": text } })
+    }
+
+    fn note(blocks: &[Value]) -> Vec<u8> {
+        serde_json::to_vec(&json!({ "time": 1, "blocks": blocks, "version": "2.30.8" })).unwrap()
+    }
+
+    fn merged_blocks(base: &[Value], local: &[Value], remote: &[Value]) -> (Vec<Value>, usize) {
+        let merged = merge_note(&note(base), &note(local), &note(remote)).unwrap();
+        let document: Value = serde_json::from_slice(&merged.content).unwrap();
+        (document["blocks"].as_array().unwrap().clone(), merged.conflicts)
+    }
+
+    fn texts(blocks: &[Value]) -> Vec<&str> {
+        blocks.iter().map(|b| b["data"]["text"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn disjoint_edits_merge_cleanly() {
+        let base = [paragraph("a", "one"), paragraph("b", "two"), paragraph("c", "three")];
+        let local = [paragraph("a", "one, edited here"), paragraph("b", "two"), paragraph("c", "three")];
+        let remote = [paragraph("a", "one"), paragraph("b", "two"), paragraph("d", "new there"), paragraph("c", "three, edited there")];
+
+        let (blocks, conflicts) = merged_blocks(&base, &local, &remote);
+        assert_eq!(conflicts, 0);
+        assert_eq!(texts(&blocks), ["one, edited here", "two", "new there", "three, edited there"]);
+        // Both directions agree.
+        let (reverse, _) = merged_blocks(&base, &remote, &local);
+        assert_eq!(texts(&reverse), texts(&blocks));
+    }
+
+    #[test]
+    fn a_block_edited_on_both_sides_is_kept_twice_behind_a_mark() {
+        let base = [paragraph("a", "one"), paragraph("b", "two")];
+        let local = [paragraph("a", "one, mine"), paragraph("b", "two")];
+        let remote = [paragraph("a", "one, theirs"), paragraph("b", "two")];
+
+        let (blocks, conflicts) = merged_blocks(&base, &local, &remote);
+        assert_eq!(conflicts, 1);
+        assert_eq!(texts(&blocks), [CONFLICT_MARK, "one, mine", "one, theirs", "two"]);
+        assert_eq!(blocks[1]["id"], "a");
+        // The copy and the mark get ids of their own, so the document keeps distinct ids.
+        let ids: HashSet<&str> = blocks.iter().map(|b| b["id"].as_str().unwrap()).collect();
+        assert_eq!(ids.len(), blocks.len());
+    }
+
+    #[test]
+    fn a_deletion_wins_over_an_unchanged_block() {
+        let base = [paragraph("a", "one"), paragraph("b", "two"), paragraph("c", "three")];
+        let deleted = [paragraph("a", "one"), paragraph("c", "three")];
+
+        for (local, remote) in [(&deleted[..], &base[..]), (&base[..], &deleted[..])] {
+            let (blocks, conflicts) = merged_blocks(&base, local, remote);
+            assert_eq!(conflicts, 0);
+            assert_eq!(texts(&blocks), ["one", "three"]);
+        }
+    }
+
+    #[test]
+    fn an_edit_wins_over_a_deletion() {
+        let base = [paragraph("a", "one"), paragraph("b", "two"), paragraph("c", "three")];
+        let deleted = [paragraph("a", "one"), paragraph("c", "three")];
+        let edited = [paragraph("a", "one"), paragraph("b", "two, edited"), paragraph("c", "three")];
+
+        for (local, remote) in [(&deleted[..], &edited[..]), (&edited[..], &deleted[..])] {
+            let (blocks, conflicts) = merged_blocks(&base, local, remote);
+            assert_eq!(conflicts, 0);
+            assert_eq!(texts(&blocks), ["one", "two, edited", "three"]);
+        }
+    }
+
+    #[test]
+    fn documents_without_distinct_block_ids_are_not_merged() {
+        let base = note(&[paragraph("a", "one")]);
+        let repeated = note(&[paragraph("a", "one"), paragraph("a", "two")]);
+        assert!(merge_note(&base, &repeated, &base).is_none());
+        assert!(merge_note(&base, b"not json", &base).is_none());
+    }
+}
@@ -0,0 +1,949 @@
+mod clock;
+mod folder;
+mod http;
+mod lan;
+mod merge;
+mod remote;
+mod s3;
+mod webdav;
+
+pub(crate) use lan::{HostEvents, LanHost, LanHostStatus, LanPeer};
+pub(crate) use remote::RemoteConfig;
+
+use crate::attachments::{self, AttachmentInfo};
+use crate::cipher::{Cipher, CipherSuite};
+use crate::crdt::{self, Ack, NoteLog};
+use crate::metadata::KdfParams;
+use crate::secret::{Plaintext, SecretKey};
+use crate::storage::{self, StorageSettings, VaultStore};
+use crate::{
+    cipher_from_key, derive_key, find_note, read_note, read_notes_list, shred, write_note, write_notes_list,
+    IdVaultEntry, Session, DECRYPT_FAILED, SALT_LEN,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::Utc;
+use clock::VectorClock;
+use hmac::{Hmac, Mac};
+use remote::{Listing, Remote};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+// Remote layout: the wrapped sync key, then one state object per device so two devices never
+// write the same object, and note contents stored once per revision.
+//   key.json
+//   devices/<device>
+//   vaults/<vault>/index/<device>
+//   vaults/<vault>/notes/<rev>
+//   vaults/<vault>/ops/<rev>
+//   vaults/<vault>/attachments/<id>
+const KEY_OBJECT: &str = "key.json";
+const DEVICES_PREFIX: &str = "devices";
+const VAULTS_PREFIX: &str = "vaults";
+const CONFLICT_SUFFIX: &str = " (conflict)";
+const CACHE_FOLDER: &str = "sync-cache";
+
+// Per vault, what this device last published, so local edits can be told from untouched notes.
+pub(crate) const SYNC_FILE: &str = "sync.json";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+struct Version {
+    rev: String,
+    clock: VectorClock,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    digest: String,
+    #[serde(default)]
+    meta: Value,
+}
+
+type Versions = BTreeMap<String, Version>;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+struct RootState {
+    vaults: Versions,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+struct VaultIndex {
+    notes: Versions,
+    #[serde(default)]
+    attachments: BTreeMap<String, AttachmentInfo>,
+}
+
+// Another device's state as last downloaded, only fetched again once its etag changes.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+struct Cached<T> {
+    etag: String,
+    value: T,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct VaultState {
+    notes: Versions,
+    #[serde(default)]
+    attachments: BTreeSet<String>,
+    #[serde(default)]
+    devices: BTreeMap<String, Cached<VaultIndex>>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct SyncConfig {
+    pub remote: RemoteConfig,
+    pub device_id: String,
+    key: Zeroizing<String>,
+    #[serde(default)]
+    vaults: Versions,
+    #[serde(default)]
+    devices: BTreeMap<String, Cached<RootState>>,
+    #[serde(default)]
+    pub last_sync: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct SyncStatus {
+    pub target: Option<String>,
+    pub last_sync: Option<i64>,
+    pub running: bool,
+    pub last_report: Option<SyncReport>,
+    pub last_error: Option<String>,
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+pub(crate) struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub merged: usize,
+    pub deleted: usize,
+    pub conflicts: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WrappedKey {
+    salt: String,
+    kdf: KdfParams,
+    key: String,
+}
+
+#[derive(Default)]
+struct Activity {
+    running: bool,
+    last_report: Option<SyncReport>,
+    last_error: Option<String>,
+}
+
+// Tracks the sync in progress across commands, and keeps two from running at once.
+#[derive(Default)]
+pub(crate) struct SyncMonitor {
+    activity: Mutex<Activity>,
+}
+
+impl SyncMonitor {
+    fn activity(&self) -> std::sync::MutexGuard<'_, Activity> {
+        self.activity.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn start(&self) -> Result<(), String> {
+        let mut activity = self.activity();
+        if activity.running {
+            return Err("A sync is already running".into());
+        }
+        activity.running = true;
+        Ok(())
+    }
+
+    pub fn finish(&self, result: &Result<SyncReport, String>) {
+        let mut activity = self.activity();
+        activity.running = false;
+        match result {
+            Ok(report) => {
+                activity.last_report = Some(report.clone());
+                activity.last_error = None;
+            }
+            Err(e) => activity.last_error = Some(e.clone()),
+        }
+    }
+}
+
+pub(crate) fn status(config: Option<&SyncConfig>, monitor: &SyncMonitor) -> SyncStatus {
+    let activity = monitor.activity();
+    SyncStatus {
+        target: config.map(|c| c.remote.describe()),
+        last_sync: config.and_then(|c| c.last_sync),
+        running: activity.running,
+        last_report: activity.last_report.clone(),
+        last_error: activity.last_error.clone(),
+    }
+}
+
+// Saves a device that paired with this one while it hosts.
+pub(crate) fn add_lan_peer(config: Option<&mut SyncConfig>, peer: &LanPeer) -> Result<(), String> {
+    match config.map(|c| &mut c.remote) {
+        Some(RemoteConfig::LanHost { peers, .. }) => {
+            peers.push(peer.clone());
+            Ok(())
+        }
+        _ => Err("This device no longer hosts LAN sync".into()),
+    }
+}
+
+pub(crate) fn cache_dir(session: &Session) -> PathBuf {
+    session.vault_dir.join(CACHE_FOLDER)
+}
+
+// Every device unwraps the same random sync key with the sync password, so devices keep their
+// own store passwords and changing one never touches the remote.
+pub(crate) fn connect(remote: RemoteConfig, password: &str, cache_dir: &Path) -> Result<SyncConfig, String> {
+    let remote = remote.pair()?;
+    let target = remote.open(cache_dir)?;
+    let key = match target.get(KEY_OBJECT)? {
+        Some(object) => {
+            let wrapped: WrappedKey =
+                serde_json::from_slice(&object.data).map_err(|_| "Invalid sync key file".to_string())?;
+            let salt = hex::decode(&wrapped.salt).map_err(|_| "Invalid sync key file".to_string())?;
+            let sealed = hex::decode(&wrapped.key).map_err(|_| "Invalid sync key file".to_string())?;
+
+            let wrapping_key = derive_key(password.as_bytes(), &salt, &wrapped.kdf)?;
+            let key = cipher_from_key(&wrapping_key[..], CipherSuite::default())?
+                .open(&sealed)
+                .map_err(|_| "Wrong sync password".to_string())?;
+            if key.len() != 32 {
+                return Err("Invalid sync key file".into());
+            }
+            Zeroizing::new(hex::encode(&key[..]))
+        }
+        None => {
+            let mut key = SecretKey::zeroed();
+            OsRng.fill_bytes(key.as_mut());
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let kdf = KdfParams::default();
+
+            let wrapping_key = derive_key(password.as_bytes(), &salt, &kdf)?;
+            let sealed = cipher_from_key(&wrapping_key[..], CipherSuite::default())?.seal(&key[..])?;
+            let wrapped = WrappedKey {
+                salt: hex::encode(salt),
+                kdf,
+                key: hex::encode(sealed),
+            };
+            target.put(KEY_OBJECT, &serde_json::to_vec(&wrapped).map_err(|e| e.to_string())?)?;
+            Zeroizing::new(hex::encode(&key[..]))
+        }
+    };
+
+    Ok(SyncConfig {
+        remote,
+        device_id: Uuid::new_v4().to_string(),
+        key,
+        vaults: Versions::new(),
+        devices: BTreeMap::new(),
+        last_sync: None,
+    })
+}
+
+pub(crate) fn run(session: &mut Session) -> Result<SyncReport, String> {
+    let mut config = session.index.sync.clone().ok_or("Sync is not set up")?;
+    let remote = config.remote.open(&cache_dir(session))?;
+    let key = Zeroizing::new(hex::decode(&*config.key).map_err(|_| "Invalid sync key".to_string())?);
+
+    let mut engine = Engine {
+        remote: remote.as_ref(),
+        cipher: cipher_from_key(&key, session.index.storage.cipher)?,
+        key,
+        device: config.device_id.clone(),
+        settings: session.index.storage,
+        report: SyncReport::default(),
+    };
+
+    let vaults = engine.sync_vaults(session, &config.vaults, &mut config.devices)?;
+
+    let ids: Vec<String> = session
+        .index
+        .vaults
+        .iter()
+        .filter(|v| v.team.is_none())
+        .map(|v| v.folder_id.clone())
+        .collect();
+    for id in ids {
+        engine.sync_vault(session, &id)?;
+    }
+
+    engine.put(&format!("{}/{}", DEVICES_PREFIX, engine.device), &RootState { vaults: vaults.clone() })?;
+    // Sync may have been turned off, or pointed elsewhere, while this ran.
+    session.update_index(|session| {
+        if let Some(current) = session.index.sync.as_mut().filter(|c| c.device_id == config.device_id) {
+            current.vaults = vaults;
+            current.devices = config.devices;
+            current.last_sync = Some(Utc::now().timestamp());
+        }
+        Ok(())
+    })?;
+
+    Ok(engine.report)
+}
+
+// Names coming from the remote become file and folder names.
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 128
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn names(local: &Versions, remote: &BTreeMap<String, Vec<Version>>) -> BTreeSet<String> {
+    local.keys().chain(remote.keys()).filter(|n| is_safe_name(n)).cloned().collect()
+}
+
+fn new_rev() -> String {
+    Uuid::new_v4().to_string()
+}
+
+// Whether the local item already matches the version that won.
+fn has_content(local: Option<&Version>, winner: &Version) -> bool {
+    match local {
+        Some(version) => version.deleted == winner.deleted && (winner.deleted || version.digest == winner.digest),
+        None => winner.deleted,
+    }
+}
+
+fn note_object(prefix: &str, rev: &str) -> String {
+    format!("{}/notes/{}", prefix, rev)
+}
+
+// The CRDT log a revision was saved with, for notes that have one.
+fn log_object(prefix: &str, rev: &str) -> String {
+    format!("{}/ops/{}", prefix, rev)
+}
+
+fn note_meta(entry: &Value) -> Value {
+    json!({
+        "notetitle": entry.get("notetitle").cloned().unwrap_or(Value::Null),
+        "icon": entry.get("icon").cloned().unwrap_or(Value::Null),
+        "modified": entry.get("modified").cloned().unwrap_or(Value::Null),
+    })
+}
+
+// Writes a note received from another device. Keys stay per device, a new note gets a fresh one.
+fn apply_note(
+    store: &VaultStore,
+    notes_list: &mut Vec<Value>,
+    filename: &str,
+    meta: &Value,
+    content: &[u8],
+) -> Result<(), String> {
+    let position = notes_list
+        .iter()
+        .position(|note| note.get("filename").and_then(|f| f.as_str()) == Some(filename));
+    let index = match position {
+        Some(index) => index,
+        None => {
+            notes_list.push(json!({ "filename": filename, "key": shred::new_note_key() }));
+            notes_list.len() - 1
+        }
+    };
+
+    let entry = &mut notes_list[index];
+    for field in ["notetitle", "icon", "modified"] {
+        match meta.get(field) {
+            Some(value) if !value.is_null() => entry[field] = value.clone(),
+            _ => {
+                if let Some(entry) = entry.as_object_mut() {
+                    entry.remove(field);
+                }
+            }
+        }
+    }
+    write_note(store, entry, filename, content)
+}
+
+// A note written without a log drops the one it had, its next save here starts a new one.
+fn apply_log(store: &VaultStore, notes_list: &[Value], filename: &str, log: Option<&NoteLog>) -> Result<(), String> {
+    match log {
+        Some(log) => crdt::save(store, find_note(notes_list, filename)?, filename, log),
+        None => store.remove(&crdt::log_name(filename)).map(|_| ()),
+    }
+}
+
+// The caller writes the notes list with `VaultStore::shredding` afterwards.
+fn remove_note(store: &VaultStore, notes_list: &mut Vec<Value>, filename: &str) -> Result<(), String> {
+    notes_list.retain(|note| note.get("filename").and_then(|f| f.as_str()) != Some(filename));
+    let store = store.shredding();
+    store.remove(filename)?;
+    store.remove(&crdt::log_name(filename))?;
+    Ok(())
+}
+
+fn entry_name(entry: &Value) -> Option<&str> {
+    entry.get("filename").and_then(|f| f.as_str())
+}
+
+// Commands may have changed the list while sync talked to the remote. Only the entries sync added,
+// changed or removed come from its copy, everything else stays as it is now.
+fn merge_notes_list(current: Vec<Value>, initial: &[Value], synced: Vec<Value>) -> Vec<Value> {
+    let find = |list: &[Value], name: &str| list.iter().find(|e| entry_name(e) == Some(name)).cloned();
+    let mut merged: Vec<Value> = current
+        .iter()
+        .filter_map(|entry| {
+            let Some(name) = entry_name(entry) else {
+                return Some(entry.clone());
+            };
+            match (find(initial, name), find(&synced, name)) {
+                (Some(_), None) => None,
+                (before, Some(after)) if before.as_ref() != Some(&after) => Some(after),
+                _ => Some(entry.clone()),
+            }
+        })
+        .collect();
+    for entry in synced {
+        let new = entry_name(&entry).is_some_and(|name| find(initial, name).is_none() && find(&current, name).is_none());
+        if new {
+            merged.push(entry);
+        }
+    }
+    merged
+}
+
+fn read_state(store: &VaultStore) -> Result<VaultState, String> {
+    if !store.contains(SYNC_FILE)? {
+        return Ok(VaultState::default());
+    }
+    serde_json::from_slice(&store.read(SYNC_FILE)?).map_err(|_| "Invalid sync state".to_string())
+}
+
+fn write_state(store: &VaultStore, state: &VaultState) -> Result<(), String> {
+    let json = Zeroizing::new(serde_json::to_vec(state).map_err(|e| e.to_string())?);
+    store.write(SYNC_FILE, &json)
+}
+
+struct Engine<'a> {
+    remote: &'a dyn Remote,
+    cipher: Cipher,
+    key: Zeroizing<Vec<u8>>,
+    device: String,
+    settings: StorageSettings,
+    report: SyncReport,
+}
+
+impl Engine<'_> {
+    fn open(&self, data: &[u8]) -> Result<Plaintext, String> {
+        storage::open(&self.cipher, data).map_err(|e| {
+            if e == DECRYPT_FAILED {
+                "The sync folder was set up with another sync key, connect to it again".to_string()
+            } else {
+                e
+            }
+        })
+    }
+
+    // Every other device's state object under `prefix`, downloading only the ones whose etag
+    // changed since `cache` was filled. Devices that disappeared drop out of the cache.
+    fn device_states<T: DeserializeOwned + Clone>(
+        &self,
+        prefix: &str,
+        cache: &mut BTreeMap<String, Cached<T>>,
+    ) -> Result<Vec<T>, String> {
+        let listings: Vec<Listing> = self.remote.list(prefix)?;
+        let mut fresh = BTreeMap::new();
+        for listing in listings {
+            if listing.name == self.device || !is_safe_name(&listing.name) {
+                continue;
+            }
+            if let Some(cached) = cache.remove(&listing.name) {
+                if !listing.etag.is_empty() && cached.etag == listing.etag {
+                    fresh.insert(listing.name, cached);
+                    continue;
+                }
+            }
+            if let Some(object) = self.remote.get(&format!("{}/{}", prefix, listing.name))? {
+                let value = serde_json::from_slice(&self.open(&object.data)?)
+                    .map_err(|_| "Invalid sync data".to_string())?;
+                fresh.insert(listing.name, Cached { etag: object.etag, value });
+            }
+        }
+        *cache = fresh;
+        Ok(cache.values().map(|c| c.value.clone()).collect())
+    }
+
+    fn put<T: Serialize>(&self, name: &str, value: &T) -> Result<(), String> {
+        let json = Zeroizing::new(serde_json::to_vec(value).map_err(|e| e.to_string())?);
+        self.put_data(name, &json)
+    }
+
+    // A missing object means another device replaced it while we were reading, the next sync
+    // sees its new state.
+    fn get_data(&self, name: &str) -> Result<Plaintext, String> {
+        let object = self.remote.get(name)?.ok_or("The sync folder changed during sync, try again")?;
+        self.open(&object.data)
+    }
+
+    fn get_log(&self, prefix: &str, rev: &str) -> Result<Option<NoteLog>, String> {
+        match self.remote.get(&log_object(prefix, rev))? {
+            Some(object) => NoteLog::parse(&self.open(&object.data)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // None when a copy other devices hold is gone, the next sync tries again. Copies that do not
+    // share the log's history never merge with it and are left out.
+    fn acks<'r>(&self, prefix: &str, log: &NoteLog, revs: impl Iterator<Item = &'r String>) -> Result<Option<Vec<Ack>>, String> {
+        let mut acks = Vec::new();
+        for rev in revs {
+            match self.get_log(prefix, rev)? {
+                Some(copy) if copy.shares_history(log) => acks.push(copy.ack()),
+                Some(_) => {}
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(acks))
+    }
+
+    fn put_data(&self, name: &str, plaintext: &[u8]) -> Result<(), String> {
+        self.remote.put(name, &storage::seal(&self.cipher, plaintext, self.settings)?)
+    }
+
+    // Keyed, so the remote cannot confirm guesses about note contents.
+    fn digest(&self, meta: &Value, content: &[u8]) -> Result<String, String> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key).map_err(|e| e.to_string())?;
+        mac.update(b"noetiq-sync\0");
+        mac.update(&serde_json::to_vec(meta).map_err(|e| e.to_string())?);
+        mac.update(b"\0");
+        mac.update(content);
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    // Whether a note is still what sync read when it started, called under the store lock right
+    // before sync writes over it.
+    fn unchanged(&self, store: &VaultStore, name: &str, read: Option<&Version>) -> Result<bool, String> {
+        let notes_list = read_notes_list(store)?;
+        Ok(match (notes_list.iter().find(|e| entry_name(e) == Some(name)), read) {
+            (Some(entry), Some(read)) if !read.deleted => {
+                self.digest(&note_meta(entry), &read_note(store, entry, name)?)? == read.digest
+            }
+            (None, Some(read)) => read.deleted,
+            (None, None) => true,
+            _ => false,
+        })
+    }
+
+    // Turns the current local items into versions, ticking this device's counter for every item
+    // that changed, appeared or disappeared since the last sync.
+    fn local_versions(&self, base: &Versions, current: BTreeMap<String, (String, Value)>) -> Versions {
+        let mut versions = Versions::new();
+        for (name, (digest, meta)) in current {
+            let version = match base.get(&name) {
+                Some(previous) if !previous.deleted && previous.digest == digest => previous.clone(),
+                previous => {
+                    let mut clock = previous.map(|p| p.clock.clone()).unwrap_or_default();
+                    clock.tick(&self.device);
+                    Version { rev: new_rev(), clock, deleted: false, digest, meta }
+                }
+            };
+            versions.insert(name, version);
+        }
+
+        for (name, previous) in base {
+            if versions.contains_key(name) {
+                continue;
+            }
+            let version = if previous.deleted {
+                previous.clone()
+            } else {
+                let mut clock = previous.clock.clone();
+                clock.tick(&self.device);
+                Version { rev: new_rev(), clock, deleted: true, digest: String::new(), meta: Value::Null }
+            };
+            versions.insert(name.clone(), version);
+        }
+        versions
+    }
+
+    // Picks the version to keep plus the concurrent ones to keep as copies. The local version
+    // comes first so it wins ties.
+    fn resolve(&self, local: Option<Version>, remote: Vec<Version>) -> Option<(Version, Vec<Version>)> {
+        let mut candidates: Vec<Version> = Vec::new();
+        for version in local.into_iter().chain(remote) {
+            if candidates
+                .iter()
+                .any(|c| matches!(version.clock.compare(&c.clock), Some(Ordering::Less | Ordering::Equal)))
+            {
+                continue;
+            }
+            candidates.retain(|c| version.clock.compare(&c.clock) != Some(Ordering::Greater));
+            candidates.push(version);
+        }
+        if candidates.len() <= 1 {
+            return candidates.pop().map(|winner| (winner, Vec::new()));
+        }
+
+        // Concurrent changes. The same content on both sides is no conflict and an edit beats
+        // a deletion.
+        let mut clock = VectorClock::default();
+        let mut live: Vec<Version> = Vec::new();
+        for candidate in &candidates {
+            clock.merge(&candidate.clock);
+            if !candidate.deleted && !live.iter().any(|l| l.digest == candidate.digest) {
+                live.push(candidate.clone());
+            }
+        }
+        let (mut winner, copies) = if live.is_empty() {
+            (candidates.swap_remove(0), Vec::new())
+        } else {
+            (live.remove(0), live)
+        };
+        clock.tick(&self.device);
+        winner.clock = clock;
+        Some((winner, copies))
+    }
+
+    // Folds concurrent edits from other devices into the local note. When both sides edited
+    // through the same CRDT log the logs are combined, which always converges. Otherwise a
+    // three-way merge takes the version this device last published as the common ancestor when
+    // theirs descends from it, and the log no longer matches the note. Copies that cannot be
+    // merged stay in `copies`.
+    fn merge(
+        &mut self,
+        prefix: &str,
+        base: Option<&Version>,
+        mut content: Plaintext,
+        mut log: Option<NoteLog>,
+        meta: &Value,
+        copies: &mut Vec<Version>,
+    ) -> Result<Option<(Plaintext, Value, Option<NoteLog>)>, String> {
+        let base_content = match base {
+            Some(base) => Some(self.get_data(&note_object(prefix, &base.rev))?),
+            None => None,
+        };
+        let base_meta = base.map_or_else(|| meta.clone(), |base| base.meta.clone());
+        let mut meta = meta.clone();
+        let mut merged_any = false;
+        let mut conflicts = 0;
+
+        let mut remaining = Vec::new();
+        for copy in copies.drain(..) {
+            if let Some(ours) = log.as_mut() {
+                if let Some(theirs) = self.get_log(prefix, &copy.rev)?.filter(|theirs| ours.shares_history(theirs)) {
+                    ours.merge(theirs);
+                    content = ours.materialize()?;
+                    meta = merge::merge_meta(&base_meta, &meta, &copy.meta);
+                    merged_any = true;
+                    continue;
+                }
+            }
+
+            let merged = match (base, &base_content) {
+                (Some(base), Some(base_content))
+                    if matches!(base.clock.compare(&copy.clock), Some(Ordering::Less | Ordering::Equal)) =>
+                {
+                    let theirs = self.get_data(&note_object(prefix, &copy.rev))?;
+                    merge::merge_note(base_content, &content, &theirs)
+                }
+                _ => None,
+            };
+            match merged {
+                Some(merged) => {
+                    content = merged.content;
+                    meta = merge::merge_meta(&base_meta, &meta, &copy.meta);
+                    conflicts += merged.conflicts;
+                    merged_any = true;
+                    log = None;
+                }
+                None => remaining.push(copy),
+            }
+        }
+        *copies = remaining;
+        if !merged_any {
+            return Ok(None);
+        }
+
+        self.report.merged += 1;
+        if conflicts > 0 {
+            self.report.conflicts += 1;
+        }
+        Ok(Some((content, meta, log)))
+    }
+
+    fn sync_vaults(
+        &mut self,
+        session: &mut Session,
+        base: &Versions,
+        devices: &mut BTreeMap<String, Cached<RootState>>,
+    ) -> Result<Versions, String> {
+        let mut current = BTreeMap::new();
+        // Team vaults travel through the shared history folder, sync only carries the user's own.
+        for vault in session.index.vaults.iter().filter(|v| v.team.is_none()) {
+            let meta = json!({ "name": vault.name, "icon": vault.icon, "description": vault.description });
+            current.insert(vault.folder_id.clone(), (self.digest(&meta, b"")?, meta));
+        }
+        let local = self.local_versions(base, current);
+
+        let mut remote: BTreeMap<String, Vec<Version>> = BTreeMap::new();
+        for state in self.device_states(DEVICES_PREFIX, devices)? {
+            for (id, version) in state.vaults {
+                remote.entry(id).or_default().push(version);
+            }
+        }
+
+        let mut result = Versions::new();
+        for id in names(&local, &remote) {
+            let local_version = local.get(&id).cloned();
+            // Concurrent renames keep this device's details, vaults are never duplicated.
+            let Some((winner, _)) = self.resolve(local_version.clone(), remote.remove(&id).unwrap_or_default()) else {
+                continue;
+            };
+
+            if !has_content(local_version.as_ref(), &winner) {
+                if winner.deleted {
+                    session.update_index(|session| {
+                        session.index.vaults.retain(|v| v.folder_id != id);
+                        let vault_path = session.vault_dir.join(&id);
+                        if vault_path.exists() {
+                            shred::remove_dir_all(&vault_path, session.index.storage.secure_delete)
+                                .map_err(|_| "Failed to delete vault folder".to_string())?;
+                        }
+                        Ok(())
+                    })?;
+                    self.report.deleted += 1;
+                } else {
+                    let field = |name: &str| winner.meta[name].as_str().unwrap_or_default().to_string();
+                    // A vault deleted here since it was read stays deleted, the next sync shares that.
+                    let known = local_version.is_some();
+                    session.update_index(|session| {
+                        match session.index.vaults.iter_mut().find(|v| v.folder_id == id) {
+                            Some(vault) => {
+                                vault.name = field("name");
+                                vault.icon = field("icon");
+                                vault.description = field("description");
+                            }
+                            None if known => {}
+                            None => {
+                                session.vault(&id)?.init()?;
+                                session.index.vaults.push(IdVaultEntry {
+                                    icon: field("icon"),
+                                    name: field("name"),
+                                    description: field("description"),
+                                    folder_id: id.clone(),
+                                    team: None,
+                                });
+                            }
+                        }
+                        Ok(())
+                    })?;
+                    self.report.pulled += 1;
+                }
+            }
+            result.insert(id, winner);
+        }
+        Ok(result)
+    }
+
+    fn sync_vault(&mut self, session: &Session, folder_id: &str) -> Result<(), String> {
+        let store = session.vault(folder_id)?;
+        let prefix = format!("{}/{}", VAULTS_PREFIX, folder_id);
+        let mut notes_list = read_notes_list(&store)?;
+        let initial_list = notes_list.clone();
+
+        let mut current = BTreeMap::new();
+        for entry in &notes_list {
+            let Some(filename) = entry.get("filename").and_then(|f| f.as_str()) else {
+                continue;
+            };
+            let content = read_note(&store, entry, filename)?;
+            let meta = note_meta(entry);
+            current.insert(filename.to_string(), (self.digest(&meta, &content)?, meta));
+        }
+        let mut state = read_state(&store)?;
+        let local = self.local_versions(&state.notes, current);
+
+        // Revisions and attachments other devices still point to are never removed.
+        let index_prefix = format!("{}/index", prefix);
+        let mut remote: BTreeMap<String, Vec<Version>> = BTreeMap::new();
+        let mut remote_attachments = BTreeMap::new();
+        let mut referenced = HashSet::new();
+        // The revisions other devices hold, whose logs say what they have seen of each note.
+        let mut holders: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for index in self.device_states(&index_prefix, &mut state.devices)? {
+            for (name, version) in index.notes {
+                referenced.insert(version.rev.clone());
+                if !version.deleted {
+                    holders.entry(name.clone()).or_default().insert(version.rev.clone());
+                }
+                remote.entry(name).or_default().push(version);
+            }
+            for (id, info) in index.attachments {
+                referenced.insert(id.clone());
+                remote_attachments.insert(id, info);
+            }
+        }
+        let objects: HashSet<String> = self.remote.list(&format!("{}/notes", prefix))?.into_iter().map(|l| l.name).collect();
+        // Listed after the notes, and uploaded before them, so a listed revision's log is too.
+        let logs: HashSet<String> = self.remote.list(&format!("{}/ops", prefix))?.into_iter().map(|l| l.name).collect();
+
+        let mut result = Versions::new();
+        let mut removed = false;
+        for name in names(&local, &remote) {
+            let mut local_version = local.get(&name).cloned();
+            let Some((mut winner, mut copies)) = self.resolve(local_version.clone(), remote.remove(&name).unwrap_or_default()) else {
+                continue;
+            };
+            let base = state.notes.get(&name).filter(|base| !base.deleted && objects.contains(&base.rev));
+            if !copies.is_empty() && !winner.deleted && has_content(local_version.as_ref(), &winner) {
+                let entry = find_note(&notes_list, &name)?;
+                let content = read_note(&store, entry, &name)?;
+                let log = crdt::load(&store, entry, &name)?;
+                if let Some((content, meta, log)) = self.merge(&prefix, base, content, log, &winner.meta, &mut copies)? {
+                    winner.rev = new_rev();
+                    winner.digest = self.digest(&meta, &content)?;
+                    winner.meta = meta;
+                    let _writing = session.writing();
+                    // Saved here since sync read it. The save is a local version of its own, which
+                    // the next sync resolves against the others, so the note keeps what it published.
+                    if !self.unchanged(&store, &name, local_version.as_ref())? {
+                        result.extend(state.notes.get(&name).map(|base| (name.clone(), base.clone())));
+                        continue;
+                    }
+                    apply_note(&store, &mut notes_list, &name, &winner.meta, &content)?;
+                    apply_log(&store, &notes_list, &name, log.as_ref())?;
+                    local_version = Some(winner.clone());
+                }
+            }
+
+            if !has_content(local_version.as_ref(), &winner) {
+                if winner.deleted {
+                    let _writing = session.writing();
+                    if !self.unchanged(&store, &name, local_version.as_ref())? {
+                        result.extend(state.notes.get(&name).map(|base| (name.clone(), base.clone())));
+                        continue;
+                    }
+                    remove_note(&store, &mut notes_list, &name)?;
+                    removed = true;
+                    self.report.deleted += 1;
+                } else {
+                    let content = self.get_data(&note_object(&prefix, &winner.rev))?;
+                    let log = match logs.contains(&winner.rev) {
+                        true => self.get_log(&prefix, &winner.rev)?,
+                        false => None,
+                    };
+                    let _writing = session.writing();
+                    if !self.unchanged(&store, &name, local_version.as_ref())? {
+                        result.extend(state.notes.get(&name).map(|base| (name.clone(), base.clone())));
+                        continue;
+                    }
+                    apply_note(&store, &mut notes_list, &name, &winner.meta, &content)?;
+                    apply_log(&store, &notes_list, &name, log.as_ref())?;
+                    self.report.pulled += 1;
+                }
+            } else if !winner.deleted && !objects.contains(&winner.rev) {
+                let entry = find_note(&notes_list, &name)?;
+                if let Some(mut log) = crdt::load(&store, entry, &name)? {
+                    let held = holders.get(&name).map(|revs| revs.iter().filter(|rev| logs.contains(*rev)));
+                    if let Some(acks) = self.acks(&prefix, &log, held.into_iter().flatten())? {
+                        if log.compact(&acks) {
+                            let _writing = session.writing();
+                            if !self.unchanged(&store, &name, local_version.as_ref())? {
+                                result.extend(state.notes.get(&name).map(|base| (name.clone(), base.clone())));
+                                continue;
+                            }
+                            crdt::save(&store, entry, &name, &log)?;
+                        }
+                    }
+                    self.put_data(&log_object(&prefix, &winner.rev), &log.encode()?)?;
+                }
+                let content = read_note(&store, entry, &name)?;
+                self.put_data(&note_object(&prefix, &winner.rev), &content)?;
+                self.report.pushed += 1;
+            }
+
+            // The other side of a conflict becomes a note of its own, reusing the stored revision.
+            for copy in copies {
+                let content = self.get_data(&note_object(&prefix, &copy.rev))?;
+                let copy_name = format!("{}.json", Uuid::new_v4());
+                let mut meta = copy.meta.clone();
+                let title = meta["notetitle"].as_str().unwrap_or_default().to_string();
+                meta["notetitle"] = json!(format!("{}{}", title, CONFLICT_SUFFIX));
+                let _writing = session.writing();
+                apply_note(&store, &mut notes_list, &copy_name, &meta, &content)?;
+
+                let mut clock = VectorClock::default();
+                clock.tick(&self.device);
+                let digest = self.digest(&meta, &content)?;
+                result.insert(copy_name, Version { rev: copy.rev, clock, deleted: false, digest, meta });
+                self.report.conflicts += 1;
+            }
+            result.insert(name, winner);
+        }
+
+        let blobs_prefix = format!("{}/attachments", prefix);
+        let blobs: HashSet<String> = self.remote.list(&blobs_prefix)?.into_iter().map(|l| l.name).collect();
+        let mut local_attachments = attachments::list(&store)?;
+        for (id, info) in remote_attachments {
+            if local_attachments.contains_key(&id) || info.id != id || !is_safe_name(&id) || !blobs.contains(&id) {
+                continue;
+            }
+            let data = self.get_data(&format!("{}/{}", blobs_prefix, id))?;
+            let _writing = session.writing();
+            attachments::import(&store, &info, &data)?;
+            local_attachments.insert(id, info);
+            self.report.pulled += 1;
+        }
+        for id in local_attachments.keys() {
+            if !blobs.contains(id) {
+                let (_, data) = attachments::load(&store, id)?;
+                self.put_data(&format!("{}/{}", blobs_prefix, id), &data)?;
+                self.report.pushed += 1;
+            }
+        }
+
+        {
+            let _writing = session.writing();
+            let notes_list = merge_notes_list(read_notes_list(&store)?, &initial_list, notes_list);
+            match removed {
+                true => write_notes_list(&store.shredding(), &notes_list)?,
+                false => write_notes_list(&store, &notes_list)?,
+            }
+        }
+
+        // Only objects this device published before are removed, anything another device may be
+        // uploading right now is left alone.
+        let revs: HashSet<String> = result.values().map(|v| v.rev.clone()).collect();
+        let stale_revs: Vec<String> = state
+            .notes
+            .values()
+            .map(|v| v.rev.clone())
+            .filter(|rev| !revs.contains(rev) && !referenced.contains(rev) && objects.contains(rev))
+            .collect();
+        let stale_blobs: Vec<String> = state
+            .attachments
+            .iter()
+            .filter(|id| !local_attachments.contains_key(*id) && !referenced.contains(*id) && blobs.contains(*id))
+            .cloned()
+            .collect();
+
+        let index = VaultIndex { notes: result, attachments: local_attachments };
+        self.put(&format!("{}/{}", index_prefix, self.device), &index)?;
+        state.notes = index.notes;
+        state.attachments = index.attachments.into_keys().collect();
+        {
+            let _writing = session.writing();
+            write_state(&store, &state)?;
+        }
+
+        for rev in stale_revs {
+            self.remote.delete(&note_object(&prefix, &rev))?;
+            if logs.contains(&rev) {
+                self.remote.delete(&log_object(&prefix, &rev))?;
+            }
+        }
+        for id in stale_blobs {
+            self.remote.delete(&format!("{}/{}", blobs_prefix, id))?;
+        }
+        Ok(())
+    }
+}
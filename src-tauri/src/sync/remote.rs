@@ -0,0 +1,112 @@
+use super::folder::FolderRemote;
+use super::lan::{self, LanPeer, LanRemote};
+use super::s3::S3Remote;
+use super::webdav::WebDavRemote;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+// Where synced objects live. Names are '/' separated paths built by the engine from checked
+// components, and every object is written whole.
+pub(crate) trait Remote {
+    // The objects directly under `prefix`, named without the prefix.
+    fn list(&self, prefix: &str) -> Result<Vec<Listing>, String>;
+    fn get(&self, name: &str) -> Result<Option<Object>, String>;
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), String>;
+    fn delete(&self, name: &str) -> Result<(), String>;
+}
+
+// The etag changes whenever the object does. An empty one means the remote cannot tell.
+pub(crate) struct Listing {
+    pub name: String,
+    pub etag: String,
+}
+
+pub(crate) struct Object {
+    pub data: Vec<u8>,
+    pub etag: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum RemoteConfig {
+    // A directory some other tool keeps in sync between devices.
+    Folder { path: String },
+    WebDav {
+        url: String,
+        username: String,
+        password: Zeroizing<String>,
+    },
+    // Amazon S3 or a compatible store. The prefix keeps sync objects apart from anything else in
+    // the bucket.
+    S3 {
+        endpoint: String,
+        #[serde(default)]
+        region: String,
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+        access_key: String,
+        secret_key: Zeroizing<String>,
+    },
+    // This device keeps the objects and serves them to paired devices on the same network.
+    LanHost {
+        port: u16,
+        #[serde(default)]
+        peers: Vec<LanPeer>,
+    },
+    // Another device hosting on the same network. The code is only used to pair and never saved.
+    Lan {
+        address: String,
+        #[serde(default)]
+        peer: String,
+        #[serde(default)]
+        secret: Zeroizing<String>,
+        #[serde(default, skip_serializing)]
+        code: Zeroizing<String>,
+    },
+}
+
+impl RemoteConfig {
+    // `cache_dir` holds partial downloads so an interrupted transfer resumes where it stopped.
+    pub fn open(&self, cache_dir: &Path) -> Result<Box<dyn Remote>, String> {
+        match self {
+            RemoteConfig::Folder { path } => Ok(Box::new(FolderRemote::new(path)?)),
+            RemoteConfig::WebDav { url, username, password } => {
+                Ok(Box::new(WebDavRemote::new(url, username, password, cache_dir)?))
+            }
+            RemoteConfig::S3 { endpoint, region, bucket, prefix, access_key, secret_key } => {
+                Ok(Box::new(S3Remote::new(endpoint, region, bucket, prefix, access_key, secret_key)?))
+            }
+            RemoteConfig::LanHost { .. } => Ok(Box::new(lan::hub(cache_dir)?)),
+            RemoteConfig::Lan { address, peer, secret, .. } => Ok(Box::new(LanRemote::new(address, peer, secret)?)),
+        }
+    }
+
+    // Trades a pairing code for the secret the devices share from then on.
+    pub fn pair(self) -> Result<Self, String> {
+        match self {
+            RemoteConfig::Lan { address, code, .. } if !code.is_empty() => {
+                let (peer, secret) = lan::pair(&address, &code)?;
+                Ok(RemoteConfig::Lan { address, peer, secret, code: Zeroizing::default() })
+            }
+            remote => Ok(remote),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            RemoteConfig::Folder { path } => path.clone(),
+            RemoteConfig::WebDav { url, .. } => url.clone(),
+            RemoteConfig::S3 { endpoint, bucket, prefix, .. } => {
+                let prefix = prefix.trim_matches('/');
+                if prefix.is_empty() {
+                    format!("{} ({})", bucket, endpoint)
+                } else {
+                    format!("{}/{} ({})", bucket, prefix, endpoint)
+                }
+            }
+            RemoteConfig::LanHost { port, .. } => format!("this device, port {}", port),
+            RemoteConfig::Lan { address, .. } => address.clone(),
+        }
+    }
+}
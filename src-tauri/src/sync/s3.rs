@@ -0,0 +1,349 @@
+use super::http;
+use super::remote::{Listing, Object, Remote};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+const DEFAULT_REGION: &str = "us-east-1";
+const SERVICE: &str = "s3";
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+// Talks to Amazon S3 and compatible stores such as MinIO with path-style addressing, which every
+// one of them understands, and Signature Version 4.
+pub(crate) struct S3Remote {
+    agent: ureq::Agent,
+    // The endpoint without a trailing slash.
+    endpoint: String,
+    host: String,
+    region: String,
+    bucket: String,
+    // Empty, or ends with a slash.
+    prefix: String,
+    access_key: String,
+    secret_key: Zeroizing<String>,
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+// SigV4 encodes everything except the unreserved characters, and '/' only outside the path.
+fn uri_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    query
+        .iter()
+        .map(|(name, value)| format!("{}={}", uri_encode(name, false), uri_encode(value, false)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+// `headers` are lowercase, sorted and all signed.
+fn canonical_request(method: &str, path: &str, query: &str, headers: &[(&str, &str)], payload_hash: &str) -> String {
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, signed_headers, payload_hash)
+}
+
+fn string_to_sign(timestamp: &str, scope: &str, canonical_request: &str) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        timestamp,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    )
+}
+
+fn signature(secret_key: &str, date: &str, region: &str, service: &str, string_to_sign: &str) -> String {
+    let secret = Zeroizing::new(format!("AWS4{}", secret_key));
+    let key = hmac(secret.as_bytes(), date);
+    let key = hmac(&key, region);
+    let key = hmac(&key, service);
+    let key = hmac(&key, "aws4_request");
+    hex::encode(hmac(&key, string_to_sign))
+}
+
+// The Host header ureq sends leaves out a default port, and the signature must cover that value.
+fn host_header(endpoint: &str, authority: &str) -> String {
+    let default_port = if endpoint.starts_with("https://") { ":443" } else { ":80" };
+    authority.strip_suffix(default_port).unwrap_or(authority).to_string()
+}
+
+fn xml_text<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+    node.children().find(|n| n.tag_name().name() == name).and_then(|n| n.text())
+}
+
+fn status_error(code: u16, body: &str) -> String {
+    match error_code(body).unwrap_or_default().as_str() {
+        "NoSuchBucket" => "The bucket does not exist".into(),
+        "InvalidAccessKeyId" | "SignatureDoesNotMatch" => "The storage server rejected the access key".into(),
+        "AccessDenied" => "The access key is not allowed to use this bucket".into(),
+        "AuthorizationHeaderMalformed" | "PermanentRedirect" => "The bucket is in another region".into(),
+        _ => format!("The storage server answered with status {}", code),
+    }
+}
+
+fn response_error(response: ureq::Response) -> String {
+    let code = response.status();
+    status_error(code, &response.into_string().unwrap_or_default())
+}
+
+fn error_code(body: &str) -> Option<String> {
+    let document = roxmltree::Document::parse(body).ok()?;
+    xml_text(document.root_element(), "Code").map(str::to_string)
+}
+
+impl S3Remote {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        prefix: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, String> {
+        let endpoint = endpoint.trim().trim_end_matches('/');
+        let authority = http::check_url(endpoint)?;
+        if endpoint.split('/').count() > 3 {
+            return Err("Enter the bucket in its own field, not in the address".into());
+        }
+        let bucket = bucket.trim();
+        if bucket.is_empty() || bucket.contains('/') {
+            return Err("Enter a bucket name".into());
+        }
+        if access_key.is_empty() || secret_key.is_empty() {
+            return Err("Enter the access key and the secret key".into());
+        }
+
+        let prefix = prefix.trim().trim_matches('/');
+        let region = region.trim();
+        Ok(S3Remote {
+            agent: http::agent(),
+            host: host_header(endpoint, authority),
+            endpoint: endpoint.to_string(),
+            region: if region.is_empty() { DEFAULT_REGION } else { region }.to_string(),
+            bucket: bucket.to_string(),
+            prefix: if prefix.is_empty() { String::new() } else { format!("{}/", prefix) },
+            access_key: access_key.to_string(),
+            secret_key: Zeroizing::new(secret_key.to_string()),
+        })
+    }
+
+    fn object_path(&self, name: &str) -> String {
+        format!("/{}/{}{}", self.bucket, self.prefix, name)
+    }
+
+    // Signs and sends one request. `query` must already be sorted by name.
+    fn request(&self, method: &str, path: &str, query: &[(&str, &str)], body: &[u8]) -> Result<ureq::Response, String> {
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex::encode(Sha256::digest(body));
+
+        let path = uri_encode(path, true);
+        let query = canonical_query(query);
+        let headers = [
+            ("host", self.host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", timestamp.as_str()),
+        ];
+        let authorization = self.authorization(method, &path, &query, &headers, &date, &timestamp);
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.endpoint, path, query)
+        };
+        let mut headers = headers.to_vec();
+        headers.push(("Authorization", authorization.as_str()));
+        http::send(&self.agent, method, &url, &headers, body)
+    }
+
+    // `headers` are lowercase, sorted and all signed.
+    fn authorization(
+        &self,
+        method: &str,
+        path: &str,
+        query: &str,
+        headers: &[(&str, &str)],
+        date: &str,
+        timestamp: &str,
+    ) -> String {
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let payload_hash = headers
+            .iter()
+            .find(|(name, _)| *name == "x-amz-content-sha256")
+            .map(|(_, value)| *value)
+            .unwrap_or_default();
+        let canonical_request = canonical_request(method, path, query, headers, payload_hash);
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let string_to_sign = string_to_sign(timestamp, &scope, &canonical_request);
+        let signature = signature(&self.secret_key, date, &self.region, SERVICE, &string_to_sign);
+
+        format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM, self.access_key, scope, signed_headers, signature
+        )
+    }
+}
+
+impl Remote for S3Remote {
+    // The listing carries each object's ETag, which for a single upload is the MD5 of its
+    // content, so changed objects are found without downloading anything.
+    fn list(&self, prefix: &str) -> Result<Vec<Listing>, String> {
+        let key_prefix = format!("{}{}/", self.prefix, prefix);
+        let bucket_path = format!("/{}", self.bucket);
+        let mut listings = Vec::new();
+        let mut token = String::new();
+
+        loop {
+            let mut query = vec![("delimiter", "/"), ("list-type", "2"), ("prefix", key_prefix.as_str())];
+            if !token.is_empty() {
+                query.insert(0, ("continuation-token", token.as_str()));
+            }
+            let response = self.request("GET", &bucket_path, &query, &[])?;
+            if response.status() != 200 {
+                return Err(response_error(response));
+            }
+            let body = response.into_string().map_err(|_| "Invalid storage server response".to_string())?;
+            let document =
+                roxmltree::Document::parse(&body).map_err(|_| "Invalid storage server response".to_string())?;
+            let result = document.root_element();
+
+            for contents in result.children().filter(|n| n.tag_name().name() == "Contents") {
+                let name = xml_text(contents, "Key").and_then(|key| key.strip_prefix(&key_prefix)).unwrap_or_default();
+                if name.is_empty() || name.contains('/') || name.ends_with(".tmp") || name.starts_with('.') {
+                    continue;
+                }
+                let etag = xml_text(contents, "ETag").unwrap_or_default().to_string();
+                listings.push(Listing { name: name.to_string(), etag });
+            }
+
+            match xml_text(result, "NextContinuationToken") {
+                Some(next) if xml_text(result, "IsTruncated") == Some("true") => token = next.to_string(),
+                _ => return Ok(listings),
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Object>, String> {
+        let response = self.request("GET", &self.object_path(name), &[], &[])?;
+        match response.status() {
+            200 => {}
+            // A missing bucket also answers 404 and must not look like a missing object.
+            404 => {
+                let body = response.into_string().unwrap_or_default();
+                return match error_code(&body).as_deref() {
+                    None | Some("NoSuchKey") => Ok(None),
+                    Some(_) => Err(status_error(404, &body)),
+                };
+            }
+            _ => return Err(response_error(response)),
+        }
+        let etag = response.header("ETag").unwrap_or_default().to_string();
+        let mut data = Vec::new();
+        std::io::copy(&mut response.into_reader(), &mut data)
+            .map_err(|_| "The download was interrupted, sync again".to_string())?;
+        Ok(Some(Object { data, etag }))
+    }
+
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        let response = self.request("PUT", &self.object_path(name), &[], data)?;
+        match response.status() {
+            200 => Ok(()),
+            _ => Err(response_error(response)),
+        }
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        let response = self.request("DELETE", &self.object_path(name), &[], &[])?;
+        match response.status() {
+            200 | 204 | 404 => Ok(()),
+            _ => Err(response_error(response)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The get-vanilla case of the AWS Signature Version 4 test suite.
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const DATE: &str = "20150830";
+    const TIMESTAMP: &str = "20150830T123600Z";
+    const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn signs_the_get_vanilla_vector() {
+        let headers = [("host", "example.amazonaws.com"), ("x-amz-date", TIMESTAMP)];
+        let canonical = canonical_request("GET", "/", "", &headers, EMPTY_HASH);
+        assert_eq!(
+            canonical,
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\nhost;x-amz-date\n".to_string()
+                + EMPTY_HASH
+        );
+
+        let scope = "20150830/us-east-1/service/aws4_request";
+        let to_sign = string_to_sign(TIMESTAMP, scope, &canonical);
+        assert_eq!(
+            to_sign,
+            "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/service/aws4_request\n\
+             bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63"
+        );
+
+        assert_eq!(
+            signature(SECRET_KEY, DATE, "us-east-1", "service", &to_sign),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn authorization_covers_the_s3_scope() {
+        let remote = S3Remote::new("https://s3.example.com", "", "notes", "", "AKIDEXAMPLE", SECRET_KEY).unwrap();
+        let headers = [("host", "s3.example.com"), ("x-amz-content-sha256", EMPTY_HASH), ("x-amz-date", TIMESTAMP)];
+        let authorization = remote.authorization("GET", "/notes/key.json", "", &headers, DATE, TIMESTAMP);
+
+        let canonical = canonical_request("GET", "/notes/key.json", "", &headers, EMPTY_HASH);
+        let scope = "20150830/us-east-1/s3/aws4_request";
+        let signature = signature(SECRET_KEY, DATE, "us-east-1", "s3", &string_to_sign(TIMESTAMP, scope, &canonical));
+        assert_eq!(
+            authorization,
+            format!(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                scope, signature
+            )
+        );
+    }
+
+    #[test]
+    fn encodes_path_style_keys() {
+        let remote = S3Remote::new("https://s3.example.com:443/", "eu-west-1", "my-bucket", "/team notes/", "a", "b").unwrap();
+        assert_eq!(remote.host, "s3.example.com");
+        assert_eq!(remote.region, "eu-west-1");
+
+        let path = remote.object_path("vaults/a b/notes/r~1+2.json");
+        assert_eq!(path, "/my-bucket/team notes/vaults/a b/notes/r~1+2.json");
+        assert_eq!(uri_encode(&path, true), "/my-bucket/team%20notes/vaults/a%20b/notes/r~1%2B2.json");
+
+        // In the query a slash is data like any other character.
+        let query = canonical_query(&[("delimiter", "/"), ("list-type", "2"), ("prefix", "team notes/vaults/")]);
+        assert_eq!(query, "delimiter=%2F&list-type=2&prefix=team%20notes%2Fvaults%2F");
+        assert_eq!(uri_encode("ü", false), "%C3%BC");
+    }
+}
@@ -0,0 +1,233 @@
+use super::http;
+use super::remote::{Listing, Object, Remote};
+use crate::attachments::percent_decode;
+use data_encoding::BASE64;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const SYNC_COLLECTION: &str = "noetiq-sync";
+const DAV: &str = "DAV:";
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+pub(crate) struct WebDavRemote {
+    agent: ureq::Agent,
+    // Always ends with a slash.
+    base: String,
+    authorization: Option<Zeroizing<String>>,
+    partial_dir: PathBuf,
+    collections: RefCell<HashSet<String>>,
+}
+
+// Servers differ on whether listings and downloads mark the same etag as weak.
+fn normalize_etag(etag: &str) -> String {
+    etag.trim().trim_start_matches("W/").to_string()
+}
+
+fn status_error(code: u16) -> String {
+    match code {
+        401 | 403 => "The WebDAV server rejected the user name or password".into(),
+        507 => "The WebDAV server is out of space".into(),
+        code => format!("The WebDAV server answered with status {}", code),
+    }
+}
+
+// Hrefs may be absolute URLs or paths, and are percent-encoded.
+fn href_name(href: &str) -> Option<String> {
+    let segment = href.trim_end_matches('/').rsplit('/').next()?;
+    percent_decode(segment).ok().filter(|name| !name.is_empty())
+}
+
+fn parse_multistatus(body: &str) -> Result<Vec<Listing>, String> {
+    let document = roxmltree::Document::parse(body).map_err(|_| "Invalid WebDAV response".to_string())?;
+
+    let mut listings = Vec::new();
+    for response in document.descendants().filter(|n| n.has_tag_name((DAV, "response"))) {
+        if response.descendants().any(|n| n.has_tag_name((DAV, "collection"))) {
+            continue;
+        }
+        let href = response
+            .children()
+            .find(|n| n.has_tag_name((DAV, "href")))
+            .and_then(|n| n.text())
+            .unwrap_or_default();
+        let etag = response
+            .descendants()
+            .find(|n| n.has_tag_name((DAV, "getetag")))
+            .and_then(|n| n.text())
+            .map(normalize_etag)
+            .unwrap_or_default();
+
+        if let Some(name) = href_name(href).filter(|n| !n.ends_with(".tmp") && !n.starts_with('.')) {
+            listings.push(Listing { name, etag });
+        }
+    }
+    Ok(listings)
+}
+
+impl WebDavRemote {
+    pub fn new(url: &str, username: &str, password: &str, cache_dir: &Path) -> Result<Self, String> {
+        let url = url.trim();
+        http::check_url(url)?;
+
+        let authorization = if username.is_empty() {
+            None
+        } else {
+            let credentials = Zeroizing::new(format!("{}:{}", username, password));
+            Some(Zeroizing::new(format!("Basic {}", BASE64.encode(credentials.as_bytes()))))
+        };
+
+        Ok(WebDavRemote {
+            agent: http::agent(),
+            base: format!("{}/{}/", url.trim_end_matches('/'), SYNC_COLLECTION),
+            authorization,
+            partial_dir: cache_dir.to_path_buf(),
+            collections: RefCell::new(HashSet::new()),
+        })
+    }
+
+    fn url(&self, name: &str) -> String {
+        format!("{}{}", self.base, name)
+    }
+
+    fn request(&self, method: &str, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<ureq::Response, String> {
+        let mut headers = headers.to_vec();
+        if let Some(authorization) = &self.authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+        http::send(&self.agent, method, url, &headers, body)
+    }
+
+    // PUT does not create missing parent collections.
+    fn make_collections(&self, name: &str) -> Result<(), String> {
+        let mut collection = String::new();
+        let mut collections = vec![collection.clone()];
+        let parts: Vec<&str> = name.split('/').collect();
+        for part in &parts[..parts.len() - 1] {
+            collection = format!("{}{}/", collection, part);
+            collections.push(collection.clone());
+        }
+
+        for collection in collections {
+            if self.collections.borrow().contains(&collection) {
+                continue;
+            }
+            let response = self.request("MKCOL", &self.url(&collection), &[], &[])?;
+            match response.status() {
+                // 405 means it already exists.
+                200 | 201 | 405 => {
+                    self.collections.borrow_mut().insert(collection);
+                }
+                409 if collection.is_empty() => return Err("The folder at the WebDAV address does not exist".into()),
+                code => return Err(status_error(code)),
+            }
+        }
+        Ok(())
+    }
+
+    fn partial_path(&self, name: &str) -> PathBuf {
+        self.partial_dir.join(hex::encode(Sha256::digest(self.url(name).as_bytes())))
+    }
+
+    fn discard_partial(&self, path: &Path) {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(path.with_extension("etag"));
+    }
+}
+
+impl Remote for WebDavRemote {
+    fn list(&self, prefix: &str) -> Result<Vec<Listing>, String> {
+        let headers = [("Depth", "1"), ("Content-Type", "application/xml; charset=utf-8")];
+        let response = self.request("PROPFIND", &self.url(&format!("{}/", prefix)), &headers, PROPFIND_BODY.as_bytes())?;
+        match response.status() {
+            207 => {}
+            404 => return Ok(Vec::new()),
+            code => return Err(status_error(code)),
+        }
+        let body = response.into_string().map_err(|_| "Invalid WebDAV response".to_string())?;
+        parse_multistatus(&body)
+    }
+
+    // Downloads land in a partial file first. When a transfer breaks, the next attempt asks only
+    // for the missing range, and If-Range makes the server send everything again if the object
+    // changed in between.
+    fn get(&self, name: &str) -> Result<Option<Object>, String> {
+        let partial = self.partial_path(name);
+        let etag_path = partial.with_extension("etag");
+        let offset = fs::metadata(&partial).map(|m| m.len()).unwrap_or(0);
+        let partial_etag = fs::read_to_string(&etag_path).unwrap_or_default();
+
+        let range = format!("bytes={}-", offset);
+        let mut headers = Vec::new();
+        if offset > 0 && !partial_etag.is_empty() {
+            headers.push(("Range", range.as_str()));
+            headers.push(("If-Range", partial_etag.as_str()));
+        }
+
+        let response = self.request("GET", &self.url(name), &headers, &[])?;
+        let append = match response.status() {
+            200 => false,
+            206 => true,
+            404 => {
+                self.discard_partial(&partial);
+                return Ok(None);
+            }
+            // The partial file already holds everything, or something that no longer fits.
+            416 if offset > 0 => {
+                self.discard_partial(&partial);
+                return self.get(name);
+            }
+            code => return Err(status_error(code)),
+        };
+        let etag = response.header("ETag").unwrap_or_default().to_string();
+
+        fs::create_dir_all(&self.partial_dir).map_err(|e| e.to_string())?;
+        let mut file = if append {
+            File::options().append(true).open(&partial)
+        } else {
+            File::create(&partial)
+        }
+        .map_err(|e| e.to_string())?;
+        if !append {
+            if etag.is_empty() {
+                let _ = fs::remove_file(&etag_path);
+            } else {
+                fs::write(&etag_path, &etag).map_err(|e| e.to_string())?;
+            }
+        }
+
+        io::copy(&mut response.into_reader(), &mut file)
+            .map_err(|_| "The download was interrupted, sync again to resume it".to_string())?;
+        drop(file);
+
+        let data = fs::read(&partial).map_err(|e| e.to_string())?;
+        self.discard_partial(&partial);
+        Ok(Some(Object { data, etag: normalize_etag(&etag) }))
+    }
+
+    fn put(&self, name: &str, data: &[u8]) -> Result<(), String> {
+        let url = self.url(name);
+        let headers = [("Content-Type", "application/octet-stream")];
+        let mut response = self.request("PUT", &url, &headers, data)?;
+        if matches!(response.status(), 404 | 409) {
+            self.make_collections(name)?;
+            response = self.request("PUT", &url, &headers, data)?;
+        }
+        match response.status() {
+            200 | 201 | 204 => Ok(()),
+            code => Err(status_error(code)),
+        }
+    }
+
+    fn delete(&self, name: &str) -> Result<(), String> {
+        let response = self.request("DELETE", &self.url(name), &[], &[])?;
+        match response.status() {
+            200 | 204 | 404 => Ok(()),
+            code => Err(status_error(code)),
+        }
+    }
+}
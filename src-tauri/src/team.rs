@@ -0,0 +1,230 @@
+use crate::cipher::{Cipher, CipherSuite};
+use crate::identity::{self, Contact, Identity, PublicKey};
+use crate::secret::{SecretKey, KEY_LEN};
+use crate::cipher_from_key;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::path::Path;
+
+// No .json extension, so listing a vault's notes never picks it up. It sits next to the data it
+// unlocks and anyone who can see the folder can read it: member keys are public and the vault key
+// is only in it wrapped for each of them.
+pub(crate) const MEMBERS_FILE: &str = "members";
+
+const INVALID_MEMBERS: &str = "Invalid team member list";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+struct Member {
+    name: String,
+    public_key: String,
+    wrapped: String,
+}
+
+// Everything but the MAC, which covers all of it.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+struct MemberList {
+    epoch: u64,
+    // The member who wrote this list, every key in it is wrapped by their identity.
+    updated_by: String,
+    ephemeral: String,
+    members: Vec<Member>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MembersFile {
+    #[serde(flatten)]
+    list: MemberList,
+    mac: String,
+}
+
+// What this store last accepted, kept in its own root index so whoever can write to the folder
+// cannot swap in a key of their own or bring back a list from before a member was removed.
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub(crate) struct TeamState {
+    pub epoch: u64,
+    pub members: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct MemberInfo {
+    pub name: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub you: bool,
+}
+
+pub(crate) struct Team {
+    key: SecretKey,
+    pub state: TeamState,
+    pub members: Vec<(String, PublicKey)>,
+}
+
+fn list_mac(key: &[u8], folder_id: &str, list: &MemberList) -> Result<Hmac<Sha256>, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| e.to_string())?;
+    mac.update(b"noetiq-team\0");
+    mac.update(folder_id.as_bytes());
+    mac.update(b"\0");
+    mac.update(&serde_json::to_vec(list).map_err(|e| e.to_string())?);
+    Ok(mac)
+}
+
+fn state(epoch: u64, members: &[(String, PublicKey)]) -> TeamState {
+    TeamState {
+        epoch,
+        members: members.iter().map(|(_, key)| identity::encode_public_key(key)).collect(),
+    }
+}
+
+impl Team {
+    // A vault key of its own, wrapped for the creator alone.
+    pub fn create(dir: &Path, folder_id: &str, identity: &Identity, name: &str) -> Result<Team, String> {
+        let mut key = SecretKey::zeroed();
+        OsRng.fill_bytes(key.as_mut());
+        let members = vec![(identity::check_name(name)?, identity.public_key()?)];
+        let team = Team {
+            key,
+            state: state(1, &members),
+            members,
+        };
+        team.save(dir, folder_id, identity)?;
+        Ok(team)
+    }
+
+    // Without a trusted state, when joining, the list has to come from one of the contacts.
+    pub fn open(
+        dir: &Path,
+        folder_id: &str,
+        identity: &Identity,
+        trusted: Option<&TeamState>,
+        contacts: &[Contact],
+    ) -> Result<Team, String> {
+        let data = fs::read(dir.join(MEMBERS_FILE)).map_err(|_| "This vault has no team members".to_string())?;
+        let file: MembersFile = serde_json::from_slice(&data).map_err(|_| INVALID_MEMBERS.to_string())?;
+        let list = file.list;
+        let own_key = identity.public_key()?;
+        let updated_by = identity::decode_public_key(&list.updated_by)?;
+
+        let trusted_writer = updated_by == own_key
+            || match trusted {
+                Some(state) => state.members.iter().any(|m| identity::decode_public_key(m).ok() == Some(updated_by)),
+                None => contacts.iter().any(|c| identity::decode_public_key(&c.public_key).ok() == Some(updated_by)),
+            };
+        if !trusted_writer {
+            return Err("The team member list was changed by someone who is not a member".into());
+        }
+        if trusted.is_some_and(|state| list.epoch < state.epoch) {
+            return Err("The team member list is older than the one this store last saw".into());
+        }
+
+        let mut members = Vec::new();
+        for member in &list.members {
+            members.push((member.name.clone(), identity::decode_public_key(&member.public_key)?));
+        }
+        let own = list
+            .members
+            .iter()
+            .zip(&members)
+            .find(|(_, (_, key))| *key == own_key)
+            .map(|(member, _)| member)
+            .ok_or("You are not a member of this team vault")?;
+
+        let ephemeral = identity::decode_public_key(&list.ephemeral)?;
+        let unwrapping = cipher_from_key(
+            &identity::unwrapping_key(identity, &ephemeral, &updated_by)?[..],
+            CipherSuite::default(),
+        )?;
+        let wrapped = BASE64.decode(own.wrapped.as_bytes()).map_err(|_| INVALID_MEMBERS.to_string())?;
+        let unwrapped = unwrapping.open(&wrapped).map_err(|_| INVALID_MEMBERS.to_string())?;
+        if unwrapped.len() != KEY_LEN {
+            return Err(INVALID_MEMBERS.into());
+        }
+        let mut key = SecretKey::zeroed();
+        key.as_mut().copy_from_slice(&unwrapped);
+
+        let expected = hex::decode(&file.mac).map_err(|_| INVALID_MEMBERS.to_string())?;
+        list_mac(&key[..], folder_id, &list)?
+            .verify_slice(&expected)
+            .map_err(|_| INVALID_MEMBERS.to_string())?;
+
+        Ok(Team {
+            key,
+            state: state(list.epoch, &members),
+            members,
+        })
+    }
+
+    pub fn cipher(&self, suite: CipherSuite) -> Result<Cipher, String> {
+        cipher_from_key(&self.key[..], suite)
+    }
+
+    // Each list is wrapped with a fresh ephemeral key.
+    pub fn save(&self, dir: &Path, folder_id: &str, identity: &Identity) -> Result<(), String> {
+        let ephemeral = Identity::generate();
+        let mut members = Vec::new();
+        for (name, public_key) in &self.members {
+            let wrapping = cipher_from_key(
+                &identity::wrapping_key(&ephemeral, identity, public_key)?[..],
+                CipherSuite::default(),
+            )?;
+            members.push(Member {
+                name: name.clone(),
+                public_key: identity::encode_public_key(public_key),
+                wrapped: BASE64.encode(&wrapping.seal(&self.key[..])?),
+            });
+        }
+
+        let list = MemberList {
+            epoch: self.state.epoch,
+            updated_by: identity::encode_public_key(&identity.public_key()?),
+            ephemeral: identity::encode_public_key(&ephemeral.public_key()?),
+            members,
+        };
+        let mac = hex::encode(list_mac(&self.key[..], folder_id, &list)?.finalize().into_bytes());
+        let file = MembersFile { list, mac };
+        crate::atomic_write(&dir.join(MEMBERS_FILE), &serde_json::to_vec_pretty(&file).map_err(|e| e.to_string())?)
+    }
+
+    // Changes to the list start a new epoch and only take effect once saved.
+    pub fn add(&mut self, name: &str, key: PublicKey) -> Result<(), String> {
+        if self.members.iter().any(|(_, member)| *member == key) {
+            return Err("This contact is already a member".into());
+        }
+        self.members.push((identity::check_name(name)?, key));
+        self.state = state(self.state.epoch + 1, &self.members);
+        Ok(())
+    }
+
+    // The removed member may have kept the key, so the vault gets a new one, for the caller to
+    // reseal the data with before saving the list.
+    pub fn remove(&mut self, identity: &Identity, key: &PublicKey) -> Result<(), String> {
+        if *key == identity.public_key()? {
+            return Err("Delete the vault here to leave the team".into());
+        }
+        let before = self.members.len();
+        self.members.retain(|(_, member)| member != key);
+        if self.members.len() == before {
+            return Err("Member not found".into());
+        }
+
+        OsRng.fill_bytes(self.key.as_mut());
+        self.state = state(self.state.epoch + 1, &self.members);
+        Ok(())
+    }
+
+    pub fn member_info(&self, identity: &Identity) -> Result<Vec<MemberInfo>, String> {
+        let own_key = identity.public_key()?;
+        Ok(self
+            .members
+            .iter()
+            .map(|(name, key)| MemberInfo {
+                name: name.clone(),
+                public_key: identity::encode_public_key(key),
+                fingerprint: identity::fingerprint(key),
+                you: *key == own_key,
+            })
+            .collect())
+    }
+}
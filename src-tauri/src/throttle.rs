@@ -0,0 +1,109 @@
+use crate::totp::INVALID_CODE;
+use crate::duress::INDEX_FILES;
+use crate::{shred, DECRYPT_FAILED};
+use chrono::Utc;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub(crate) const ATTEMPTS_FILE: &str = "attempts.json";
+pub(crate) const WRONG_PASSWORD: &str = "Wrong password";
+
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_DELAY_SECS: i64 = 60 * 60;
+const RECENT_LIMIT: usize = 20;
+
+// Serializes unlock attempts so parallel calls cannot all pass the back-off check at once.
+static ATTEMPT_GUARD: Mutex<()> = Mutex::new(());
+
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub(crate) struct AttemptLog {
+    #[serde(default)]
+    pub failed: u32,
+    #[serde(default)]
+    pub recent: Vec<i64>,
+    #[serde(default)]
+    pub wipe_after: Option<u32>,
+}
+
+#[derive(serde::Serialize, Default)]
+pub(crate) struct UnlockReport {
+    pub failed_attempts: u32,
+    pub recent: Vec<i64>,
+}
+
+impl AttemptLog {
+    pub fn load(vault_dir: &Path) -> AttemptLog {
+        fs::read(vault_dir.join(ATTEMPTS_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, vault_dir: &Path) -> Result<(), String> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(vault_dir.join(ATTEMPTS_FILE), data).map_err(|e| e.to_string())
+    }
+
+    fn retry_at(&self) -> Option<i64> {
+        if self.failed <= FREE_ATTEMPTS {
+            return None;
+        }
+        let exponent = (self.failed - FREE_ATTEMPTS).min(31);
+        let delay = 2i64.pow(exponent).min(MAX_DELAY_SECS);
+        self.recent.last().map(|last| last + delay)
+    }
+}
+
+pub(crate) fn attempt<T>(
+    vault_dir: &Path,
+    try_unlock: impl FnOnce() -> Result<T, String>,
+) -> Result<(T, UnlockReport), String> {
+    let _guard = ATTEMPT_GUARD.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut log = AttemptLog::load(vault_dir);
+    let now = Utc::now().timestamp();
+    if let Some(retry_at) = log.retry_at() {
+        if now < retry_at {
+            return Err(format!(
+                "Too many failed attempts, try again in {} seconds",
+                retry_at - now
+            ));
+        }
+    }
+
+    match try_unlock() {
+        Ok(value) => {
+            let report = UnlockReport {
+                failed_attempts: log.failed,
+                recent: std::mem::take(&mut log.recent),
+            };
+            if report.failed_attempts > 0 {
+                log.failed = 0;
+                log.save(vault_dir)?;
+            }
+            Ok((value, report))
+        }
+        Err(e) if e == DECRYPT_FAILED || e == INVALID_CODE => {
+            log.failed += 1;
+            log.recent.push(now);
+            if log.recent.len() > RECENT_LIMIT {
+                log.recent.remove(0);
+            }
+
+            if log.wipe_after.is_some_and(|limit| log.failed >= limit) {
+                // Overwrite the salted root indexes first, without them no vault key can be derived again.
+                INDEX_FILES
+                    .iter()
+                    .try_for_each(|file| shred::remove_file(&vault_dir.join(file), true))
+                    .and_then(|_| shred::remove_dir_all(vault_dir, false))
+                    .map_err(|e| format!("Failed to wipe vaults: {}", e))?;
+                return Err("Too many failed attempts, all vaults have been wiped".into());
+            }
+
+            log.save(vault_dir)?;
+            Err(if e == DECRYPT_FAILED { WRONG_PASSWORD.into() } else { e })
+        }
+        Err(e) => Err(e),
+    }
+}
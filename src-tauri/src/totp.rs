@@ -0,0 +1,256 @@
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+pub(crate) const CODE_REQUIRED: &str = "An authentication code is required";
+pub(crate) const INVALID_CODE: &str = "Invalid authentication code";
+
+const SECRET_LEN: usize = 20;
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// Accept codes from one step before and after the current one to tolerate clock drift.
+const SKEW_STEPS: i64 = 1;
+const BACKUP_CODES: usize = 10;
+const BACKUP_CODE_LEN: usize = 10;
+const BACKUP_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+// Lives inside the encrypted root index. The password alone can still decrypt the store,
+// so this is a policy the app enforces at unlock rather than part of the key.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub(crate) struct TotpConfig {
+    pub secret: Zeroizing<String>,
+    #[serde(default)]
+    pub backup_codes: Vec<String>,
+    #[serde(default)]
+    pub last_step: i64,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct TotpEnrollment {
+    pub secret: String,
+    pub uri: String,
+    pub qr: String,
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct TotpStatus {
+    pub enabled: bool,
+    pub backup_codes_left: usize,
+}
+
+fn code_at(secret: &[u8], step: i64) -> Result<u32, String> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).map_err(|e| e.to_string())?;
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    Ok(binary % 10u32.pow(DIGITS))
+}
+
+fn decode_secret(secret: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .map(Zeroizing::new)
+        .map_err(|_| "Invalid authenticator secret".to_string())
+}
+
+fn hash_backup_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+// Returns the matching step, rejecting steps at or before `last_step` so a code cannot be replayed.
+fn verify_code(secret: &str, code: &str, last_step: i64) -> Result<Option<i64>, String> {
+    verify_code_at(secret, code, last_step, Utc::now().timestamp() / STEP_SECS)
+}
+
+fn verify_code_at(secret: &str, code: &str, last_step: i64, now: i64) -> Result<Option<i64>, String> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let code: u32 = code.parse().map_err(|_| INVALID_CODE.to_string())?;
+
+    let secret = decode_secret(secret)?;
+    for step in (now - SKEW_STEPS)..=(now + SKEW_STEPS) {
+        if step > last_step && code_at(&secret, step)? == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn new_secret() -> String {
+    let mut secret = Zeroizing::new([0u8; SECRET_LEN]);
+    OsRng.fill_bytes(&mut secret[..]);
+    BASE32_NOPAD.encode(&secret[..])
+}
+
+pub(crate) fn enrollment(secret: &str, store_name: &str) -> Result<TotpEnrollment, String> {
+    let label: String = store_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let uri = format!(
+        "otpauth://totp/Noetiq:{}?secret={}&issuer=Noetiq&algorithm=SHA1&digits={}&period={}",
+        label, secret, DIGITS, STEP_SECS
+    );
+    let qr = QrCode::new(uri.as_bytes())
+        .map_err(|e| e.to_string())?
+        .render::<Dense1x2>()
+        .quiet_zone(true)
+        .build();
+
+    Ok(TotpEnrollment {
+        secret: secret.to_string(),
+        uri,
+        qr,
+    })
+}
+
+pub(crate) fn generate_backup_codes() -> (Vec<String>, Vec<String>) {
+    let mut codes = Vec::with_capacity(BACKUP_CODES);
+    for _ in 0..BACKUP_CODES {
+        let mut bytes = [0u8; BACKUP_CODE_LEN];
+        OsRng.fill_bytes(&mut bytes);
+        let chars: String = bytes
+            .iter()
+            .map(|b| BACKUP_ALPHABET[*b as usize % BACKUP_ALPHABET.len()] as char)
+            .collect();
+        codes.push(format!("{}-{}", &chars[..BACKUP_CODE_LEN / 2], &chars[BACKUP_CODE_LEN / 2..]));
+    }
+    let hashes = codes.iter().map(|code| hash_backup_code(code)).collect();
+    (codes, hashes)
+}
+
+impl TotpConfig {
+    pub fn enroll(secret: &str, code: &str) -> Result<(TotpConfig, Vec<String>), String> {
+        decode_secret(secret)?;
+        let step = verify_code(secret, code, i64::MIN)?.ok_or(INVALID_CODE)?;
+
+        let (codes, hashes) = generate_backup_codes();
+        Ok((
+            TotpConfig {
+                secret: Zeroizing::new(secret.to_string()),
+                backup_codes: hashes,
+                last_step: step,
+            },
+            codes,
+        ))
+    }
+
+    // Checks an authenticator or backup code, consuming it. The caller must persist the index.
+    pub fn verify(&mut self, code: &str) -> Result<(), String> {
+        if let Some(step) = verify_code(&self.secret, code, self.last_step)? {
+            self.last_step = step;
+            return Ok(());
+        }
+
+        let hash = hash_backup_code(code);
+        match self.backup_codes.iter().position(|stored| *stored == hash) {
+            Some(index) => {
+                self.backup_codes.remove(index);
+                Ok(())
+            }
+            None => Err(INVALID_CODE.into()),
+        }
+    }
+
+    pub fn status(config: Option<&TotpConfig>) -> TotpStatus {
+        TotpStatus {
+            enabled: config.is_some(),
+            backup_codes_left: config.map(|c| c.backup_codes.len()).unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The RFC 6238 SHA-1 secret, "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn code(step: i64) -> String {
+        format!("{:06}", code_at(&decode_secret(RFC_SECRET).unwrap(), step).unwrap())
+    }
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        // The RFC lists eight digits, these are their last six.
+        for (time, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(code(time / STEP_SECS), expected, "T = {}", time);
+        }
+    }
+
+    #[test]
+    fn accepts_one_step_of_clock_drift() {
+        let now = 1234567890 / STEP_SECS;
+        for drift in -SKEW_STEPS..=SKEW_STEPS {
+            let step = now + drift;
+            assert_eq!(verify_code_at(RFC_SECRET, &code(step), i64::MIN, now).unwrap(), Some(step));
+        }
+        for step in [now - SKEW_STEPS - 1, now + SKEW_STEPS + 1] {
+            assert_eq!(verify_code_at(RFC_SECRET, &code(step), i64::MIN, now).unwrap(), None);
+        }
+        assert_eq!(verify_code_at(RFC_SECRET, "12345", i64::MIN, now).unwrap(), None);
+        assert_eq!(verify_code_at(RFC_SECRET, "abcdef", i64::MIN, now).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_a_replayed_code() {
+        let now = 1234567890 / STEP_SECS;
+        let current = code(now);
+        assert_eq!(verify_code_at(RFC_SECRET, &current, now - 1, now).unwrap(), Some(now));
+        assert_eq!(verify_code_at(RFC_SECRET, &current, now, now).unwrap(), None);
+        // Nor an earlier one once a later step was used.
+        assert_eq!(verify_code_at(RFC_SECRET, &code(now - 1), now, now).unwrap(), None);
+
+        let mut config = TotpConfig {
+            secret: Zeroizing::new(RFC_SECRET.to_string()),
+            backup_codes: Vec::new(),
+            last_step: 0,
+        };
+        let live = code(Utc::now().timestamp() / STEP_SECS);
+        config.verify(&live).unwrap();
+        assert_eq!(config.verify(&live), Err(INVALID_CODE.to_string()));
+    }
+
+    #[test]
+    fn backup_codes_work_once() {
+        let (codes, hashes) = generate_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODES);
+        let mut config = TotpConfig {
+            secret: Zeroizing::new(RFC_SECRET.to_string()),
+            backup_codes: hashes,
+            last_step: 0,
+        };
+
+        // Typed without the dash and in capitals it is still the same code.
+        let typed = codes[3].replace('-', "").to_uppercase();
+        config.verify(&typed).unwrap();
+        assert_eq!(TotpConfig::status(Some(&config)).backup_codes_left, BACKUP_CODES - 1);
+        assert_eq!(config.verify(&codes[3]), Err(INVALID_CODE.to_string()));
+        config.verify(&codes[0]).unwrap();
+        assert_eq!(config.backup_codes.len(), BACKUP_CODES - 2);
+        assert_eq!(config.verify("aaaaa-aaaaa"), Err(INVALID_CODE.to_string()));
+    }
+}
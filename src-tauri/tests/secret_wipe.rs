@@ -0,0 +1,67 @@
+// Checks that key material is wiped before its memory is freed. Freed memory cannot be read
+// safely afterwards, so this binary installs an allocator that checks the watched buffer as it is
+// handed back.
+
+use noetiq_lib::secret::{Plaintext, SecretKey};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+struct Watcher;
+
+static WATCHED: AtomicUsize = AtomicUsize::new(0);
+static WIPED: AtomicBool = AtomicBool::new(false);
+static SERIAL: Mutex<()> = Mutex::new(());
+
+unsafe impl GlobalAlloc for Watcher {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if WATCHED.compare_exchange(ptr as usize, 0, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            let bytes = std::slice::from_raw_parts(ptr, layout.size());
+            WIPED.store(bytes.iter().all(|b| *b == 0), Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Watcher = Watcher;
+
+fn wiped_on_drop<T>(value: T, buffer: *const u8) -> bool {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    WIPED.store(false, Ordering::SeqCst);
+    WATCHED.store(buffer as usize, Ordering::SeqCst);
+    drop(value);
+    WIPED.load(Ordering::SeqCst)
+}
+
+#[test]
+fn secret_key_is_wiped_on_drop() {
+    let mut key = SecretKey::zeroed();
+    key.as_mut().fill(0xa5);
+    let buffer = key.as_ptr();
+    assert!(wiped_on_drop(key, buffer));
+}
+
+#[test]
+fn plaintext_is_wiped_on_drop() {
+    let mut plaintext: Plaintext = Zeroizing::new(Vec::with_capacity(64));
+    plaintext.extend_from_slice(b"a note nobody else should read");
+    let buffer = plaintext.as_ptr();
+    assert!(wiped_on_drop(plaintext, buffer));
+}
+
+#[test]
+fn clone_is_a_separate_wiped_copy() {
+    let mut key = SecretKey::zeroed();
+    key.as_mut().fill(0x5a);
+    let copy = key.clone();
+    assert!(copy == key);
+    let buffer = copy.as_ptr();
+    assert!(wiped_on_drop(copy, buffer));
+    assert_eq!(key[0], 0x5a);
+}
@@ -5,9 +5,16 @@ import "./styles/editor.css";
 import LoginView from "./components/LoginView";
 import SetPasswordView from "./components/SetPasswordView";
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 
-interface PublicProps {
-    hint: string,
+export interface PublicInfo {
+    format_version: number,
+    store_name: string,
+    hint_mode: "none" | "plain" | "encrypted",
+    hint: string | null,
+    hint_question: string | null,
+    keyfile: boolean,
+    totp: boolean,
 }
 
 function App() {
@@ -15,23 +22,51 @@ function App() {
   const [vaultName, setVaultName] = useState<string>("");
   const [vaultId, setVaultId] = useState<string>("");
 
-  const [hint, setHint] = useState<string>("");
+  const [publicInfo, setPublicInfo] = useState<PublicInfo | null>(null);
   const [fileExist, setFileExist] = useState<boolean>(false);
 
   const [isLoggedIn, setIsLoggedIn] = useState<boolean>(false);
 
   useEffect(() => {
-      invoke<string>("read_public")
+      invoke<PublicInfo>("read_public")
       .then((result) => {
-          const parsed = JSON.parse(result) as PublicProps;
           setFileExist(true);
-          setHint(parsed.hint);
+          setPublicInfo(result);
       })
       .catch((err) => {
           console.error("Error loading public.json:", err);
       });
   }, []);
 
+  useEffect(() => {
+      const unlisten = listen("locked", () => {
+          setVaultPath(null);
+          setIsLoggedIn(false);
+      });
+      return () => {
+          unlisten.then((off) => off());
+      };
+  }, []);
+
+  useEffect(() => {
+      if (!isLoggedIn) return;
+
+      let lastReport = 0;
+      const onActivity = () => {
+          const now = Date.now();
+          if (now - lastReport < 15000) return;
+          lastReport = now;
+          invoke("report_activity");
+      };
+
+      window.addEventListener("keydown", onActivity);
+      window.addEventListener("mousemove", onActivity);
+      return () => {
+          window.removeEventListener("keydown", onActivity);
+          window.removeEventListener("mousemove", onActivity);
+      };
+  }, [isLoggedIn]);
+
   const onVaultClose = () => {
     setVaultPath(null);
     };
@@ -49,10 +84,13 @@ function App() {
         <VaultOpen path={vaultPath} name={vaultName} id={vaultId} onVaultClose={onVaultClose} />
       ) : isLoggedIn ? (
         <MainView onVaultSelect={(path) => setVaultPath(path)} onVaultNameSet={setVaultName} onVaultIdSet={setVaultId} />
-      ) : fileExist ? (
-        <LoginView hint={hint} onLoginSuccess={() => setIsLoggedIn(true)} />
+      ) : fileExist && publicInfo ? (
+        <LoginView info={publicInfo} onLoginSuccess={() => setIsLoggedIn(true)} />
       ) : (
-        <SetPasswordView />
+        <SetPasswordView onPasswordSet={() => {
+          setFileExist(true);
+          setIsLoggedIn(true);
+        }} />
       )}
     </>
   );  
@@ -1,50 +1,78 @@
-import React, { useState } from "react";
-import { usePassword } from "./PasswordContext";
+import React, { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import PasswordStrengthMeter, { PasswordStrength } from "./PasswordStrengthMeter";
+import KeyfilePicker from "./KeyfilePicker";
+import HintFields, { emptyHint, HintState, toHintInput } from "./HintFields";
+
+type CipherSuite = "aes-256-gcm" | "xchacha20-poly1305";
 
 interface Props {
   onCloseDialog: () => void;
 }
 
 const ChangePasswordDialog: React.FC<Props> = ({onCloseDialog}) => {
-  const { password, setPassword } = usePassword();
-
   const [currentPasswordInput, setCurrentPasswordInput] = useState("");
   const [newPassword, setNewPassword] = useState("");
   const [confirmPassword, setConfirmPassword] = useState("");
-  const [hint, setHint] = useState("");
-
-  const handlePasswordChange = (e: React.FormEvent) => {
+  const [hint, setHint] = useState<HintState>(emptyHint);
+  const [currentKeyfile, setCurrentKeyfile] = useState<string | null>(null);
+  const [newKeyfile, setNewKeyfile] = useState<string | null>(null);
+  const [strength, setStrength] = useState<PasswordStrength | null>(null);
+  const [currentCipher, setCurrentCipher] = useState<CipherSuite | null>(null);
+  const [cipher, setCipher] = useState<CipherSuite | null>(null);
+  const [historyEnabled, setHistoryEnabled] = useState(false);
+
+  useEffect(() => {
+    invoke<{ cipher: CipherSuite }>("get_storage_settings")
+      .then((settings) => {
+        setCurrentCipher(settings.cipher);
+        setCipher(settings.cipher);
+      })
+      .catch((err) => console.error("Error loading storage settings:", err));
+    invoke<{ enabled: boolean }>("get_history_settings")
+      .then((status) => setHistoryEnabled(status.enabled))
+      .catch((err) => console.error("Error loading history settings:", err));
+  }, []);
+
+  const handlePasswordChange = async (e: React.FormEvent) => {
     e.preventDefault();
 
-    if (currentPasswordInput !== password) {
-      alert("Current password is incorrect");
-      return;
-    }
-
     if (newPassword !== confirmPassword) {
       alert("New password and confirmation do not match");
       return;
     }
 
-    if(currentPasswordInput === newPassword) {
+    if(currentPasswordInput === newPassword && currentKeyfile === newKeyfile && cipher === currentCipher) {
         alert("New password cannot be the same as current password");
         return;
     }
 
-    invoke("reencrypt_data", {
-      oldPassword: password,
-      newPassword: newPassword,
-      newHint: hint
-    });
+    if (strength?.hint_warning && !confirm(strength.hint_warning + ". Use this hint anyway?")) {
+      return;
+    }
+
+    try {
+      await invoke("reencrypt_data", {
+        oldPassword: currentPasswordInput,
+        oldKeyfile: currentKeyfile,
+        newPassword: newPassword,
+        newKeyfile: newKeyfile,
+        newHint: toHintInput(hint),
+        cipher
+      });
+    } catch (err) {
+      alert("Failed to change password: " + err);
+      return;
+    }
 
-    setPassword(newPassword);
     alert("Password changed successfully!");
 
     setCurrentPasswordInput("");
     setNewPassword("");
     setConfirmPassword("");
-    setHint("");
+    setHint(emptyHint);
+    setCurrentKeyfile(null);
+    setNewKeyfile(null);
 
     onCloseDialog();
   };
@@ -62,6 +90,8 @@ const ChangePasswordDialog: React.FC<Props> = ({onCloseDialog}) => {
           onChange={(e) => setCurrentPasswordInput(e.target.value)}
         />
 
+        <KeyfilePicker label="Current keyfile (if any)" value={currentKeyfile} onChange={setCurrentKeyfile} optional />
+
         <p className="dialog-input-label">New password</p>
         <input
           className="dialog-input-text"
@@ -72,6 +102,8 @@ const ChangePasswordDialog: React.FC<Props> = ({onCloseDialog}) => {
           onChange={(e) => setNewPassword(e.target.value)}
         />
 
+        <PasswordStrengthMeter password={newPassword} hint={hint.mode === "none" ? "" : hint.hint} onEvaluate={setStrength} />
+
         <p className="dialog-input-label">Confirm new password</p>
         <input
           className="dialog-input-text"
@@ -82,14 +114,39 @@ const ChangePasswordDialog: React.FC<Props> = ({onCloseDialog}) => {
           onChange={(e) => setConfirmPassword(e.target.value)}
         />
 
-        <p className="dialog-input-label">New password hint</p>
-        <input
-          className="dialog-input-text"
-          type="text"
-          placeholder="New password hint..."
-          value={hint}
-          onChange={(e) => setHint(e.target.value)}
-        />
+        <KeyfilePicker label="New keyfile (optional)" value={newKeyfile} onChange={setNewKeyfile} optional />
+
+        <HintFields value={hint} onChange={setHint} />
+
+        {cipher && (
+          <>
+            <p className="dialog-input-label">Cipher</p>
+            <div className="swap-button-container">
+              <button
+                type="button"
+                onClick={() => setCipher("aes-256-gcm")}
+                className={`swap-button swap-button-left ${cipher === "aes-256-gcm" ? 'swap-button-selected' : ''}`}
+              >
+                AES-256-GCM
+              </button>
+              <button
+                type="button"
+                onClick={() => setCipher("xchacha20-poly1305")}
+                className={`swap-button swap-button-right ${cipher === "xchacha20-poly1305" ? 'swap-button-selected' : ''}`}
+              >
+                XChaCha20-Poly1305
+              </button>
+            </div>
+          </>
+        )}
+
+        {historyEnabled && (
+          <p className="dialog-about-text">
+            History keeps earlier versions sealed under the password they were written with, so the
+            current password will still open them. Turning history off and on again starts it over
+            on this device, versions already pushed to the history folder stay there.
+          </p>
+        )}
 
         <button className="dialog-button" type="submit">
           Change password
@@ -0,0 +1,43 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { ContactInfo } from "./ContactsSettings";
+
+interface Props {
+    selected: string[],
+    onChange: (keys: string[]) => void,
+}
+
+const ContactPicker = ({ selected, onChange }: Props) => {
+    const [contacts, setContacts] = useState<ContactInfo[]>([]);
+
+    useEffect(() => {
+        invoke<ContactInfo[]>("get_contacts")
+            .then(setContacts)
+            .catch((err) => console.error("Error loading contacts:", err));
+    }, []);
+
+    const toggle = (key: string) => {
+        onChange(selected.includes(key) ? selected.filter((k) => k !== key) : [...selected, key]);
+    };
+
+    if (contacts.length === 0) {
+        return <p className="dialog-about-text">Add contacts in the settings to share with them.</p>;
+    }
+
+    return (
+        <>
+            {contacts.map((contact) => (
+                <label key={contact.public_key} className="dialog-about-text">
+                    <input
+                        type="checkbox"
+                        checked={selected.includes(contact.public_key)}
+                        onChange={() => toggle(contact.public_key)}
+                    />
+                    {` ${contact.name} (${contact.fingerprint})`}
+                </label>
+            ))}
+        </>
+    );
+};
+
+export default ContactPicker;
@@ -0,0 +1,141 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { open, save } from "@tauri-apps/plugin-dialog";
+
+interface IdentityInfo {
+    public_key: string,
+    fingerprint: string,
+}
+
+export interface ContactInfo {
+    name: string,
+    public_key: string,
+    fingerprint: string,
+}
+
+const ContactsSettings = () => {
+    const [identity, setIdentity] = useState<IdentityInfo | null>(null);
+    const [contacts, setContacts] = useState<ContactInfo[]>([]);
+    const [cardName, setCardName] = useState("");
+    const [newName, setNewName] = useState("");
+    const [newKey, setNewKey] = useState("");
+    const [cardFingerprint, setCardFingerprint] = useState<string | null>(null);
+
+    const loadContacts = () => {
+        invoke<ContactInfo[]>("get_contacts")
+            .then(setContacts)
+            .catch((err) => console.error("Error loading contacts:", err));
+    };
+
+    useEffect(() => {
+        invoke<IdentityInfo>("get_identity")
+            .then(setIdentity)
+            .catch((err) => console.error("Error loading identity:", err));
+        loadContacts();
+    }, []);
+
+    const handleExport = async () => {
+        const path = await save({
+            title: "Save contact card",
+            defaultPath: `${cardName.trim() || "contact"}.json`,
+            filters: [{ name: "Noetiq contact", extensions: ["json"] }],
+        });
+        if (!path) return;
+        invoke("export_identity", { name: cardName, path })
+            .catch((err) => alert("Failed to save the contact card: " + err));
+    };
+
+    const handleLoadCard = async () => {
+        const selected = await open({ multiple: false, directory: false, title: "Load contact card" });
+        if (typeof selected !== "string") return;
+        invoke<ContactInfo>("read_contact_card", { path: selected })
+            .then((card) => {
+                setNewName(card.name);
+                setNewKey(card.public_key);
+                setCardFingerprint(card.fingerprint);
+            })
+            .catch((err) => alert("Failed to load the contact card: " + err));
+    };
+
+    const handleAdd = () => {
+        invoke<ContactInfo>("add_contact", { name: newName, publicKey: newKey })
+            .then(() => {
+                setNewName("");
+                setNewKey("");
+                setCardFingerprint(null);
+                loadContacts();
+            })
+            .catch((err) => alert("Failed to add the contact: " + err));
+    };
+
+    const handleRemove = (contact: ContactInfo) => {
+        invoke("remove_contact", { publicKey: contact.public_key })
+            .then(loadContacts)
+            .catch((err) => alert("Failed to remove the contact: " + err));
+    };
+
+    return (
+        <>
+            <p className="dialog-input-label">Your public key</p>
+            {identity && (
+                <>
+                    <input className="dialog-input-text" type="text" readOnly value={identity.public_key} />
+                    <p className="dialog-about-text">{`Fingerprint ${identity.fingerprint}`}</p>
+                </>
+            )}
+            <input
+                className="dialog-input-text"
+                type="text"
+                placeholder="Name on your contact card..."
+                value={cardName}
+                onChange={(e) => setCardName(e.target.value)}
+            />
+            <button className="dialog-button-neutral" onClick={handleExport} disabled={!cardName.trim()}>
+                Save contact card
+            </button>
+
+            <p className="dialog-input-label">Contacts</p>
+            {contacts.length === 0 && (
+                <p className="dialog-about-text">Add the public keys of the people you share notes with.</p>
+            )}
+            {contacts.map((contact) => (
+                <div key={contact.public_key} className="swap-button-container">
+                    <p className="dialog-about-text">
+                        <strong>{contact.name}</strong>{` ${contact.fingerprint}`}
+                    </p>
+                    <button className="dialog-button-neutral" onClick={() => handleRemove(contact)}>Remove</button>
+                </div>
+            ))}
+            <input
+                className="dialog-input-text"
+                type="text"
+                placeholder="Contact name..."
+                value={newName}
+                onChange={(e) => setNewName(e.target.value)}
+            />
+            <input
+                className="dialog-input-text"
+                type="text"
+                placeholder="noetiq-pk-..."
+                value={newKey}
+                onChange={(e) => {
+                    setNewKey(e.target.value);
+                    setCardFingerprint(null);
+                }}
+            />
+            {cardFingerprint && (
+                <p className="dialog-about-text">
+                    {`Fingerprint ${cardFingerprint}, check it with the owner before adding the contact`}
+                </p>
+            )}
+            <div className="swap-button-container">
+                <button className="dialog-button-neutral" onClick={handleLoadCard}>Load contact card</button>
+                <button className="dialog-button-neutral" onClick={handleAdd} disabled={!newName.trim() || !newKey.trim()}>
+                    Add contact
+                </button>
+            </div>
+        </>
+    );
+};
+
+export default ContactsSettings;
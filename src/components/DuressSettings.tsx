@@ -0,0 +1,110 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import KeyfilePicker from "./KeyfilePicker";
+import { PublicInfo } from "../App";
+
+interface DuressStatus {
+    enabled: boolean,
+    wipe_primary: boolean,
+}
+
+const DuressSettings = () => {
+    const [status, setStatus] = useState<DuressStatus | null>(null);
+    const [needsKeyfile, setNeedsKeyfile] = useState(false);
+    const [editing, setEditing] = useState(false);
+    const [password, setPassword] = useState("");
+    const [keyfile, setKeyfile] = useState<string | null>(null);
+    const [wipePrimary, setWipePrimary] = useState(false);
+
+    const loadStatus = () => {
+        invoke<DuressStatus>("get_duress_status")
+            .then(setStatus)
+            .catch((err) => console.error("Error loading duress settings:", err));
+    };
+
+    useEffect(loadStatus, []);
+
+    useEffect(() => {
+        invoke<PublicInfo>("read_public")
+            .then((info) => setNeedsKeyfile(info.keyfile))
+            .catch((err) => console.error("Error loading public.json:", err));
+    }, []);
+
+    const handleSave = () => {
+        if (status?.enabled && !confirm("Replacing the duress password discards the current decoy vaults. Continue?")) {
+            return;
+        }
+        invoke("set_duress_password", { password, keyfile, wipePrimary })
+            .then(() => {
+                setEditing(false);
+                setPassword("");
+                setKeyfile(null);
+                loadStatus();
+            })
+            .catch((err) => alert("Failed to set duress password: " + err));
+    };
+
+    const handleRemove = () => {
+        if (!confirm("Removing the duress password discards the decoy vaults. Continue?")) {
+            return;
+        }
+        invoke("remove_duress_password")
+            .then(loadStatus)
+            .catch((err) => alert("Failed to remove duress password: " + err));
+    };
+
+    if (!status) return null;
+
+    return (
+        <>
+            <p className="dialog-input-label">Duress password</p>
+            <p className="dialog-about-text">
+                {status.enabled
+                    ? `Set, ${status.wipe_primary ? "using it destroys these vaults" : "these vaults are kept"}`
+                    : "Opens a separate set of decoy vaults instead of these ones"}
+            </p>
+
+            {editing ? (
+                <>
+                    <input
+                        className="dialog-input-text"
+                        type="password"
+                        value={password}
+                        onChange={(e) => setPassword(e.target.value)}
+                        placeholder="Duress password..."
+                    />
+                    {needsKeyfile && (
+                        <KeyfilePicker label="Keyfile" value={keyfile} onChange={setKeyfile} />
+                    )}
+                    <p className="dialog-input-label">When the duress password is used</p>
+                    <div className="swap-button-container">
+                        <button
+                            onClick={() => setWipePrimary(false)}
+                            className={`swap-button swap-button-left ${!wipePrimary ? 'swap-button-selected' : ''}`}
+                        >
+                            Keep these vaults
+                        </button>
+                        <button
+                            onClick={() => setWipePrimary(true)}
+                            className={`swap-button swap-button-right ${wipePrimary ? 'swap-button-selected' : ''}`}
+                        >
+                            Destroy these vaults
+                        </button>
+                    </div>
+                    <button className="dialog-button-neutral" onClick={handleSave} disabled={!password}>Save</button>
+                </>
+            ) : (
+                <div className="swap-button-container">
+                    <button className="dialog-button-neutral" onClick={() => setEditing(true)}>
+                        {status.enabled ? "Change" : "Set up"}
+                    </button>
+                    {status.enabled && (
+                        <button className="dialog-button-neutral" onClick={handleRemove}>Remove</button>
+                    )}
+                </div>
+            )}
+        </>
+    );
+};
+
+export default DuressSettings;
@@ -7,7 +7,8 @@ import {
   useImperativeHandle,
 } from "react";
 import EditorJS from "@editorjs/editorjs";
-import { EDITOR_JS_TOOLS } from "./Tool";
+import { editorTools } from "./Tool";
+import { resolveAttachmentUrls } from "./attachments";
 import DragDrop from "editorjs-drag-drop";
 import Undo from "editorjs-undo";
 import MultiBlockSelectionPlugin from "editorjs-multiblock-selection-plugin";
@@ -23,6 +24,7 @@ interface EditorProps {
   title?: string;
   onTitleChange: (newTitle: string) => void;
   editDate: string;
+  vaultId: string;
 }
 
 export interface EditorHandle {
@@ -40,6 +42,7 @@ const Editor = forwardRef<EditorHandle, EditorProps>(
       title,
       onTitleChange,
       editDate,
+      vaultId,
     },
     ref
   ) => {
@@ -77,8 +80,8 @@ const Editor = forwardRef<EditorHandle, EditorProps>(
 
         const editor = new EditorJS({
           holder: editorBlock,
-          data: initialData,
-          tools: EDITOR_JS_TOOLS,
+          data: resolveAttachmentUrls(initialData, vaultId),
+          tools: editorTools(vaultId),
           onReady: () => {
             new DragDrop(editor, "2px solid #fff");
             new Undo({ editor });
@@ -0,0 +1,69 @@
+export type HintInput =
+    | { mode: "none" }
+    | { mode: "plain", hint: string }
+    | { mode: "encrypted", hint: string, question: string, answer: string };
+
+export interface HintState {
+    mode: HintInput["mode"],
+    hint: string,
+    question: string,
+    answer: string,
+}
+
+export const emptyHint: HintState = { mode: "none", hint: "", question: "", answer: "" };
+
+export const toHintInput = (state: HintState): HintInput => {
+    switch (state.mode) {
+        case "plain":
+            return { mode: "plain", hint: state.hint };
+        case "encrypted":
+            return { mode: "encrypted", hint: state.hint, question: state.question, answer: state.answer };
+        default:
+            return { mode: "none" };
+    }
+};
+
+const modes: { label: string, mode: HintInput["mode"] }[] = [
+    { label: "No hint", mode: "none" },
+    { label: "Visible", mode: "plain" },
+    { label: "Behind a question", mode: "encrypted" },
+];
+
+interface Props {
+    value: HintState,
+    onChange: (value: HintState) => void,
+}
+
+const HintFields = ({ value, onChange }: Props) => {
+    return (
+        <>
+            <p className="dialog-input-label">Password hint</p>
+            <div className="swap-button-container">
+                {modes.map((option, index) => (
+                    <button
+                        key={option.mode}
+                        type="button"
+                        onClick={() => onChange({ ...value, mode: option.mode })}
+                        className={`swap-button ${index === 0 ? 'swap-button-left' : index === modes.length - 1 ? 'swap-button-right' : 'swap-button-middle'} ${value.mode === option.mode ? 'swap-button-selected' : ''}`}
+                    >
+                        {option.label}
+                    </button>
+                ))}
+            </div>
+
+            {value.mode !== "none" && (
+                <input className="dialog-input-text" type="text" value={value.hint} onChange={(e) => onChange({ ...value, hint: e.target.value })} placeholder="Password hint..." />
+            )}
+
+            {value.mode === "encrypted" && (
+                <>
+                    <p className="dialog-about-text">The hint is only shown after answering this question. Anyone can see the question.</p>
+                    <input className="dialog-input-text" type="text" value={value.question} onChange={(e) => onChange({ ...value, question: e.target.value })} placeholder="Security question..." required />
+                    <input className="dialog-input-text" type="password" value={value.answer} onChange={(e) => onChange({ ...value, answer: e.target.value })} placeholder="Answer..." required />
+                </>
+            )}
+        </>
+    );
+};
+
+export default HintFields;
@@ -0,0 +1,97 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { open } from "@tauri-apps/plugin-dialog";
+
+interface HistoryStatus {
+    enabled: boolean,
+    remote: string | null,
+}
+
+const HistorySettings = () => {
+    const [status, setStatus] = useState<HistoryStatus | null>(null);
+    const [busy, setBusy] = useState(false);
+
+    const loadStatus = () => {
+        invoke<HistoryStatus>("get_history_settings")
+            .then(setStatus)
+            .catch((err) => console.error("Error loading history settings:", err));
+    };
+
+    useEffect(loadStatus, []);
+
+    const setEnabled = (enabled: boolean) => {
+        if (!enabled && !confirm("Turning history off deletes every earlier version of your notes. Continue?")) {
+            return;
+        }
+        invoke("set_history_enabled", { enabled })
+            .then(loadStatus)
+            .catch((err) => alert("Failed to update history: " + err));
+    };
+
+    const handleChooseRemote = async () => {
+        const selected = await open({ multiple: false, directory: true, title: "History folder" });
+        if (typeof selected !== "string") return;
+        invoke("set_history_remote", { remote: selected })
+            .then(loadStatus)
+            .catch((err) => alert("Failed to set the history folder: " + err));
+    };
+
+    const handlePush = () => {
+        setBusy(true);
+        invoke("push_history")
+            .catch((err) => alert("Push failed: " + err))
+            .finally(() => setBusy(false));
+    };
+
+    const handlePull = () => {
+        setBusy(true);
+        invoke<number>("pull_history")
+            .then((updated) => alert(updated > 0 ? `${updated} vault(s) updated` : "Already up to date"))
+            .catch((err) => alert("Pull failed: " + err))
+            .finally(() => setBusy(false));
+    };
+
+    if (!status) return null;
+
+    return (
+        <>
+            <p className="dialog-input-label">History</p>
+            <p className="dialog-about-text">
+                Keeps every vault as a git repository of its encrypted files, so earlier versions of a note can be restored
+            </p>
+            <div className="swap-button-container">
+                <button
+                    onClick={() => setEnabled(false)}
+                    className={`swap-button swap-button-left ${!status.enabled ? 'swap-button-selected' : ''}`}
+                >
+                    Off
+                </button>
+                <button
+                    onClick={() => setEnabled(true)}
+                    className={`swap-button swap-button-right ${status.enabled ? 'swap-button-selected' : ''}`}
+                >
+                    On
+                </button>
+            </div>
+
+            {status.enabled && (
+                <>
+                    {status.remote && <p className="dialog-about-text">{status.remote}</p>}
+                    <div className="swap-button-container">
+                        <button className="dialog-button-neutral" onClick={handleChooseRemote}>
+                            {status.remote ? "Change folder" : "Choose folder"}
+                        </button>
+                        {status.remote && (
+                            <>
+                                <button className="dialog-button-neutral" onClick={handlePush} disabled={busy}>Push</button>
+                                <button className="dialog-button-neutral" onClick={handlePull} disabled={busy}>Pull</button>
+                            </>
+                        )}
+                    </div>
+                </>
+            )}
+        </>
+    );
+};
+
+export default HistorySettings;
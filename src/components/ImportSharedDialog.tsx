@@ -0,0 +1,129 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+interface Props {
+    path: string,
+    vault?: string,
+    handleCloseDialog: () => void,
+    onImported?: () => void,
+}
+
+interface VaultProps {
+    icon: string;
+    name: string;
+    folder_id: string;
+}
+
+interface SharedFileInfo {
+    content: "note" | "vault",
+    password: boolean,
+    sender: { name: string | null, fingerprint: string } | null,
+    readable: boolean,
+}
+
+const ImportSharedDialog = ({ path, vault, handleCloseDialog, onImported }: Props) => {
+    const [info, setInfo] = useState<SharedFileInfo | null>(null);
+    const [error, setError] = useState<string | null>(null);
+    const [vaults, setVaults] = useState<VaultProps[]>([]);
+    const [selectedVault, setSelectedVault] = useState(vault ?? "");
+    const [password, setPassword] = useState("");
+    const [importing, setImporting] = useState(false);
+
+    useEffect(() => {
+        invoke<SharedFileInfo>("describe_shared_file", { path })
+            .then(setInfo)
+            .catch((err) => setError(String(err)));
+        invoke<string>("get_vaults")
+            .then((result) => {
+                const parsed = JSON.parse(result) as VaultProps[];
+                setVaults(parsed);
+                setSelectedVault((current) => current || (parsed[0]?.folder_id ?? ""));
+            })
+            .catch((err) => console.error("Error loading vaults:", err));
+    }, [path]);
+
+    const handleImport = async () => {
+        if (!info) return;
+        setImporting(true);
+        const sharePassword = info.password ? password : null;
+        try {
+            if (info.content === "vault") {
+                await invoke("import_shared_vault", { path, sharePassword });
+            } else {
+                await invoke("import_shared_note", { vaultfolder: selectedVault, path, sharePassword });
+            }
+            setPassword("");
+            onImported?.();
+            handleCloseDialog();
+        } catch (error) {
+            alert("Failed to import: " + error);
+        } finally {
+            setImporting(false);
+        }
+    };
+
+    const fileName = path.split(/[\\/]/).pop();
+    const isVault = info?.content === "vault";
+    const needsVault = !isVault && vaults.length === 0;
+
+    return (
+        <div id="openvault-container">
+            <p className="dialog-input-label">{isVault ? "Import shared vault" : "Import shared note"}</p>
+            <p className="dialog-about-text">{fileName}</p>
+            {error && <p className="dialog-about-text">{error}</p>}
+            {info?.sender && (
+                <p className="dialog-about-text">
+                    {info.sender.name
+                        ? `Shared by ${info.sender.name}`
+                        : `Shared by someone who is not in your contacts, fingerprint ${info.sender.fingerprint}`}
+                </p>
+            )}
+            {info && !info.readable && (
+                <p className="dialog-about-text">This file was shared with other people, not with you.</p>
+            )}
+            {info?.readable && (needsVault ? (
+                <p className="dialog-about-text">Create a vault first to import notes into it.</p>
+            ) : (
+                <>
+                    {!isVault && (
+                        <>
+                            <p className="dialog-input-label">Vault</p>
+                            <select
+                                className="dialog-input-text"
+                                value={selectedVault}
+                                onChange={(e) => setSelectedVault(e.target.value)}
+                            >
+                                {vaults.map((v) => (
+                                    <option key={v.folder_id} value={v.folder_id}>
+                                        {`${v.icon} ${v.name}`}
+                                    </option>
+                                ))}
+                            </select>
+                        </>
+                    )}
+                    {info.password && (
+                        <>
+                            <p className="dialog-input-label">Share password</p>
+                            <input
+                                className="dialog-input-text"
+                                type="password"
+                                placeholder="Password the note was shared with..."
+                                value={password}
+                                onChange={(e) => setPassword(e.target.value)}
+                            />
+                        </>
+                    )}
+                    <button
+                        className="dialog-button-neutral"
+                        onClick={handleImport}
+                        disabled={importing || (info.password && !password) || (!isVault && !selectedVault)}
+                    >
+                        {importing ? "Importing..." : isVault ? "Import vault" : "Import note"}
+                    </button>
+                </>
+            ))}
+        </div>
+    );
+};
+
+export default ImportSharedDialog;
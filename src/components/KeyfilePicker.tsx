@@ -0,0 +1,37 @@
+import { open } from "@tauri-apps/plugin-dialog";
+
+interface Props {
+    label: string,
+    value: string | null,
+    onChange: (path: string | null) => void,
+    optional?: boolean,
+}
+
+const KeyfilePicker = ({ label, value, onChange, optional }: Props) => {
+
+    const handleChoose = async () => {
+        const selected = await open({ multiple: false, directory: false, title: label });
+        if (typeof selected === "string") {
+            onChange(selected);
+        }
+    };
+
+    return (
+        <>
+            <p className="dialog-input-label">{label}</p>
+            {value && <p className="dialog-about-text">{value}</p>}
+            <div className="swap-button-container">
+                <button className="dialog-button-neutral" type="button" onClick={handleChoose}>
+                    {value ? "Change keyfile" : "Choose keyfile"}
+                </button>
+                {optional && value && (
+                    <button className="dialog-button-neutral" type="button" onClick={() => onChange(null)}>
+                        No keyfile
+                    </button>
+                )}
+            </div>
+        </>
+    );
+};
+
+export default KeyfilePicker;
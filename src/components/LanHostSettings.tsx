@@ -0,0 +1,77 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+interface LanHostStatus {
+    configured: boolean,
+    running: boolean,
+    address: string | null,
+    code: string | null,
+    peers: number,
+}
+
+const REFRESH_INTERVAL = 3000;
+
+// Shown while sync is set up to host on this device.
+const LanHostSettings = () => {
+    const [status, setStatus] = useState<LanHostStatus | null>(null);
+
+    const loadStatus = () => {
+        invoke<LanHostStatus>("get_lan_host_status")
+            .then(setStatus)
+            .catch((err) => console.error("Error loading LAN host status:", err));
+    };
+
+    useEffect(loadStatus, []);
+
+    // Picks up devices pairing and codes running out.
+    useEffect(() => {
+        if (!status?.running) return;
+        const timer = setInterval(loadStatus, REFRESH_INTERVAL);
+        return () => clearInterval(timer);
+    }, [status?.running]);
+
+    const handleStart = () => {
+        invoke("start_lan_host")
+            .then(loadStatus)
+            .catch((err) => alert("Failed to start hosting: " + err));
+    };
+
+    const handleStop = () => {
+        invoke("stop_lan_host").then(loadStatus);
+    };
+
+    const handlePair = () => {
+        invoke<string>("open_lan_pairing")
+            .then(loadStatus)
+            .catch((err) => alert("Failed to start pairing: " + err));
+    };
+
+    if (!status?.configured) return null;
+
+    return (
+        <>
+            <p className="dialog-about-text">
+                {status.running
+                    ? `Hosting at ${status.address ?? "this device"}, ${status.peers} paired device(s)`
+                    : "Not hosting, other devices cannot sync right now"}
+            </p>
+            {status.code && (
+                <p className="dialog-about-text">
+                    {`Pairing code ${status.code.slice(0, 3)} ${status.code.slice(3)}, enter it on the other device within 5 minutes`}
+                </p>
+            )}
+            <div className="swap-button-container">
+                {status.running ? (
+                    <>
+                        <button className="dialog-button-neutral" onClick={handlePair}>Pair a device</button>
+                        <button className="dialog-button-neutral" onClick={handleStop}>Stop hosting</button>
+                    </>
+                ) : (
+                    <button className="dialog-button-neutral" onClick={handleStart}>Start hosting</button>
+                )}
+            </div>
+        </>
+    );
+};
+
+export default LanHostSettings;
@@ -1,31 +1,61 @@
 import { useState } from "react";
-import { usePassword } from "./PasswordContext";
 import { invoke } from "@tauri-apps/api/core";
+import { PublicInfo } from "../App";
+import KeyfilePicker from "./KeyfilePicker";
+
+interface UnlockReport {
+    failed_attempts: number,
+    recent: number[],
+}
 
 interface LoginViewProps {
-    hint: string,
+    info: PublicInfo,
     onLoginSuccess: () => void,
   }
 
-  const LoginView = ({ hint, onLoginSuccess }: LoginViewProps ) => {
+  const LoginView = ({ info, onLoginSuccess }: LoginViewProps ) => {
 
     const [passwordInput, setPasswordInput] = useState("");
-    const { setPassword } = usePassword();
+    const [hint, setHint] = useState(info.hint ?? "");
+    const [keyfile, setKeyfile] = useState<string | null>(null);
+    const [code, setCode] = useState("");
+
+    const handleRevealHint = async () => {
+        const answer = prompt(info.hint_question ?? "");
+        if (!answer) return;
+        try {
+            setHint(await invoke<string>("reveal_hint", { answer }));
+        } catch (err) {
+            alert("Failed to reveal hint: " + err);
+        }
+    };
 
     const handleUnlock = async (e?: React.FormEvent) => {
         if (e) e.preventDefault();
+        if (info.keyfile && !keyfile) {
+            alert("Choose the keyfile for this store first");
+            return;
+        }
         try {
-            await invoke<string>("get_vaults", { password: passwordInput });
-            setPassword(passwordInput);
+            const report = await invoke<UnlockReport>("unlock", { password: passwordInput, keyfile, code: code || null });
+            setPasswordInput("");
+            setCode("");
+            if (report.failed_attempts > 0) {
+                const last = new Date(report.recent[report.recent.length - 1] * 1000);
+                alert(`${report.failed_attempts} failed unlock attempt(s) since your last login, the last one on ${last.toLocaleString()}`);
+            }
             onLoginSuccess();
         } catch (err) {
             alert("Failed to unlock vaults: " + err);
+            if (String(err).includes("wiped")) {
+                window.location.reload();
+            }
         }
     };
 
     return (
         <div id="loginview-container">
-            <h1>🔐 Welcome to <strong>Noetiq</strong> 🔐</h1>
+            <h1>🔐 Welcome to <strong>{info.store_name}</strong> 🔐</h1>
             <p>Previous activity found, enter your password to start working</p>
             <form id="loginview-dialog" onSubmit={handleUnlock}>
                 <p className="dialog-input-label">Password</p>
@@ -36,6 +66,26 @@ interface LoginViewProps {
                     onChange={(e) => setPasswordInput(e.target.value)}
                     placeholder={hint}
                 />
+                {info.totp && (
+                    <>
+                        <p className="dialog-input-label">Authentication code</p>
+                        <input
+                            className="dialog-input-text"
+                            type="text"
+                            inputMode="numeric"
+                            autoComplete="one-time-code"
+                            value={code}
+                            onChange={(e) => setCode(e.target.value)}
+                            placeholder="Code from your authenticator app or a backup code"
+                        />
+                    </>
+                )}
+                {info.keyfile && (
+                    <KeyfilePicker label="Keyfile" value={keyfile} onChange={setKeyfile} />
+                )}
+                {info.hint_mode === "encrypted" && !hint && (
+                    <button className="dialog-button-neutral" type="button" onClick={handleRevealHint}>Show hint</button>
+                )}
                 <button className="dialog-button" type="submit">Access Vaults</button>
             </form>
         </div>
@@ -6,10 +6,11 @@ import CustomDialog from "./CustomDialog";
 import NewVaultDialog from "./NewVaultDialog";
 import VaultOptionsDialog from "./VaultOptionsDialog";
 import OptionsDialog from "./OptionsDialog";
+import ImportSharedDialog from "./ImportSharedDialog";
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { join } from '@tauri-apps/api/path';
 import { configDir } from '@tauri-apps/api/path';
-import { usePassword } from "./PasswordContext";
 
 interface Props {
   onVaultSelect: (path: string | null) => void;
@@ -22,6 +23,7 @@ interface VaultProps {
   name: string;
   description: string;
   folder_id: string;
+  team?: { epoch: number, members: string[] };
 }
 
 const MainView: React.FC<Props> = ({ onVaultSelect, onVaultNameSet, onVaultIdSet }) => {
@@ -52,10 +54,10 @@ const MainView: React.FC<Props> = ({ onVaultSelect, onVaultNameSet, onVaultIdSet
   const [isOptionsModalOpen, setIsOptionsModalOpen] = useState(false);
   const [selectedVault, setSelectedVault] = useState<VaultProps | null>(null);
   const [searchTerm, setSearchTerm] = useState('');
-  const { password } = usePassword();
+  const [openedFiles, setOpenedFiles] = useState<string[]>([]);
 
   const refreshVaults = () => {
-    invoke<string>("get_vaults", { password })
+    invoke<string>("get_vaults")
       .then((result) => {
         const parsed = JSON.parse(result) as VaultProps[];
         setVaults(parsed);
@@ -69,6 +71,27 @@ const MainView: React.FC<Props> = ({ onVaultSelect, onVaultNameSet, onVaultIdSet
     refreshVaults();
   }, []);
 
+  useEffect(() => {
+    const unlisten = listen("synced", refreshVaults);
+    return () => {
+      unlisten.then((off) => off());
+    };
+  }, []);
+
+  // Shared notes opened with the app wait in the backend until the vaults are unlocked.
+  useEffect(() => {
+    const takeOpenedFiles = () => {
+      invoke<string[]>("take_opened_files")
+        .then((files) => setOpenedFiles((current) => [...current, ...files]))
+        .catch((err) => console.error("Error loading opened files:", err));
+    };
+    takeOpenedFiles();
+    const unlisten = listen("shared-note-opened", takeOpenedFiles);
+    return () => {
+      unlisten.then((off) => off());
+    };
+  }, []);
+
   const query = searchTerm.trim().toLowerCase();
   const filteredVaults = vaults.filter((vault) =>
     vault.name.toLowerCase().includes(query) ||
@@ -134,6 +157,7 @@ const MainView: React.FC<Props> = ({ onVaultSelect, onVaultNameSet, onVaultIdSet
             description={selectedVault.description}
             icon={selectedVault.icon}
             id={selectedVault.folder_id}
+            team={!!selectedVault.team}
             refreshVaults={refreshVaults}
             handleCloseDialog={handleCloseVaultOptionstDialog}
           />
@@ -144,6 +168,17 @@ const MainView: React.FC<Props> = ({ onVaultSelect, onVaultNameSet, onVaultIdSet
         <OptionsDialog />
       </CustomDialog>
 
+      <CustomDialog isOpen={openedFiles.length > 0} onClose={() => setOpenedFiles((files) => files.slice(1))}>
+        {openedFiles.length > 0 && (
+          <ImportSharedDialog
+            key={openedFiles[0]}
+            path={openedFiles[0]}
+            handleCloseDialog={() => setOpenedFiles((files) => files.slice(1))}
+            onImported={refreshVaults}
+          />
+        )}
+      </CustomDialog>
+
 
 
     </div>
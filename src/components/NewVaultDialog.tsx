@@ -1,7 +1,6 @@
 import React, { useState } from "react";
 import EmojiPicker from "./EmojiPicker";
 import { invoke } from "@tauri-apps/api/core";
-import { usePassword } from "./PasswordContext";
 
 interface Props {
     refreshVaults: () => void;
@@ -24,7 +23,8 @@ const NewVaultDialog: React.FC<Props> = ({ refreshVaults, handleCloseDialog }) =
     const [icon, setIcon] = useState(emojiList[Math.floor(Math.random() * emojiList.length)]);
     const [name, setName] = useState("");
     const [description, setDescription] = useState("");
-    const {password} = usePassword();
+    const [team, setTeam] = useState(false);
+    const [memberName, setMemberName] = useState("");
 
     const handleNewVault = async (e: React.FormEvent) => {
         e.preventDefault();
@@ -32,19 +32,22 @@ const NewVaultDialog: React.FC<Props> = ({ refreshVaults, handleCloseDialog }) =
             alert("Name is required");
             return;
         }
+        if (team && !memberName.trim()) {
+            alert("Your name in the team is required");
+            return;
+        }
         try {
-            await invoke('create_vault', {
-                password,
-                newVault: {
-                    icon,
-                    name,
-                    description,
-                }
-            });
+            const newVault = { icon, name, description };
+            if (team) {
+                await invoke('create_team_vault', { newVault, memberName });
+            } else {
+                await invoke('create_vault', { newVault });
+            }
             refreshVaults();
             handleCloseDialog();
             setName("");
             setDescription("");
+            setTeam(false);
             setIcon(emojiList[Math.floor(Math.random() * emojiList.length)]);
         } catch (error) {
             alert("Error creando vault: " + String(error));
@@ -61,6 +64,31 @@ const NewVaultDialog: React.FC<Props> = ({ refreshVaults, handleCloseDialog }) =
                 <input className="dialog-input-text" type="text" value={name} onChange={(e) => setName(e.target.value)} placeholder="Vault name..." required />
                 <p className="dialog-input-label">Description</p>
                 <input className="dialog-input-text" type="text" value={description} onChange={(e) => setDescription(e.target.value)} placeholder="Vault description..." />
+                <div className="swap-button-container">
+                    <button
+                        type="button"
+                        onClick={() => setTeam(false)}
+                        className={`swap-button swap-button-left ${!team ? 'swap-button-selected' : ''}`}
+                    >
+                        Personal
+                    </button>
+                    <button
+                        type="button"
+                        onClick={() => setTeam(true)}
+                        className={`swap-button swap-button-right ${team ? 'swap-button-selected' : ''}`}
+                    >
+                        Team
+                    </button>
+                </div>
+                {team && (
+                    <>
+                        <p className="dialog-about-text">
+                            A team vault has a key of its own that each member opens with their own password. It reaches them through the history folder.
+                        </p>
+                        <p className="dialog-input-label">Your name in the team</p>
+                        <input className="dialog-input-text" type="text" value={memberName} onChange={(e) => setMemberName(e.target.value)} placeholder="Name the other members see..." />
+                    </>
+                )}
                 <div>
                 <button className="dialog-button" type="submit" onClick={handleNewVault} >Create vault</button>
                 </div>
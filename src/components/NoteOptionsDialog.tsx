@@ -1,25 +1,47 @@
-import React from "react";
-import { usePassword } from "./PasswordContext";
+import React, { useEffect, useState } from "react";
 import { invoke } from '@tauri-apps/api/core';
+import { save } from "@tauri-apps/plugin-dialog";
+import ContactPicker from "./ContactPicker";
 
 interface NoteOptionsDialogProps {
   id: string;
+  title: string;
   vaultFolder: string;
   refreshNotes: () => void;
   handleCloseDialog: () => void;
   setSelectedNote: React.Dispatch<React.SetStateAction<string>>
 }
 
-function deleteNote(password: string, id: string, vaultFolder: string) {
-  return invoke("delete_note", { password, noteId: id, vaultFolder: vaultFolder });
+interface NoteVersion {
+  commit: string;
+  time: number;
 }
 
-const NoteOptionsDialog: React.FC<NoteOptionsDialogProps> = ({ id, vaultFolder, refreshNotes, handleCloseDialog, setSelectedNote }) => {
-  const { password } = usePassword();
+function deleteNote(id: string, vaultFolder: string) {
+  return invoke("delete_note", { noteId: id, vaultFolder: vaultFolder });
+}
+
+const NoteOptionsDialog: React.FC<NoteOptionsDialogProps> = ({ id, title, vaultFolder, refreshNotes, handleCloseDialog, setSelectedNote }) => {
+  const [versions, setVersions] = useState<NoteVersion[]>([]);
+  const [selectedVersion, setSelectedVersion] = useState("");
+  const [shareWithContacts, setShareWithContacts] = useState(false);
+  const [sharePassword, setSharePassword] = useState("");
+  const [recipients, setRecipients] = useState<string[]>([]);
+
+  // Vaults without history simply have no versions to offer.
+  useEffect(() => {
+    if (!id) return;
+    invoke<NoteVersion[]>("get_note_history", { vaultfolder: vaultFolder, filename: id })
+      .then((result) => {
+        setVersions(result);
+        setSelectedVersion((result[1] ?? result[0])?.commit ?? "");
+      })
+      .catch(() => setVersions([]));
+  }, [id, vaultFolder]);
 
   const handleDelete = async () => {
     try {
-      await deleteNote(password, id, vaultFolder);
+      await deleteNote(id, vaultFolder);
       refreshNotes();
       handleCloseDialog();
       setSelectedNote("");
@@ -30,8 +52,97 @@ const NoteOptionsDialog: React.FC<NoteOptionsDialogProps> = ({ id, vaultFolder,
 
 
 
+  const handleRestore = async () => {
+    try {
+      await invoke("restore_note_version", { vaultfolder: vaultFolder, filename: id, commit: selectedVersion });
+      refreshNotes();
+      handleCloseDialog();
+      setSelectedNote("");
+    } catch (error) {
+      alert("Failed to restore the note: " + error);
+    }
+  }
+
+  const handleShare = async () => {
+    const path = await save({
+      title: "Share note",
+      defaultPath: `${title.trim() || "note"}.noetiq`,
+      filters: [{ name: "Noetiq note", extensions: ["noetiq"] }],
+    });
+    if (!path) return;
+    try {
+      if (shareWithContacts) {
+        await invoke("share_note_with_contacts", { vaultfolder: vaultFolder, filename: id, recipients, path });
+      } else {
+        await invoke("share_note", { vaultfolder: vaultFolder, filename: id, sharePassword, path });
+      }
+      setSharePassword("");
+      handleCloseDialog();
+    } catch (error) {
+      alert("Failed to share the note: " + error);
+    }
+  }
+
   return (
     <div id="openvault-container">
+      <p className="dialog-input-label">Share as file</p>
+      <div className="swap-button-container">
+        <button
+          onClick={() => setShareWithContacts(false)}
+          className={`swap-button swap-button-left ${!shareWithContacts ? 'swap-button-selected' : ''}`}
+        >
+          Password
+        </button>
+        <button
+          onClick={() => setShareWithContacts(true)}
+          className={`swap-button swap-button-right ${shareWithContacts ? 'swap-button-selected' : ''}`}
+        >
+          Contacts
+        </button>
+      </div>
+      {shareWithContacts ? (
+        <>
+          <p className="dialog-about-text">Only the contacts you choose can import the note, attachments included.</p>
+          <ContactPicker selected={recipients} onChange={setRecipients} />
+        </>
+      ) : (
+        <>
+          <p className="dialog-about-text">Anyone with the file and its password can import the note, attachments included.</p>
+          <input
+            className="dialog-input-text"
+            type="password"
+            placeholder="Share password..."
+            value={sharePassword}
+            onChange={(e) => setSharePassword(e.target.value)}
+          />
+        </>
+      )}
+      <button
+        className="dialog-button-neutral"
+        onClick={handleShare}
+        disabled={shareWithContacts ? recipients.length === 0 : sharePassword.length < 8}
+      >
+        Share note
+      </button>
+      {versions.length > 0 && (
+        <>
+          <p className="dialog-input-label">History</p>
+          <select
+            className="dialog-input-text"
+            value={selectedVersion}
+            onChange={(e) => setSelectedVersion(e.target.value)}
+          >
+            {versions.map((version) => (
+              <option key={version.commit} value={version.commit}>
+                {new Date(version.time * 1000).toLocaleString()}
+              </option>
+            ))}
+          </select>
+          <button className="dialog-button-neutral" onClick={handleRestore} disabled={!selectedVersion}>
+            Restore this version
+          </button>
+        </>
+      )}
       <p className="dialog-input-label">Delete note</p>
       <button className="dialog-button-red" onClick={handleDelete}>Delete note</button>
     </div>
@@ -2,12 +2,111 @@ import React, { useEffect, useState } from 'react';
 import { useSettings } from './SettingsContext';
 import CustomDialog from './CustomDialog';
 import ChangePasswordDialog from './ChangePasswordDialog';
+import TotpSettings from './TotpSettings';
+import DuressSettings from './DuressSettings';
+import SyncSettings from './SyncSettings';
+import HistorySettings from './HistorySettings';
+import ContactsSettings from './ContactsSettings';
+import { invoke } from '@tauri-apps/api/core';
+
+interface LockSettings {
+  idle_timeout_secs: number;
+  lock_on_blur: boolean;
+  lock_on_minimize: boolean;
+  lock_on_suspend: boolean;
+}
+
+const idleTimeouts = [
+  { label: "Never", secs: 0 },
+  { label: "5 min", secs: 5 * 60 },
+  { label: "15 min", secs: 15 * 60 },
+  { label: "1 hour", secs: 60 * 60 },
+];
+
+interface PasswordPolicy {
+  min_length: number;
+  min_score: number;
+}
+
+const policyLevels = [
+  { label: "Weak", min_score: 1, min_length: 6 },
+  { label: "Fair", min_score: 2, min_length: 8 },
+  { label: "Strong", min_score: 3, min_length: 12 },
+];
+
+const wipeLimits = [
+  { label: "Never", limit: null },
+  { label: "10 tries", limit: 10 },
+  { label: "25 tries", limit: 25 },
+];
+
+interface StorageSettings {
+  hardened: boolean;
+  pack_vaults: boolean;
+  secure_delete: boolean;
+  compress: boolean;
+}
 /*import { join, configDir } from '@tauri-apps/api/path';
 import { openPath } from '@tauri-apps/plugin-opener';*/
 
 const OptionsDialog: React.FC = () => {
   const { fontSize, setFontSize, theme, setTheme } = useSettings();
   const [isChangePasswordModalOpen, setIsChangePasswordModalOpen] = useState(false);
+  const [storage, setStorage] = useState<StorageSettings | null>(null);
+
+  useEffect(() => {
+    invoke<StorageSettings>("get_storage_settings")
+      .then(setStorage)
+      .catch((err) => console.error("Error loading storage settings:", err));
+  }, []);
+
+  const [lockSettings, setLockSettings] = useState<LockSettings | null>(null);
+
+  useEffect(() => {
+    invoke<LockSettings>("get_lock_settings")
+      .then(setLockSettings)
+      .catch((err) => console.error("Error loading lock settings:", err));
+  }, []);
+
+  const updateLockSettings = (settings: LockSettings) => {
+    invoke("set_lock_settings", { settings })
+      .then(() => setLockSettings(settings))
+      .catch((err) => alert("Failed to update lock settings: " + err));
+  };
+
+  const [wipeAfter, setWipeAfter] = useState<number | null | undefined>(undefined);
+
+  useEffect(() => {
+    invoke<number | null>("get_wipe_policy")
+      .then(setWipeAfter)
+      .catch((err) => console.error("Error loading wipe policy:", err));
+  }, []);
+
+  const updateWipePolicy = (limit: number | null) => {
+    invoke("set_wipe_policy", { wipeAfter: limit })
+      .then(() => setWipeAfter(limit))
+      .catch((err) => alert("Failed to update wipe policy: " + err));
+  };
+
+  const [policy, setPolicy] = useState<PasswordPolicy | null>(null);
+
+  useEffect(() => {
+    invoke<PasswordPolicy>("get_password_policy")
+      .then(setPolicy)
+      .catch((err) => console.error("Error loading password policy:", err));
+  }, []);
+
+  const updatePolicy = (newPolicy: PasswordPolicy) => {
+    invoke("set_password_policy", { policy: newPolicy })
+      .then(() => setPolicy(newPolicy))
+      .catch((err) => alert("Failed to update password policy: " + err));
+  };
+
+  const updateStorage = (settings: StorageSettings) => {
+    invoke("set_storage_settings", { settings })
+      .then(() => setStorage(settings))
+      .catch((err) => alert("Failed to update storage settings: " + err));
+  };
 
   useEffect(() => {
     if (theme === 'dark') {
@@ -109,6 +208,145 @@ const OptionsDialog: React.FC = () => {
         Change password
       </button>
 
+      <TotpSettings />
+
+      <DuressSettings />
+
+      {policy && (
+        <>
+          <p className="dialog-input-label">Minimum password strength</p>
+          <div className="swap-button-container">
+            {policyLevels.map((level, index) => (
+              <button
+                key={level.label}
+                onClick={() => updatePolicy({ min_score: level.min_score, min_length: level.min_length })}
+                className={`swap-button ${index === 0 ? 'swap-button-left' : index === policyLevels.length - 1 ? 'swap-button-right' : 'swap-button-middle'} ${policy.min_score === level.min_score ? 'swap-button-selected' : ''}`}
+              >
+                {level.label}
+              </button>
+            ))}
+          </div>
+        </>
+      )}
+
+      {lockSettings && (
+        <>
+          <p className="dialog-input-label">Auto-lock after inactivity</p>
+          <div className="swap-button-container">
+            {idleTimeouts.map((timeout, index) => (
+              <button
+                key={timeout.secs}
+                onClick={() => updateLockSettings({ ...lockSettings, idle_timeout_secs: timeout.secs })}
+                className={`swap-button ${index === 0 ? 'swap-button-left' : index === idleTimeouts.length - 1 ? 'swap-button-right' : 'swap-button-middle'} ${lockSettings.idle_timeout_secs === timeout.secs ? 'swap-button-selected' : ''}`}
+              >
+                {timeout.label}
+              </button>
+            ))}
+          </div>
+
+          <p className="dialog-input-label">Lock when the window loses focus</p>
+          <div className="swap-button-container">
+            <button
+              onClick={() => updateLockSettings({ ...lockSettings, lock_on_blur: false })}
+              className={`swap-button swap-button-left ${!lockSettings.lock_on_blur ? 'swap-button-selected' : ''}`}
+            >
+              Off
+            </button>
+            <button
+              onClick={() => updateLockSettings({ ...lockSettings, lock_on_blur: true })}
+              className={`swap-button swap-button-right ${lockSettings.lock_on_blur ? 'swap-button-selected' : ''}`}
+            >
+              On
+            </button>
+          </div>
+
+          <p className="dialog-input-label">Compress notes</p>
+          <div className="swap-button-container">
+            <button
+              onClick={() => updateStorage({ ...storage, compress: false })}
+              className={`swap-button swap-button-left ${!storage.compress ? 'swap-button-selected' : ''}`}
+            >
+              Off
+            </button>
+            <button
+              onClick={() => updateStorage({ ...storage, compress: true })}
+              className={`swap-button swap-button-right ${storage.compress ? 'swap-button-selected' : ''}`}
+            >
+              On
+            </button>
+          </div>
+        </>
+      )}
+
+      {wipeAfter !== undefined && (
+        <>
+          <p className="dialog-input-label">Wipe vaults after failed unlocks</p>
+          <div className="swap-button-container">
+            {wipeLimits.map((option, index) => (
+              <button
+                key={option.label}
+                onClick={() => updateWipePolicy(option.limit)}
+                className={`swap-button ${index === 0 ? 'swap-button-left' : index === wipeLimits.length - 1 ? 'swap-button-right' : 'swap-button-middle'} ${wipeAfter === option.limit ? 'swap-button-selected' : ''}`}
+              >
+                {option.label}
+              </button>
+            ))}
+          </div>
+        </>
+      )}
+
+      <button className="dialog-button-neutral" onClick={() => invoke("lock_vaults")}>
+        Lock now
+      </button>
+
+      {storage && (
+        <>
+          <p className="dialog-input-label">Hardened storage</p>
+          <div className="swap-button-container">
+            <button
+              onClick={() => updateStorage({ ...storage, hardened: false, pack_vaults: false })}
+              className={`swap-button swap-button-left ${!storage.hardened ? 'swap-button-selected' : ''}`}
+            >
+              Off
+            </button>
+            <button
+              onClick={() => updateStorage({ ...storage, hardened: true, pack_vaults: false })}
+              className={`swap-button swap-button-middle ${storage.hardened && !storage.pack_vaults ? 'swap-button-selected' : ''}`}
+            >
+              Padded
+            </button>
+            <button
+              onClick={() => updateStorage({ ...storage, hardened: true, pack_vaults: true })}
+              className={`swap-button swap-button-right ${storage.hardened && storage.pack_vaults ? 'swap-button-selected' : ''}`}
+            >
+              Packed
+            </button>
+          </div>
+
+          <p className="dialog-input-label">Overwrite deleted files</p>
+          <div className="swap-button-container">
+            <button
+              onClick={() => updateStorage({ ...storage, secure_delete: false })}
+              className={`swap-button swap-button-left ${!storage.secure_delete ? 'swap-button-selected' : ''}`}
+            >
+              Off
+            </button>
+            <button
+              onClick={() => updateStorage({ ...storage, secure_delete: true })}
+              className={`swap-button swap-button-right ${storage.secure_delete ? 'swap-button-selected' : ''}`}
+            >
+              On
+            </button>
+          </div>
+        </>
+      )}
+
+      <SyncSettings />
+
+      <HistorySettings />
+
+      <ContactsSettings />
+
       <p className="dialog-input-label">About</p>
       <p className="dialog-about-text"><strong>Version: </strong>Beta 0.2.0</p>
       <p className="dialog-about-text"><strong>Developer: </strong>iBManu</p>
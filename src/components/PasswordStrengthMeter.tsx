@@ -0,0 +1,50 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+export interface PasswordStrength {
+    score: number,
+    entropy_bits: number,
+    feedback: string[],
+    hint_warning: string | null,
+    meets_policy: boolean,
+}
+
+interface Props {
+    password: string,
+    hint: string,
+    onEvaluate?: (strength: PasswordStrength) => void,
+}
+
+const scoreLabels = ["Very weak", "Weak", "Fair", "Strong", "Very strong"];
+
+const PasswordStrengthMeter = ({ password, hint, onEvaluate }: Props) => {
+    const [strength, setStrength] = useState<PasswordStrength | null>(null);
+
+    useEffect(() => {
+        const timeout = setTimeout(() => {
+            invoke<PasswordStrength>("evaluate_password", { password, hint })
+                .then((result) => {
+                    setStrength(result);
+                    onEvaluate?.(result);
+                })
+                .catch((err) => console.error("Error evaluating password:", err));
+        }, 300);
+        return () => clearTimeout(timeout);
+    }, [password, hint]);
+
+    if (!password || !strength) return null;
+
+    return (
+        <div className="password-strength">
+            <p className="dialog-input-label">Strength: {scoreLabels[strength.score]}</p>
+            {strength.feedback.map((line) => (
+                <p key={line} className="dialog-about-text">{line}</p>
+            ))}
+            {strength.hint_warning && (
+                <p className="dialog-about-text"><strong>Warning: </strong>{strength.hint_warning}</p>
+            )}
+        </div>
+    );
+};
+
+export default PasswordStrengthMeter;
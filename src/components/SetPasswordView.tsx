@@ -1,33 +1,46 @@
 import { useState } from "react";
 import { invoke } from '@tauri-apps/api/core';
-import { usePassword } from "./PasswordContext";
+import PasswordStrengthMeter, { PasswordStrength } from "./PasswordStrengthMeter";
+import KeyfilePicker from "./KeyfilePicker";
+import HintFields, { emptyHint, HintState, toHintInput } from "./HintFields";
 
-const SetPasswordView = () => {
+interface SetPasswordViewProps {
+    onPasswordSet: () => void,
+}
+
+const SetPasswordView = ({ onPasswordSet }: SetPasswordViewProps) => {
 
     const [password, setPassword] = useState("");
     const [confirmPassword, setConfirmPassword] = useState("");
-    const [hint, setHint] = useState("");
+    const [storeName, setStoreName] = useState("");
+    const [keyfile, setKeyfile] = useState<string | null>(null);
+    const [hint, setHint] = useState<HintState>(emptyHint);
+    const [strength, setStrength] = useState<PasswordStrength | null>(null);
 
-    const { setPassword: setGlobalPassword } = usePassword();
-
-    const handlePasswordSet = async () => {
+    const handlePasswordSet = async (e: React.FormEvent) => {
+        e.preventDefault();
         if (password !== confirmPassword) {
             alert("Passwords do not match");
             return;
           }
 
+          if (strength?.hint_warning && !confirm(strength.hint_warning + ". Use this hint anyway?")) {
+            return;
+          }
+
           try {
             await invoke("set_password", {
               password,
-              hint
+              hint: toHintInput(hint),
+              storeName: storeName || null,
+              keyfile
             });
       
-            setGlobalPassword(password);
-
             alert("Password set successfully!");
+            onPasswordSet();
           } catch (err) {
             console.error(err);
-            alert("Failed to set password.");
+            alert("Failed to set password: " + err);
           }
     };
 
@@ -39,10 +52,13 @@ const SetPasswordView = () => {
                 <form action="" onSubmit={handlePasswordSet}>
                 <p className="dialog-input-label">Password</p>
                 <input className="dialog-input-text" type="password" value={password} onChange={(e) => setPassword(e.target.value)} placeholder="Enter password..." required/>
+                <PasswordStrengthMeter password={password} hint={hint.mode === "none" ? "" : hint.hint} onEvaluate={setStrength} />
                 <p className="dialog-input-label">Confirm password</p>
                 <input className="dialog-input-text" type="password" value={confirmPassword} onChange={(e) => setConfirmPassword(e.target.value)} placeholder="Confirm password..." required/>
-                <p className="dialog-input-label">Password hint</p>
-                <input className="dialog-input-text" type="text" value={hint} onChange={(e) => setHint(e.target.value)} placeholder="Password hint..." />
+                <KeyfilePicker label="Keyfile (optional second factor)" value={keyfile} onChange={setKeyfile} optional />
+                <HintFields value={hint} onChange={setHint} />
+                <p className="dialog-input-label">Store name</p>
+                <input className="dialog-input-text" type="text" value={storeName} onChange={(e) => setStoreName(e.target.value)} placeholder="Noetiq" />
                 <button className="dialog-button" type="submit">Set password</button>
                 </form>
             </div>
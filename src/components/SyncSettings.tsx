@@ -0,0 +1,319 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { open } from "@tauri-apps/plugin-dialog";
+import LanHostSettings from "./LanHostSettings";
+
+interface SyncReport {
+    pushed: number,
+    pulled: number,
+    merged: number,
+    deleted: number,
+    conflicts: number,
+}
+
+interface SyncStatus {
+    target: string | null,
+    last_sync: number | null,
+    running: boolean,
+    last_report: SyncReport | null,
+    last_error: string | null,
+}
+
+type RemoteKind = "folder" | "webdav" | "s3" | "lan";
+
+const DEFAULT_LAN_PORT = "47474";
+
+const SyncSettings = () => {
+    const [status, setStatus] = useState<SyncStatus | null>(null);
+    const [editing, setEditing] = useState(false);
+    const [kind, setKind] = useState<RemoteKind>("folder");
+    const [folder, setFolder] = useState<string | null>(null);
+    const [url, setUrl] = useState("");
+    const [username, setUsername] = useState("");
+    const [serverPassword, setServerPassword] = useState("");
+    const [endpoint, setEndpoint] = useState("");
+    const [region, setRegion] = useState("");
+    const [bucket, setBucket] = useState("");
+    const [prefix, setPrefix] = useState("noetiq-sync");
+    const [accessKey, setAccessKey] = useState("");
+    const [secretKey, setSecretKey] = useState("");
+    const [lanHost, setLanHost] = useState(true);
+    const [port, setPort] = useState(DEFAULT_LAN_PORT);
+    const [address, setAddress] = useState("");
+    const [code, setCode] = useState("");
+    const [password, setPassword] = useState("");
+    const [syncing, setSyncing] = useState(false);
+
+    const loadStatus = () => {
+        invoke<SyncStatus>("get_sync_status")
+            .then(setStatus)
+            .catch((err) => console.error("Error loading sync settings:", err));
+    };
+
+    useEffect(loadStatus, []);
+
+    const handleChooseFolder = async () => {
+        const selected = await open({ multiple: false, directory: true, title: "Sync folder" });
+        if (typeof selected === "string") {
+            setFolder(selected);
+        }
+    };
+
+    const handleSave = () => {
+        const remote = kind === "folder"
+            ? { kind, path: folder }
+            : kind === "webdav"
+                ? { kind, url, username, password: serverPassword }
+                : kind === "s3"
+                    ? { kind, endpoint, region, bucket, prefix, access_key: accessKey, secret_key: secretKey }
+                    : lanHost
+                        ? { kind: "lanhost", port: Number(port) }
+                        : { kind, address, code };
+        invoke("set_sync_remote", { remote, password })
+            .then(() => {
+                setEditing(false);
+                setFolder(null);
+                setUrl("");
+                setUsername("");
+                setServerPassword("");
+                setAccessKey("");
+                setSecretKey("");
+                setCode("");
+                setPassword("");
+                loadStatus();
+            })
+            .catch((err) => alert("Failed to set up sync: " + err));
+    };
+
+    const handleSync = () => {
+        setSyncing(true);
+        invoke<SyncReport>("sync_now")
+            .then((report) => {
+                loadStatus();
+                if (report.conflicts > 0) {
+                    alert(`${report.conflicts} note(s) were changed on two devices at once. Look for "(conflict)" notes and "Sync conflict" marks inside notes`);
+                }
+            })
+            .catch((err) => {
+                loadStatus();
+                alert("Sync failed: " + err);
+            })
+            .finally(() => setSyncing(false));
+    };
+
+    const handleDisable = () => {
+        invoke("disable_sync")
+            .then(loadStatus)
+            .catch((err) => alert("Failed to turn off sync: " + err));
+    };
+
+    if (!status) return null;
+
+    const report = status.last_report;
+    const validPort = Number.isInteger(Number(port)) && Number(port) > 0 && Number(port) < 65536;
+    const canSave = password && (
+        kind === "folder" ? folder
+            : kind === "webdav" ? url
+                : kind === "s3" ? endpoint && bucket && accessKey && secretKey
+                    : lanHost ? validPort : address && code
+    );
+
+    return (
+        <>
+            <p className="dialog-input-label">Sync</p>
+            <p className="dialog-about-text">
+                {status.target
+                    ? `${status.target}, ${status.last_sync ? "last synced " + new Date(status.last_sync * 1000).toLocaleString() : "never synced"}`
+                    : "Keeps vaults in sync with other devices through a shared folder, a WebDAV server, S3 storage or directly over the local network"}
+            </p>
+            {status.last_error && <p className="dialog-about-text">Last sync failed: {status.last_error}</p>}
+            {!status.last_error && report && (
+                <p className="dialog-about-text">
+                    {`Sent ${report.pushed}, received ${report.pulled}, merged ${report.merged}, deleted ${report.deleted}, conflicts ${report.conflicts}`}
+                </p>
+            )}
+            {!editing && <LanHostSettings key={status.target ?? ""} />}
+
+            {editing ? (
+                <>
+                    <div className="swap-button-container">
+                        <button
+                            onClick={() => setKind("folder")}
+                            className={`swap-button swap-button-left ${kind === "folder" ? 'swap-button-selected' : ''}`}
+                        >
+                            Shared folder
+                        </button>
+                        <button
+                            onClick={() => setKind("webdav")}
+                            className={`swap-button swap-button-middle ${kind === "webdav" ? 'swap-button-selected' : ''}`}
+                        >
+                            WebDAV
+                        </button>
+                        <button
+                            onClick={() => setKind("s3")}
+                            className={`swap-button swap-button-middle ${kind === "s3" ? 'swap-button-selected' : ''}`}
+                        >
+                            S3
+                        </button>
+                        <button
+                            onClick={() => setKind("lan")}
+                            className={`swap-button swap-button-right ${kind === "lan" ? 'swap-button-selected' : ''}`}
+                        >
+                            LAN
+                        </button>
+                    </div>
+                    {kind === "folder" ? (
+                        <>
+                            {folder && <p className="dialog-about-text">{folder}</p>}
+                            <button className="dialog-button-neutral" type="button" onClick={handleChooseFolder}>
+                                {folder ? "Change folder" : "Choose folder"}
+                            </button>
+                        </>
+                    ) : kind === "webdav" ? (
+                        <>
+                            <input
+                                className="dialog-input-text"
+                                type="url"
+                                value={url}
+                                onChange={(e) => setUrl(e.target.value)}
+                                placeholder="https://server/remote.php/dav/files/me/..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="text"
+                                value={username}
+                                onChange={(e) => setUsername(e.target.value)}
+                                placeholder="User name..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="password"
+                                value={serverPassword}
+                                onChange={(e) => setServerPassword(e.target.value)}
+                                placeholder="Server password..."
+                            />
+                        </>
+                    ) : kind === "lan" ? (
+                        <>
+                            <div className="swap-button-container">
+                                <button
+                                    onClick={() => setLanHost(true)}
+                                    className={`swap-button swap-button-left ${lanHost ? 'swap-button-selected' : ''}`}
+                                >
+                                    Host here
+                                </button>
+                                <button
+                                    onClick={() => setLanHost(false)}
+                                    className={`swap-button swap-button-right ${!lanHost ? 'swap-button-selected' : ''}`}
+                                >
+                                    Join a host
+                                </button>
+                            </div>
+                            {lanHost ? (
+                                <>
+                                    <p className="dialog-about-text">
+                                        This device keeps the synced data, other devices on the network pair with it using a short code
+                                    </p>
+                                    <input
+                                        className="dialog-input-text"
+                                        type="number"
+                                        value={port}
+                                        onChange={(e) => setPort(e.target.value)}
+                                        placeholder="Port..."
+                                    />
+                                </>
+                            ) : (
+                                <>
+                                    <input
+                                        className="dialog-input-text"
+                                        type="text"
+                                        value={address}
+                                        onChange={(e) => setAddress(e.target.value)}
+                                        placeholder="Address shown on the host, 192.168.1.20:47474..."
+                                    />
+                                    <input
+                                        className="dialog-input-text"
+                                        type="text"
+                                        inputMode="numeric"
+                                        value={code}
+                                        onChange={(e) => setCode(e.target.value)}
+                                        placeholder="Pairing code shown on the host..."
+                                    />
+                                </>
+                            )}
+                        </>
+                    ) : (
+                        <>
+                            <input
+                                className="dialog-input-text"
+                                type="url"
+                                value={endpoint}
+                                onChange={(e) => setEndpoint(e.target.value)}
+                                placeholder="https://s3.amazonaws.com or http://127.0.0.1:9000..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="text"
+                                value={region}
+                                onChange={(e) => setRegion(e.target.value)}
+                                placeholder="Region, us-east-1 if empty..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="text"
+                                value={bucket}
+                                onChange={(e) => setBucket(e.target.value)}
+                                placeholder="Bucket..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="text"
+                                value={prefix}
+                                onChange={(e) => setPrefix(e.target.value)}
+                                placeholder="Folder in the bucket..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="text"
+                                value={accessKey}
+                                onChange={(e) => setAccessKey(e.target.value)}
+                                placeholder="Access key..."
+                            />
+                            <input
+                                className="dialog-input-text"
+                                type="password"
+                                value={secretKey}
+                                onChange={(e) => setSecretKey(e.target.value)}
+                                placeholder="Secret key..."
+                            />
+                        </>
+                    )}
+                    <input
+                        className="dialog-input-text"
+                        type="password"
+                        value={password}
+                        onChange={(e) => setPassword(e.target.value)}
+                        placeholder="Sync password, the same on every device..."
+                    />
+                    <button className="dialog-button-neutral" onClick={handleSave} disabled={!canSave}>Save</button>
+                </>
+            ) : (
+                <div className="swap-button-container">
+                    {status.target && (
+                        <button className="dialog-button-neutral" onClick={handleSync} disabled={syncing || status.running}>
+                            {syncing || status.running ? "Syncing..." : "Sync now"}
+                        </button>
+                    )}
+                    <button className="dialog-button-neutral" onClick={() => setEditing(true)}>
+                        {status.target ? "Change" : "Set up"}
+                    </button>
+                    {status.target && (
+                        <button className="dialog-button-neutral" onClick={handleDisable}>Turn off</button>
+                    )}
+                </div>
+            )}
+        </>
+    );
+};
+
+export default SyncSettings;
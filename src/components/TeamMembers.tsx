@@ -0,0 +1,107 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { ContactInfo } from "./ContactsSettings";
+
+interface Props {
+    vaultfolder: string,
+}
+
+interface MemberInfo {
+    name: string,
+    public_key: string,
+    fingerprint: string,
+    you: boolean,
+}
+
+const TeamMembers = ({ vaultfolder }: Props) => {
+    const [members, setMembers] = useState<MemberInfo[]>([]);
+    const [contacts, setContacts] = useState<ContactInfo[]>([]);
+    const [newMember, setNewMember] = useState("");
+    const [error, setError] = useState<string | null>(null);
+    const [busy, setBusy] = useState(false);
+
+    const loadMembers = () => {
+        invoke<MemberInfo[]>("get_team_members", { vaultfolder })
+            .then((result) => {
+                setMembers(result);
+                setError(null);
+            })
+            .catch((err) => setError(String(err)));
+    };
+
+    useEffect(() => {
+        loadMembers();
+        invoke<ContactInfo[]>("get_contacts")
+            .then(setContacts)
+            .catch((err) => console.error("Error loading contacts:", err));
+    }, [vaultfolder]);
+
+    const handleAdd = () => {
+        setBusy(true);
+        invoke("add_team_member", { vaultfolder, publicKey: newMember })
+            .then(() => {
+                setNewMember("");
+                loadMembers();
+            })
+            .catch((err) => alert("Failed to add the member: " + err))
+            .finally(() => setBusy(false));
+    };
+
+    // Removing a member seals the whole vault again under a new key.
+    const handleRemove = (member: MemberInfo) => {
+        setBusy(true);
+        invoke("remove_team_member", { vaultfolder, publicKey: member.public_key })
+            .then(loadMembers)
+            .catch((err) => alert("Failed to remove the member: " + err))
+            .finally(() => setBusy(false));
+    };
+
+    const candidates = contacts.filter((c) => !members.some((m) => m.public_key === c.public_key));
+
+    return (
+        <>
+            <p className="dialog-input-label">Team members</p>
+            {error && <p className="dialog-about-text">{error}</p>}
+            {members.map((member) => (
+                <div key={member.public_key} className="swap-button-container">
+                    <p className="dialog-about-text">
+                        <strong>{member.you ? `${member.name} (you)` : member.name}</strong>{` ${member.fingerprint}`}
+                    </p>
+                    {!member.you && (
+                        <button className="dialog-button-neutral" onClick={() => handleRemove(member)} disabled={busy}>
+                            Remove
+                        </button>
+                    )}
+                </div>
+            ))}
+            {!error && (candidates.length === 0 ? (
+                <p className="dialog-about-text">Add people as contacts in the settings to invite them.</p>
+            ) : (
+                <div className="swap-button-container">
+                    <select
+                        className="dialog-input-text"
+                        value={newMember}
+                        onChange={(e) => setNewMember(e.target.value)}
+                    >
+                        <option value="">Choose a contact...</option>
+                        {candidates.map((contact) => (
+                            <option key={contact.public_key} value={contact.public_key}>
+                                {`${contact.name} (${contact.fingerprint})`}
+                            </option>
+                        ))}
+                    </select>
+                    <button className="dialog-button-neutral" onClick={handleAdd} disabled={busy || !newMember}>
+                        Add member
+                    </button>
+                </div>
+            ))}
+            {!error && (
+                <p className="dialog-about-text">
+                    Members get the vault from the history folder after your next push, once they have you as a contact.
+                </p>
+            )}
+        </>
+    );
+};
+
+export default TeamMembers;
@@ -8,6 +8,9 @@ import Underline from "@editorjs/underline"
 import Hotkey from "editorjs-inline-hotkey"
 import Striketrough from "@sotaproject/strikethrough"
 import Delimiter from "@editorjs/delimiter"
+import ImageTool from "@editorjs/image"
+import AttachesTool from "@editorjs/attaches"
+import { attachmentUploader } from "./attachments"
 
 export const EDITOR_JS_TOOLS = {
 
@@ -24,4 +27,17 @@ export const EDITOR_JS_TOOLS = {
     code: Code,
     inlineCode: InlineCode,
     hotkey: Hotkey,
-}
\ No newline at end of file
+}
+
+// Image and file blocks store their data in the vault, so they are bound to it.
+export const editorTools = (vaultId: string) => ({
+    ...EDITOR_JS_TOOLS,
+    image: {
+        class: ImageTool,
+        config: { uploader: attachmentUploader(vaultId) },
+    },
+    attaches: {
+        class: AttachesTool,
+        config: { uploader: attachmentUploader(vaultId) },
+    },
+})
@@ -0,0 +1,115 @@
+import { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+
+interface TotpStatus {
+    enabled: boolean,
+    backup_codes_left: number,
+}
+
+interface TotpEnrollment {
+    secret: string,
+    uri: string,
+    qr: string,
+}
+
+const TotpSettings = () => {
+    const [status, setStatus] = useState<TotpStatus | null>(null);
+    const [enrollment, setEnrollment] = useState<TotpEnrollment | null>(null);
+    const [backupCodes, setBackupCodes] = useState<string[]>([]);
+    const [code, setCode] = useState("");
+
+    const loadStatus = () => {
+        invoke<TotpStatus>("get_totp_status")
+            .then(setStatus)
+            .catch((err) => console.error("Error loading two-factor status:", err));
+    };
+
+    useEffect(loadStatus, []);
+
+    const handleBegin = () => {
+        invoke<TotpEnrollment>("begin_totp_enrollment")
+            .then(setEnrollment)
+            .catch((err) => alert("Failed to start enrollment: " + err));
+    };
+
+    const handleConfirm = () => {
+        if (!enrollment) return;
+        invoke<string[]>("confirm_totp_enrollment", { secret: enrollment.secret, code })
+            .then((codes) => {
+                setBackupCodes(codes);
+                setEnrollment(null);
+                setCode("");
+                loadStatus();
+            })
+            .catch((err) => alert("Failed to enable two-factor authentication: " + err));
+    };
+
+    const handleDisable = () => {
+        invoke("disable_totp", { code })
+            .then(() => {
+                setCode("");
+                setBackupCodes([]);
+                loadStatus();
+            })
+            .catch((err) => alert("Failed to disable two-factor authentication: " + err));
+    };
+
+    const handleRegenerate = () => {
+        invoke<string[]>("regenerate_backup_codes", { code })
+            .then((codes) => {
+                setBackupCodes(codes);
+                setCode("");
+                loadStatus();
+            })
+            .catch((err) => alert("Failed to regenerate backup codes: " + err));
+    };
+
+    if (!status) return null;
+
+    const codeInput = (
+        <input
+            className="dialog-input-text"
+            type="text"
+            inputMode="numeric"
+            value={code}
+            onChange={(e) => setCode(e.target.value)}
+            placeholder="Authentication code..."
+        />
+    );
+
+    return (
+        <>
+            <p className="dialog-input-label">Two-factor authentication</p>
+
+            {backupCodes.length > 0 && (
+                <>
+                    <p className="dialog-about-text">Store these backup codes somewhere safe. Each one unlocks once if you lose your authenticator.</p>
+                    <pre className="totp-codes">{backupCodes.join("\n")}</pre>
+                </>
+            )}
+
+            {status.enabled ? (
+                <>
+                    <p className="dialog-about-text">Enabled, {status.backup_codes_left} backup code(s) left</p>
+                    {codeInput}
+                    <div className="swap-button-container">
+                        <button className="dialog-button-neutral" onClick={handleRegenerate}>New backup codes</button>
+                        <button className="dialog-button-neutral" onClick={handleDisable}>Disable</button>
+                    </div>
+                </>
+            ) : enrollment ? (
+                <>
+                    <p className="dialog-about-text">Scan this code with your authenticator app, or enter the secret manually.</p>
+                    <pre className="totp-qr">{enrollment.qr}</pre>
+                    <p className="dialog-about-text">{enrollment.secret}</p>
+                    {codeInput}
+                    <button className="dialog-button-neutral" onClick={handleConfirm}>Confirm</button>
+                </>
+            ) : (
+                <button className="dialog-button-neutral" onClick={handleBegin}>Enable</button>
+            )}
+        </>
+    );
+};
+
+export default TotpSettings;
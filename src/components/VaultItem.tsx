@@ -2,6 +2,15 @@ import React from "react"
 import MoreIcon from "../../public/more.svg?react"
 import { invoke } from '@tauri-apps/api/core';
 
+interface VaultStats {
+  notes: number,
+  words: number,
+  characters: number,
+  block_types: Record<string, number>,
+  storage_bytes: number,
+  last_modified: { filename: string, notetitle: string, icon: string, modified: number } | null,
+}
+
 interface Props {
   icon: string,
   name: string,
@@ -13,14 +22,17 @@ interface Props {
 
 const VaultItem: React.FC<Props> = ({ icon, name, description, folder_id, onClick, vaultOptions }) => {
   
-  const [notesNumber, setNotesNumber] = React.useState<number | null>(null);
+  const [stats, setStats] = React.useState<VaultStats | null>(null);
 
   React.useEffect(() => {
-    invoke<number>("get_vault_notes_number", { foldername: folder_id })
-      .then(setNotesNumber)
-      .catch(() => setNotesNumber(0));
+    invoke<VaultStats>("get_vault_stats", { foldername: folder_id })
+      .then(setStats)
+      .catch(() => setStats(null));
   }, [folder_id]);
 
+  const notesNumber = stats?.notes ?? 0;
+  const words = stats?.words ?? 0;
+
   return (
     <div className="vault-item" onClick={onClick}>
       <MoreIcon className="vault-item-moreicon icon" onClick={(e: React.MouseEvent<SVGSVGElement, MouseEvent>) => {
@@ -30,7 +42,14 @@ const VaultItem: React.FC<Props> = ({ icon, name, description, folder_id, onClic
       <p className="vault-item-name">
         <span>{icon}</span> {name}
       </p>
-      <p className="vault-item-notesnumber">{notesNumber} {notesNumber === 1 ? "note" : "notes"}</p>
+      <p className="vault-item-notesnumber">
+        {notesNumber} {notesNumber === 1 ? "note" : "notes"} · {words} {words === 1 ? "word" : "words"}
+      </p>
+      {stats?.last_modified && (
+        <p className="vault-item-notesnumber">
+          Last edited: {stats.last_modified.icon} {stats.last_modified.notetitle || "Untitled"}
+        </p>
+      )}
       <p className="vault-item-description">{description}</p>
     </div>
   );
@@ -8,7 +8,7 @@ import CustomDialog from "./CustomDialog";
 import OptionsDialog from "./OptionsDialog";
 import NoteOptionsDialog from "./NoteOptionsDialog";
 import { invoke } from "@tauri-apps/api/core";
-import { usePassword } from "./PasswordContext";
+import { listen } from "@tauri-apps/api/event";
 import { NoteItem } from "./interfaces";
 
 interface Props {
@@ -25,7 +25,6 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
   const [isOptionsModalOpen, setIsOptionsModalOpen] = useState(false);
   const [isNoteOptionsModalOpen, setIsNoteOptionsModalOpen] = useState(false);
   const [selectedNote, setSelectedNote] = useState<string>("");
-  const { password } = usePassword();
   const [loadedNote, setLoadedNote] = useState<string | null>(null);
   const [currentNoteEditData, setCurrentNoteEditData] = useState<string>("");
 
@@ -54,7 +53,6 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
 
   function onEmojiChange(emoji: string) {
     invoke("update_note_icon", {
-      password,
       vaultfolder: id,
       filename: selectedNote,
       newIcon: emoji,
@@ -65,8 +63,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
   async function loadNotes() {
     try {
       const decryptedIndexJson = await invoke<string>("get_notes_index", {
-        password,
-        vaultfolder: id,
+          vaultfolder: id,
       });
       const updatedNotes = JSON.parse(decryptedIndexJson);
       setNotes(updatedNotes);
@@ -84,8 +81,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
       const savedData = dataOverride || (await editorRef.current.save());
 
       await invoke("save_note_data", {
-        password,
-        vaultfolder: id,
+          vaultfolder: id,
         filename: selectedNote,
         content: JSON.stringify(savedData),
       });
@@ -110,8 +106,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
 
       const newEmoji = getRandomEmoji();
       await invoke("create_note", {
-        password,
-        vaultfolder: id,
+          vaultfolder: id,
         icon: newEmoji,
       });
 
@@ -129,7 +124,6 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
   const onTitleChange = (newTitle: string) => {
     if (!selectedNote) return;
     invoke("update_note_title", {
-      password,
       vaultfolder: id,
       filename: selectedNote,
       newTitle,
@@ -146,7 +140,16 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
 
   useEffect(() => {
     loadNotes();
-  }, [id, password]);
+  }, [id]);
+
+  useEffect(() => {
+    const unlisten = listen("synced", () => {
+      loadNotes();
+    });
+    return () => {
+      unlisten.then((off) => off());
+    };
+  }, [id]);
 
   useEffect(() => {
     if (!selectedNote) {
@@ -159,8 +162,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
     async function loadNoteData() {
       try {
         const encryptedData = await invoke<string>("get_note_data", {
-          password,
-          vaultfolder: id,
+              vaultfolder: id,
           filename: selectedNote,
         });
         const parsed = JSON.parse(encryptedData);
@@ -174,7 +176,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
     }
 
     loadNoteData();
-  }, [selectedNote, id, password]);
+  }, [selectedNote, id]);
 
   // autosave al escribir
   const handleEditorChange = (data: any) => {
@@ -234,6 +236,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
             onTitleChange={onTitleChange}
             onChange={handleEditorChange}
             editDate={currentNoteEditData}
+            vaultId={id}
           />
           <NoteIndex indexList={headers} />
         </>
@@ -263,6 +266,7 @@ const VaultOpen: React.FC<Props> = ({ path, name, id, onVaultClose }) => {
       >
         <NoteOptionsDialog
           id={selectedNote}
+          title={getCurrentNoteItem()?.notetitle ?? ""}
           vaultFolder={path}
           refreshNotes={loadNotes}
           handleCloseDialog={handleCloseNoteOptionsDialog}
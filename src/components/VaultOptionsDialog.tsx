@@ -1,37 +1,42 @@
 import React, { useState } from "react";
 import EmojiPicker from "./EmojiPicker";
-import { usePassword } from "./PasswordContext";
 import { invoke } from '@tauri-apps/api/core';
+import { open, save } from "@tauri-apps/plugin-dialog";
 import CustomDialog from "./CustomDialog";
 import ConfirmVaultDeleteDialog from "./ConfirmVaultDeleteDialog";
+import ImportSharedDialog from "./ImportSharedDialog";
+import ContactPicker from "./ContactPicker";
+import TeamMembers from "./TeamMembers";
 
 interface VaultOptionsDialogProps {
     icon: string,
     name: string;
     description: string;
     id: string;
+    team: boolean;
     refreshVaults: () => void;
     handleCloseDialog: () => void;
   }
 
-  function updateVault(password: string, id: string, name: string, description: string, icon: string) {
-    return invoke("update_vault", { password, id, name, description, icon });
+  function updateVault(id: string, name: string, description: string, icon: string) {
+    return invoke("update_vault", { id, name, description, icon });
   }
 
-  function deleteVault(password: string, id: string) {
-    return invoke("delete_vault", {password, folderId: id});
+  function deleteVault(id: string) {
+    return invoke("delete_vault", { folderId: id });
   }
 
-  const VaultOptionsDialog: React.FC<VaultOptionsDialogProps> = ({ name, description, icon, id, refreshVaults, handleCloseDialog }) => {
+  const VaultOptionsDialog: React.FC<VaultOptionsDialogProps> = ({ name, description, icon, id, team, refreshVaults, handleCloseDialog }) => {
     const [emoji, setEmoji] = useState(icon);
     const [newName, setNewName] = useState(name);
     const [newDescription, setNewDescription] = useState(description);
-    const { password } = usePassword();
     const [isConfirmVaultDeleteDialogOpen, setIsConfirmVaultDeleteDialogOpen] = useState(false);
+    const [importPath, setImportPath] = useState<string | null>(null);
+    const [recipients, setRecipients] = useState<string[]>([]);
   
     const handleSave = async () => {
       try {
-        await updateVault(password, id, newName, newDescription, emoji);
+        await updateVault(id, newName, newDescription, emoji);
         refreshVaults();
         handleCloseDialog();
       } catch (error) {
@@ -41,7 +46,7 @@ interface VaultOptionsDialogProps {
 
     const handleDelete = async () => {
         try {
-            await deleteVault(password, id);
+            await deleteVault(id);
             refreshVaults();
             handleCloseDialog();
         } catch (error) {
@@ -49,6 +54,33 @@ interface VaultOptionsDialogProps {
         }
     }
 
+    const handleChooseSharedNote = async () => {
+        const selected = await open({
+            multiple: false,
+            directory: false,
+            title: "Import shared note",
+            filters: [{ name: "Noetiq note", extensions: ["noetiq"] }],
+        });
+        if (typeof selected === "string") {
+            setImportPath(selected);
+        }
+    }
+
+    const handleShareVault = async () => {
+        const path = await save({
+            title: "Share vault",
+            defaultPath: `${name.trim() || "vault"}.noetiq`,
+            filters: [{ name: "Noetiq vault", extensions: ["noetiq"] }],
+        });
+        if (!path) return;
+        try {
+            await invoke("share_vault_with_contacts", { vaultfolder: id, recipients, path });
+            setRecipients([]);
+        } catch (error) {
+            alert("Failed to share the vault: " + error);
+        }
+    }
+
     return (
       <div id="openvault-container">
         <div className="emojipicker-dialog">
@@ -76,11 +108,32 @@ interface VaultOptionsDialogProps {
           </button>
           <button className="dialog-button-red" onClick={() => setIsConfirmVaultDeleteDialogOpen(true)}>Delete vault</button>
         </div>
+        {team && <TeamMembers vaultfolder={id} />}
+        <p className="dialog-input-label">Shared notes</p>
+        <button className="dialog-button-neutral" onClick={handleChooseSharedNote}>
+          Import a shared note
+        </button>
+        <p className="dialog-input-label">Share vault with contacts</p>
+        <ContactPicker selected={recipients} onChange={setRecipients} />
+        <button className="dialog-button-neutral" onClick={handleShareVault} disabled={recipients.length === 0}>
+          Share vault
+        </button>
 
       <CustomDialog isOpen={isConfirmVaultDeleteDialogOpen} onClose={() => setIsConfirmVaultDeleteDialogOpen(false)}>
         <ConfirmVaultDeleteDialog onConfirm={handleDelete}/>
       </CustomDialog>
 
+      <CustomDialog isOpen={importPath !== null} onClose={() => setImportPath(null)}>
+        {importPath && (
+          <ImportSharedDialog
+            path={importPath}
+            vault={id}
+            handleCloseDialog={() => setImportPath(null)}
+            onImported={refreshVaults}
+          />
+        )}
+      </CustomDialog>
+
       </div>
     );
   };
@@ -0,0 +1,62 @@
+import { invoke, convertFileSrc } from "@tauri-apps/api/core";
+
+const ATTACHMENT_PROTOCOL = "noetiq-attachment";
+
+interface AttachmentInfo {
+    id: string,
+    name: string,
+    mime: string,
+    size: number,
+}
+
+// Decrypted attachments are served by the backend while the vaults are unlocked.
+export const attachmentUrl = (vaultId: string, id: string) =>
+    convertFileSrc(`${vaultId}/${id}`, ATTACHMENT_PROTOCOL);
+
+const uploadFile = async (vaultId: string, file: Blob, name: string) => {
+    const data = new Uint8Array(await file.arrayBuffer());
+    const info = await invoke<AttachmentInfo>("upload_attachment", data, {
+        headers: {
+            "x-vault": encodeURIComponent(vaultId),
+            "x-name": encodeURIComponent(name),
+            "x-mime": encodeURIComponent(file.type || "application/octet-stream"),
+        },
+    });
+
+    return {
+        success: 1,
+        file: {
+            url: attachmentUrl(vaultId, info.id),
+            attachment: info.id,
+            name: info.name,
+            title: info.name,
+            size: info.size,
+            extension: info.name.includes(".") ? info.name.split(".").pop() : undefined,
+        },
+    };
+};
+
+export const attachmentUploader = (vaultId: string) => ({
+    uploadByFile: (file: File) => uploadFile(vaultId, file, file.name),
+    uploadByUrl: async (url: string) => {
+        const response = await fetch(url);
+        const name = new URL(url).pathname.split("/").pop() || "image";
+        return uploadFile(vaultId, await response.blob(), name);
+    },
+});
+
+// Stored URLs depend on the platform the note was last saved on, so they are rebuilt on load.
+export const resolveAttachmentUrls = (data: any, vaultId: string) => {
+    if (!data?.blocks) return data;
+    return {
+        ...data,
+        blocks: data.blocks.map((block: any) => {
+            const id = block.data?.file?.attachment;
+            if (!id) return block;
+            return {
+                ...block,
+                data: { ...block.data, file: { ...block.data.file, url: attachmentUrl(vaultId, id) } },
+            };
+        }),
+    };
+};